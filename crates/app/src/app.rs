@@ -1,14 +1,167 @@
-use crate::renderer::Custom3d;
+use crate::renderer::{
+    BackgroundMode, CameraMode, Custom3d, DebugMode, Group, Mat4, Material, MaterialType,
+    PixelCenter, PointLight, PreviewFilter, RenderResolution, Session, Sphere, ToneMapping, Vec3,
+};
+use crossbeam::channel::Receiver;
+use renderer::animation::Animation;
+use renderer::raytracer::{ShaderError, WorkgroupSize, KERNEL_SOURCE};
+use renderer::renderer::Renderer;
+use renderer::scene::{Camera, MaterialPreset, MAX_FOV_Y_DEGREES, MIN_FOV_Y_DEGREES};
+use std::path::PathBuf;
+
+const ZOOM_KEY: &str = "ui_zoom";
+const SESSION_KEY: &str = "session";
+/// Number of frames the displayed FPS is averaged over.
+const FPS_WINDOW: usize = 30;
+/// Tile sizes offered for rendering animations, `None` renders whole frames.
+const TILE_SIZES: [Option<u32>; 4] = [None, Some(256), Some(512), Some(1024)];
+/// Samples per pixel of images saved at another resolution than the preview's.
+const DEFAULT_EXPORT_SAMPLES: u32 = 256;
+/// Exposure stops "Save Bracket" saves, relative to the current exposure.
+const DEFAULT_BRACKET_STOPS: &str = "-2, 0, 2";
+/// Length of the translate gizmo's arrows, relative to their distance to the camera.
+const GIZMO_SCALE: f32 = 0.15;
+/// Side of the square handle at the tip of each gizmo arrow, in points.
+const GIZMO_HANDLE_SIZE: f32 = 14.0;
+
+/// Sent by the thread rendering an animation.
+enum AnimationProgress {
+    /// Number of frames written so far, with the fraction of the next one's tiles that are done.
+    FramesWritten(f32),
+    Finished(Result<(), String>),
+}
+
+/// An animation being rendered on its own thread, with its own headless renderer.
+struct AnimationJob {
+    rx: Receiver<AnimationProgress>,
+    directory: PathBuf,
+    frames_written: f32,
+    frame_count: u32,
+}
+
+/// A photo drawn over the viewport to line up the camera with it. It never affects the render.
+struct ReferenceImage {
+    texture: egui::TextureHandle,
+    visible: bool,
+    opacity: f32,
+}
+
+/// Rolling average of the time between UI frames.
+#[derive(Default)]
+struct FrameTimer {
+    frame_times: std::collections::VecDeque<f32>,
+}
+
+impl FrameTimer {
+    fn record(&mut self, seconds: f32) {
+        if self.frame_times.len() == FPS_WINDOW {
+            self.frame_times.pop_front();
+        }
+        self.frame_times.push_back(seconds);
+    }
+
+    fn fps(&self) -> Option<f32> {
+        let total: f32 = self.frame_times.iter().sum();
+        (total > 0.0).then(|| self.frame_times.len() as f32 / total)
+    }
+}
 
 pub struct ExampleApp {
     custom: Custom3d,
+    /// Pixels per point chosen by the user, `None` follows the native scale.
+    zoom: Option<f32>,
+    restore_zoom: bool,
+    reference: Option<ReferenceImage>,
+    confirm_reset: bool,
+    animation: Animation,
+    show_animation_dialog: bool,
+    /// Size animations are rendered at, independently of the preview. `Viewport` matches it.
+    animation_resolution: RenderResolution,
+    animation_tile_size: Option<u32>,
+    /// Size "Save Image" renders at. `Viewport` saves the preview as it is.
+    export_resolution: RenderResolution,
+    /// Samples per pixel of images rendered at `export_resolution`.
+    export_samples: u32,
+    /// Comma separated exposure stops of "Save Bracket", as typed.
+    bracket_stops: String,
+    /// The UI is disabled while an animation renders.
+    animation_job: Option<AnimationJob>,
+    /// Index of the sphere shown in the sphere editor, picked in the list or the viewport.
+    selected_sphere: Option<usize>,
+    /// Index of the light shown in the light editor.
+    selected_light: Option<usize>,
+    /// Index of the group shown in the group editor, which the viewport shows a gizmo for.
+    selected_group: Option<usize>,
+    /// Camera text typed or pasted in the camera settings, applied on demand.
+    camera_paste: String,
+    /// Kernel source being edited, `None` while the editor is closed.
+    kernel_draft: Option<String>,
+    frame_timer: FrameTimer,
 }
 
 impl ExampleApp {
-    pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Self {
-        Self {
-            custom: Custom3d::new(cc).expect("Failed to vreate custom 3D renderer"),
+    /// Fails when eframe didn't set up wgpu, which the raytracer needs.
+    pub fn new<'a>(cc: &'a eframe::CreationContext<'a>) -> Result<Self, String> {
+        let zoom = cc
+            .storage
+            .and_then(|storage| eframe::get_value(storage, ZOOM_KEY))
+            .flatten();
+
+        let mut custom = Custom3d::new(cc)
+            .ok_or_else(|| "eframe started without a wgpu render state".to_string())?;
+        if let Some(session) = cc
+            .storage
+            .and_then(|storage| eframe::get_value::<Session>(storage, SESSION_KEY))
+        {
+            custom.restore_session(session);
         }
+
+        Ok(Self {
+            custom,
+            zoom,
+            restore_zoom: true,
+            reference: None,
+            confirm_reset: false,
+            animation: Default::default(),
+            show_animation_dialog: false,
+            animation_resolution: RenderResolution::Viewport,
+            animation_tile_size: None,
+            export_resolution: RenderResolution::Viewport,
+            export_samples: DEFAULT_EXPORT_SAMPLES,
+            bracket_stops: DEFAULT_BRACKET_STOPS.to_owned(),
+            animation_job: None,
+            selected_sphere: Some(0),
+            selected_light: Some(0),
+            selected_group: Some(0),
+            camera_paste: String::new(),
+            kernel_draft: None,
+            frame_timer: Default::default(),
+        })
+    }
+}
+
+/// Shown instead of `ExampleApp` when it couldn't be created, so the window explains what went
+/// wrong rather than closing.
+pub struct ErrorApp {
+    message: String,
+}
+
+impl ErrorApp {
+    pub fn new(message: String) -> Self {
+        Self { message }
+    }
+}
+
+impl eframe::App for ErrorApp {
+    fn update(&mut self, egui_ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::CentralPanel::default().show(egui_ctx, |ui| {
+            ui.heading("No compatible GPU was found");
+            ui.label(
+                "The raytracer needs a GPU supported by wgpu (Vulkan, Metal, DX12 or OpenGL).",
+            );
+            ui.separator();
+            ui.monospace(&self.message);
+        });
     }
 }
 
@@ -17,21 +170,41 @@ impl eframe::App for ExampleApp {
         [0.0; 4]
     }
 
+    fn save(&mut self, storage: &mut dyn eframe::Storage) {
+        eframe::set_value(storage, ZOOM_KEY, &self.zoom);
+        eframe::set_value(storage, SESSION_KEY, &self.custom.session());
+    }
+
     fn update(&mut self, egui_ctx: &egui::Context, frame: &mut eframe::Frame) {
-        egui_ctx.request_repaint();
-        egui::gui_zoom::zoom_with_keyboard_shortcuts(
-            egui_ctx,
-            frame.info().native_pixels_per_point,
-        );
+        if !self.custom.paused {
+            egui_ctx.request_repaint();
+        }
+        self.custom.receive_messages();
+        self.receive_animation_progress();
+        let busy = self.animation_job.is_some();
+        let native_pixels_per_point = frame.info().native_pixels_per_point;
+        if std::mem::take(&mut self.restore_zoom) {
+            if let Some(zoom) = self.zoom {
+                egui_ctx.set_pixels_per_point(zoom);
+            }
+        } else {
+            let pixels_per_point = egui_ctx.pixels_per_point();
+            let native = native_pixels_per_point.unwrap_or(pixels_per_point);
+            self.zoom = (pixels_per_point != native).then_some(pixels_per_point);
+        }
+        egui::gui_zoom::zoom_with_keyboard_shortcuts(egui_ctx, native_pixels_per_point);
 
         self.top_bar(egui_ctx, frame);
 
+        self.frame_timer
+            .record(egui_ctx.input(|input| input.unstable_dt));
         egui::TopBottomPanel::bottom("bottom_panel").show(egui_ctx, |ui| {
             ui.with_layout(egui::Layout::left_to_right(egui::Align::Center), |ui| {
                 egui::warn_if_debug_build(ui);
-                ui.strong("Bottom panel");
+                self.status_bar(ui);
             })
         });
+        self.shader_error_panel(egui_ctx);
 
         egui::SidePanel::left("left_panel")
             .default_width(500.0)
@@ -41,6 +214,7 @@ impl eframe::App for ExampleApp {
                 ..Default::default()
             })
             .show(egui_ctx, |ui| {
+                ui.set_enabled(!busy);
                 egui::TopBottomPanel::top("left_panel_tio_bar")
                     .exact_height(0.0)
                     .frame(egui::Frame {
@@ -52,14 +226,110 @@ impl eframe::App for ExampleApp {
                             ui.strong("Left bar");
                         });
 
-                        if ui.button("Save Image").clicked() {
+                        ui.horizontal(|ui| {
+                            if ui
+                                .button("Save Image")
+                                .on_hover_text(
+                                    "PNG saves the image as displayed, \
+                                     OpenEXR the raw linear render",
+                                )
+                                .clicked()
+                            {
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("PNG image", &["png"])
+                                    .add_filter("OpenEXR image", &["exr"])
+                                    .save_file()
+                                {
+                                    self.save_image(path, frame);
+                                }
+                            }
+                            ui.checkbox(&mut self.custom.export_gbuffer, "With G-buffer")
+                                .on_hover_text(
+                                    "Also save the first hit albedo, normal and depth, \
+                                     like render_albedo.png next to render.png",
+                                );
+                        });
+                        ui.horizontal(|ui| {
+                            egui::ComboBox::from_id_source("export_resolution")
+                                .selected_text(self.export_resolution.name())
+                                .show_ui(ui, |ui| {
+                                    for resolution in RenderResolution::ALL {
+                                        ui.selectable_value(
+                                            &mut self.export_resolution,
+                                            resolution,
+                                            resolution.name(),
+                                        );
+                                    }
+                                })
+                                .response
+                                .on_hover_text(
+                                    "Render saved images at this size, \
+                                     or save the preview as it is",
+                                );
+                            if self.export_resolution != RenderResolution::Viewport {
+                                ui.add(
+                                    egui::DragValue::new(&mut self.export_samples)
+                                        .clamp_range(1..=65_536)
+                                        .suffix(" spp"),
+                                )
+                                .on_hover_text("Samples per pixel of the saved image");
+                            }
+                        });
+                        self.bracket_settings(ui);
+
+                        if ui.button("Render Animation").clicked() {
+                            self.show_animation_dialog = true;
+                        }
+
+                        if ui.button("Load Scene").clicked() {
+                            if let Some(path) = rfd::FileDialog::new()
+                                .add_filter("scene", &["ron", "json"])
+                                .pick_file()
+                            {
+                                if let Err(err) = self.custom.load_scene(&path) {
+                                    re_log::error!("Failed to load {path:?}: {err}");
+                                }
+                            }
+                        }
+
+                        ui.horizontal(|ui| {
+                            if ui.button("Save Session").clicked() {
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("session", &["ron"])
+                                    .save_file()
+                                {
+                                    if let Err(err) = self.custom.session().save(&path) {
+                                        re_log::error!("Failed to save {path:?}: {err}");
+                                    }
+                                }
+                            }
+                            if ui.button("Load Session").clicked() {
+                                if let Some(path) = rfd::FileDialog::new()
+                                    .add_filter("session", &["ron"])
+                                    .pick_file()
+                                {
+                                    match Session::load(&path) {
+                                        Ok(session) => self.custom.restore_session(session),
+                                        Err(err) => {
+                                            re_log::error!("Failed to load {path:?}: {err}")
+                                        }
+                                    }
+                                }
+                            }
+                        });
+
+                        if ui.button("Load Mesh").clicked() {
                             if let Some(path) = rfd::FileDialog::new()
-                                .add_filter("image", &["png"])
-                                .save_file()
+                                .add_filter("Wavefront OBJ", &["obj"])
+                                .pick_file()
                             {
-                                pollster::block_on(self.custom.save(path));
+                                if let Err(err) = self.custom.load_mesh(&path) {
+                                    re_log::error!("Failed to load {path:?}: {err}");
+                                }
                             }
                         }
+
+                        self.reference_settings(ui);
                     });
             });
 
@@ -73,23 +343,75 @@ impl eframe::App for ExampleApp {
             .min_width(100.0)
             .frame(panel_frame)
             .show(egui_ctx, |ui| {
-                ui.strong("Right panel");
-                selection_buttons(ui);
+                ui.set_enabled(!busy);
+                self.sphere_editor(ui);
+                ui.separator();
+                self.group_editor(ui);
+                ui.separator();
+                self.render_settings(ui);
+                ui.separator();
+                self.camera_settings(ui);
+                ui.separator();
+                self.sun_settings(ui);
+                ui.separator();
+                self.light_editor(ui);
+                ui.separator();
+                self.debug_settings(ui);
+                ui.separator();
+                if ui.button("Reset to Defaults").clicked() {
+                    self.confirm_reset = true;
+                }
             });
 
+        if self.confirm_reset {
+            self.reset_dialog(egui_ctx);
+        }
+        if self.show_animation_dialog && !busy {
+            self.animation_dialog(egui_ctx);
+        }
+        if busy {
+            self.animation_progress(egui_ctx);
+        }
+        if self.kernel_draft.is_some() {
+            self.kernel_editor(egui_ctx, busy);
+        }
+
         egui::CentralPanel::default()
             .frame(egui::Frame {
                 fill: egui_ctx.style().visuals.panel_fill,
                 ..Default::default()
             })
             .show(egui_ctx, |ui| {
-                self.custom.custom_painting(ui, frame);
+                ui.set_enabled(!busy);
+                // The gizmo handles are interacted with first so they get the drags on top of
+                // the viewport, but are painted over it
+                let gizmo = self.group_gizmo(ui);
+                if let Some(response) = self.custom.custom_painting(ui, frame) {
+                    self.pick_sphere(ui, &response);
+                }
+                ui.painter().extend(gizmo);
+                if let Some(region) = self.custom.render_region_rect() {
+                    let stroke = egui::Stroke::new(1.0, ui.visuals().selection.stroke.color);
+                    ui.painter().rect_stroke(region, 0.0, stroke);
+                }
+
+                if let Some(reference) = self.reference.as_ref().filter(|r| r.visible) {
+                    let uv = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(1.0, 1.0));
+                    let tint = egui::Color32::from_white_alpha((reference.opacity * 255.0) as u8);
+                    ui.painter().image(
+                        reference.texture.id(),
+                        self.custom.viewport_rect(),
+                        uv,
+                        tint,
+                    );
+                }
             });
     }
 }
 
 impl ExampleApp {
-    fn top_bar(&mut self, egui_ctx: &egui::Context, _frame: &mut eframe::Frame) {
+    fn top_bar(&mut self, egui_ctx: &egui::Context, frame: &mut eframe::Frame) {
+        let native_pixels_per_point = frame.info().native_pixels_per_point;
         let frame = egui::Frame {
             fill: egui_ctx.style().visuals.panel_fill,
             inner_margin: 12.0.into(),
@@ -100,16 +422,1278 @@ impl ExampleApp {
             .frame(frame)
             .exact_height(24.0)
             .show(egui_ctx, |ui| {
+                ui.set_enabled(self.animation_job.is_none());
                 let _response = egui::menu::bar(ui, |ui| {
                     ui.set_height(24.0);
                     ui.add_space(0.0);
+
+                    ui.menu_button("View", |ui| {
+                        if ui.button("Reset zoom").clicked() {
+                            if let Some(native_pixels_per_point) = native_pixels_per_point {
+                                egui_ctx.set_pixels_per_point(native_pixels_per_point);
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Kernel editor").clicked() {
+                            self.kernel_draft
+                                .get_or_insert_with(|| self.custom.kernel_source().to_owned());
+                            ui.close_menu();
+                        }
+                    });
                 })
                 .response;
             });
     }
+
+    fn status_bar(&self, ui: &mut egui::Ui) {
+        match self.frame_timer.fps() {
+            Some(fps) => ui.label(format!("{fps:.1} FPS")),
+            None => ui.label("- FPS"),
+        };
+        ui.separator();
+
+        let frame_count = self.custom.frame_count();
+        let samples = frame_count * self.custom.samples_per_pixel;
+        ui.label(format!("Frame {frame_count} ({samples} samples per pixel)"));
+        if self.custom.converged() {
+            ui.label("Converged");
+        } else if let (Some(convergence), Some(change)) =
+            (self.custom.convergence(), self.custom.frame_change())
+        {
+            ui.label(format!("{:.0}% converged", convergence * 100.0))
+                .on_hover_text(format!(
+                    "Frames change the image by {change:.1e} on average, converged below {:.1e}",
+                    self.custom.convergence_threshold
+                ));
+        }
+        if self.custom.recovering_gpu() {
+            ui.colored_label(ui.visuals().warn_fg_color, "Recovering GPU...");
+        }
+        ui.separator();
+
+        let (width, height) = self.custom.texture_size();
+        ui.label(format!("{width} x {height}"));
+
+        if let Some(stats) = self.custom.stats() {
+            ui.separator();
+            let prepare_ms = stats.prepare_time.as_secs_f64() * 1000.0;
+            ui.label(format!("Prepare {prepare_ms:.2} ms"))
+                .on_hover_text(format!(
+                    "CPU time spent recording frame {}",
+                    stats.frame_count
+                ));
+        }
+
+        ui.separator();
+        match self.custom.gpu_time() {
+            Some(gpu_time) => {
+                ui.label(format!("GPU {:.2} ms", gpu_time.as_secs_f64() * 1000.0))
+                    .on_hover_text("Time spent in the raytracing compute pass");
+            }
+            None if self.custom.gpu_timing_supported() => {
+                ui.label("GPU - ms");
+            }
+            None => {
+                ui.label("GPU time unavailable")
+                    .on_hover_text("The adapter doesn't support timestamp queries");
+            }
+        }
+
+        ui.separator();
+        let adapter = self.custom.adapter_info();
+        ui.label(&adapter.name).on_hover_text(format!(
+            "{:?} on {:?}, set WGPU_POWER_PREF=low or high to pick another GPU",
+            adapter.device_type, adapter.backend
+        ));
+    }
+
+    /// Shows why the kernel failed to compile, the viewport keeps the last image meanwhile.
+    fn shader_error_panel(&self, egui_ctx: &egui::Context) {
+        let Some(error) = self.custom.shader_error() else {
+            return;
+        };
+        egui::TopBottomPanel::bottom("shader_error_panel")
+            .resizable(true)
+            .show(egui_ctx, |ui| {
+                shader_error_label(ui, error);
+            });
+    }
+
+    /// Edits the kernel's WGSL, "Apply" recompiles the pipeline with it. Compile errors show
+    /// under the source while the last kernel that compiled keeps tracing.
+    fn kernel_editor(&mut self, egui_ctx: &egui::Context, busy: bool) {
+        let Some(draft) = self.kernel_draft.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        egui::Window::new("Kernel editor")
+            .open(&mut open)
+            .default_size([600.0, 700.0])
+            .show(egui_ctx, |ui| {
+                ui.set_enabled(!busy);
+                ui.horizontal(|ui| {
+                    if ui.button("Apply").clicked() {
+                        self.custom.reload_kernel(draft.clone());
+                    }
+                    if ui
+                        .button("Revert")
+                        .on_hover_text("Go back to the kernel that is tracing")
+                        .clicked()
+                    {
+                        *draft = self.custom.kernel_source().to_owned();
+                    }
+                    if ui
+                        .button("Built-in")
+                        .on_hover_text("Start over from the kernel the app ships with")
+                        .clicked()
+                    {
+                        *draft = KERNEL_SOURCE.to_owned();
+                    }
+                });
+                if let Some(error) = self.custom.shader_error() {
+                    ui.separator();
+                    shader_error_label(ui, error);
+                }
+                ui.separator();
+                egui::ScrollArea::both()
+                    .id_source("kernel_source")
+                    .show(ui, |ui| {
+                        ui.add(
+                            egui::TextEdit::multiline(draft)
+                                .code_editor()
+                                .desired_width(f32::INFINITY),
+                        );
+                    });
+            });
+        if !open {
+            self.kernel_draft = None;
+        }
+    }
+
+    /// Selects the sphere clicked in the viewport, or nothing when clicking the background, and
+    /// draws a crosshair under the pointer to aim with.
+    fn pick_sphere(&mut self, ui: &egui::Ui, response: &egui::Response) {
+        if response.clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                self.selected_sphere = self.custom.pick_sphere(pos);
+            }
+        }
+        if let Some(pos) = response.hover_pos() {
+            let stroke = egui::Stroke::new(1.0, ui.visuals().text_color());
+            let painter = ui.painter();
+            painter.hline(pos.x - 8.0..=pos.x + 8.0, pos.y, stroke);
+            painter.vline(pos.x, pos.y - 8.0..=pos.y + 8.0, stroke);
+        }
+    }
+
+    fn reset_dialog(&mut self, egui_ctx: &egui::Context) {
+        egui::Window::new("Reset to defaults?")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(egui_ctx, |ui| {
+                ui.label("The scene, camera and render settings will be lost.");
+                ui.horizontal(|ui| {
+                    if ui.button("Reset").clicked() {
+                        self.custom.reset_to_defaults();
+                        self.confirm_reset = false;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirm_reset = false;
+                    }
+                });
+            });
+    }
+
+    fn animation_dialog(&mut self, egui_ctx: &egui::Context) {
+        let mut open = true;
+        let mut directory = None;
+        egui::Window::new("Render Animation")
+            .collapsible(false)
+            .resizable(false)
+            .open(&mut open)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(egui_ctx, |ui| {
+                let animation = &mut self.animation;
+                ui.horizontal(|ui| {
+                    ui.label("Start time");
+                    ui.add(
+                        egui::DragValue::new(&mut animation.start_time)
+                            .speed(0.05)
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Duration");
+                    ui.add(
+                        egui::DragValue::new(&mut animation.duration)
+                            .speed(0.05)
+                            .clamp_range(0.0..=f32::MAX)
+                            .suffix(" s"),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Frames");
+                    ui.add(
+                        egui::DragValue::new(&mut animation.frame_count).clamp_range(1..=100_000),
+                    );
+                });
+                ui.horizontal(|ui| {
+                    ui.label("Samples per frame");
+                    ui.add(
+                        egui::DragValue::new(&mut animation.samples_per_frame)
+                            .clamp_range(1..=4096),
+                    );
+                });
+
+                egui::ComboBox::from_label("Resolution")
+                    .selected_text(self.animation_resolution.name())
+                    .show_ui(ui, |ui| {
+                        for resolution in RenderResolution::ALL {
+                            ui.selectable_value(
+                                &mut self.animation_resolution,
+                                resolution,
+                                resolution.name(),
+                            );
+                        }
+                    });
+                egui::ComboBox::from_label("Tiles")
+                    .selected_text(tile_size_name(self.animation_tile_size))
+                    .show_ui(ui, |ui| {
+                        for tile_size in TILE_SIZES {
+                            ui.selectable_value(
+                                &mut self.animation_tile_size,
+                                tile_size,
+                                tile_size_name(tile_size),
+                            );
+                        }
+                    })
+                    .response
+                    .on_hover_text(
+                        "Render large frames a tile at a time, in case the GPU times out on them",
+                    );
+
+                let (width, height) = self.animation_size();
+                ui.label(format!("Frames are rendered at {width} x {height}"));
+                if ui.button("Choose Folder and Render").clicked() {
+                    directory = rfd::FileDialog::new().pick_folder();
+                }
+            });
+
+        if let Some(directory) = directory {
+            self.start_animation(egui_ctx, directory);
+            open = false;
+        }
+        self.show_animation_dialog = open;
+    }
+
+    /// Renders the animation on a thread, as rendering it on the UI thread would freeze the window.
+    fn start_animation(&mut self, egui_ctx: &egui::Context, directory: PathBuf) {
+        let (tx, rx) = crossbeam::channel::unbounded();
+        let animation = self.animation;
+        let scene = self.custom.scene().clone();
+        let (width, height) = self.animation_size();
+        let tile_size = self.animation_tile_size;
+        let thread_directory = directory.clone();
+        let egui_ctx = egui_ctx.clone();
+        std::thread::spawn(move || {
+            let result = match pollster::block_on(Renderer::new(width as usize, height as usize)) {
+                Ok(mut renderer) => {
+                    renderer.set_tile_size(tile_size);
+                    animation
+                        .render(&mut renderer, &scene, &thread_directory, |frames| {
+                            let _ = tx.send(AnimationProgress::FramesWritten(frames));
+                            egui_ctx.request_repaint();
+                        })
+                        .map_err(|err| err.to_string())
+                }
+                Err(err) => Err(err.to_string()),
+            };
+            let _ = tx.send(AnimationProgress::Finished(result));
+            egui_ctx.request_repaint();
+        });
+
+        self.animation_job = Some(AnimationJob {
+            rx,
+            directory,
+            frames_written: 0.0,
+            frame_count: animation.frame_count,
+        });
+    }
+
+    /// Saves the preview, or renders the image again at the export resolution.
+    fn save_image(&mut self, path: PathBuf, frame: &eframe::Frame) {
+        match self.export_resolution.size() {
+            Some(size) => pollster::block_on(self.custom.save_at_size(
+                path,
+                size,
+                self.export_samples,
+                frame.wgpu_render_state().unwrap(),
+            )),
+            None => pollster::block_on(self.custom.save(path)),
+        }
+    }
+
+    /// Saves the preview at several exposures, as typed in `bracket_stops`.
+    fn bracket_settings(&mut self, ui: &mut egui::Ui) {
+        let stops = parse_stops(&self.bracket_stops);
+        ui.horizontal(|ui| {
+            let save = ui
+                .add_enabled(stops.is_ok(), egui::Button::new("Save Bracket"))
+                .on_hover_text(
+                    "Save the preview once per exposure stop, like render_-2ev.png, \
+                     without rendering it again",
+                );
+            if save.clicked() {
+                if let (Ok(stops), Some(path)) = (
+                    &stops,
+                    rfd::FileDialog::new()
+                        .add_filter("PNG image", &["png"])
+                        .save_file(),
+                ) {
+                    pollster::block_on(self.custom.save_bracket(path, stops));
+                }
+            }
+            ui.add(egui::TextEdit::singleline(&mut self.bracket_stops).desired_width(80.0))
+                .on_hover_text("Exposure stops in EV, relative to the current exposure");
+        });
+        if let Err(err) = stops {
+            ui.colored_label(ui.visuals().error_fg_color, err);
+        }
+    }
+
+    /// The animation resolution, or the preview's when matching the viewport.
+    fn animation_size(&self) -> (u32, u32) {
+        self.animation_resolution
+            .size()
+            .unwrap_or_else(|| self.custom.texture_size())
+    }
+
+    fn receive_animation_progress(&mut self) {
+        let Some(job) = &mut self.animation_job else {
+            return;
+        };
+        let mut finished = None;
+        for progress in job.rx.try_iter() {
+            match progress {
+                AnimationProgress::FramesWritten(frames) => job.frames_written = frames,
+                AnimationProgress::Finished(result) => finished = Some(result),
+            }
+        }
+
+        let Some(result) = finished else {
+            return;
+        };
+        match result {
+            Ok(()) => re_log::info!("Wrote {} frames to {:?}", job.frame_count, job.directory),
+            Err(err) => re_log::error!(
+                "Failed to render the animation to {:?}: {err}",
+                job.directory
+            ),
+        }
+        self.animation_job = None;
+    }
+
+    fn animation_progress(&self, egui_ctx: &egui::Context) {
+        let Some(job) = &self.animation_job else {
+            return;
+        };
+        egui::Window::new("Rendering animation")
+            .collapsible(false)
+            .resizable(false)
+            .anchor(egui::Align2::CENTER_CENTER, egui::Vec2::ZERO)
+            .show(egui_ctx, |ui| {
+                ui.add(
+                    egui::ProgressBar::new(job.frames_written / job.frame_count as f32).text(
+                        format!(
+                            "Frame {} of {}",
+                            (job.frames_written as u32 + 1).min(job.frame_count),
+                            job.frame_count
+                        ),
+                    ),
+                );
+            });
+    }
+
+    fn reference_settings(&mut self, ui: &mut egui::Ui) {
+        if ui.button("Load Reference").clicked() {
+            if let Some(path) = rfd::FileDialog::new()
+                .add_filter("image", &["png"])
+                .pick_file()
+            {
+                match load_png(&path) {
+                    Ok(image) => {
+                        let texture = ui.ctx().load_texture(
+                            "reference_image",
+                            image,
+                            egui::TextureOptions::LINEAR,
+                        );
+                        self.reference = Some(ReferenceImage {
+                            texture,
+                            visible: true,
+                            opacity: 0.5,
+                        });
+                    }
+                    Err(err) => re_log::error!("Failed to load {path:?}: {err}"),
+                }
+            }
+        }
+
+        if let Some(reference) = &mut self.reference {
+            ui.checkbox(&mut reference.visible, "Show reference");
+            ui.add(egui::Slider::new(&mut reference.opacity, 0.0..=1.0).text("Opacity"));
+        }
+    }
+
+    fn sphere_editor(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Spheres");
+        let count = self.custom.spheres().len();
+        self.selected_sphere = clamp_selection(self.selected_sphere, count);
+        selection(ui, "Sphere", &mut self.selected_sphere, count);
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                let sphere = Sphere::new(
+                    Vec3::new(5.0, 0.0, 0.0),
+                    1.0,
+                    Material {
+                        albedo: Vec3::new(0.8, 0.8, 0.8),
+                        ..Default::default()
+                    },
+                );
+                self.selected_sphere = Some(self.custom.add_sphere(sphere));
+            }
+            if let Some(index) = self.selected_sphere {
+                if ui.button("Delete").clicked() {
+                    self.custom.remove_sphere(index);
+                    self.selected_sphere = Some(index.saturating_sub(1));
+                }
+            }
+        });
+
+        // The selection may have just been deleted
+        let selected = self
+            .selected_sphere
+            .and_then(|index| Some((index, *self.custom.spheres().get(index)?)));
+        let Some((index, mut sphere)) = selected else {
+            if count > 0 {
+                ui.label("Click a sphere in the viewport to edit it");
+            }
+            return;
+        };
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            for value in [
+                &mut sphere.position.x,
+                &mut sphere.position.y,
+                &mut sphere.position.z,
+            ] {
+                changed |= ui.add(egui::DragValue::new(value).speed(0.05)).changed();
+            }
+        });
+        ui.horizontal(|ui| {
+            ui.label("Radius");
+            changed |= ui
+                .add(
+                    egui::DragValue::new(&mut sphere.radius)
+                        .speed(0.01)
+                        .clamp_range(0.01..=f32::MAX),
+                )
+                .changed();
+        });
+        ui.horizontal(|ui| {
+            ui.label("Velocity").on_hover_text(
+                "Distance moved per unit of time, blurring the sphere while the shutter is open",
+            );
+            for value in [
+                &mut sphere.velocity.x,
+                &mut sphere.velocity.y,
+                &mut sphere.velocity.z,
+            ] {
+                changed |= ui.add(egui::DragValue::new(value).speed(0.05)).changed();
+            }
+        });
+
+        let mut albedo = [
+            sphere.mat.albedo.x,
+            sphere.mat.albedo.y,
+            sphere.mat.albedo.z,
+        ];
+        ui.horizontal(|ui| {
+            ui.label("Albedo");
+            if ui.color_edit_button_rgb(&mut albedo).changed() {
+                let [x, y, z] = albedo;
+                sphere.mat.albedo = Vec3::new(x, y, z);
+                changed = true;
+            }
+        });
+
+        let mut preset = None;
+        egui::ComboBox::from_label("Preset")
+            .selected_text("Apply...")
+            .show_ui(ui, |ui| {
+                for material_preset in MaterialPreset::ALL {
+                    ui.selectable_value(&mut preset, Some(material_preset), material_preset.name());
+                }
+            });
+        if let Some(preset) = preset {
+            preset.apply(&mut sphere.mat);
+            changed = true;
+        }
+
+        let mut kind = sphere.mat.kind();
+        egui::ComboBox::from_label("Material")
+            .selected_text(kind.name())
+            .show_ui(ui, |ui| {
+                for material_type in MaterialType::ALL {
+                    ui.selectable_value(&mut kind, material_type, material_type.name());
+                }
+            });
+        if kind != sphere.mat.kind() {
+            sphere.mat.set_kind(kind);
+            changed = true;
+        }
+        if kind == MaterialType::Dielectric {
+            changed |= ui
+                .add(egui::Slider::new(&mut sphere.mat.ior, 1.0..=2.5).text("IOR"))
+                .changed();
+        }
+        if kind == MaterialType::Diffuse {
+            changed |= ui
+                .add(egui::Slider::new(&mut sphere.mat.metallic, 0.0..=1.0).text("Metallic"))
+                .on_hover_text("Reflect like a mirror tinted by the albedo instead of diffusely")
+                .changed();
+        }
+        // Diffuse materials blur the specular reflection on top of them too
+        if kind != MaterialType::Dielectric {
+            changed |= ui
+                .add(egui::Slider::new(&mut sphere.mat.roughness, 0.0..=1.0).text("Roughness"))
+                .changed();
+        }
+
+        let mut checkerboard = sphere.mat.checkerboard == 1;
+        if ui.checkbox(&mut checkerboard, "Checkerboard").changed() {
+            sphere.mat.checkerboard = checkerboard as u32;
+            changed = true;
+        }
+        if checkerboard {
+            let mut checker_color = [
+                sphere.mat.checker_color.x,
+                sphere.mat.checker_color.y,
+                sphere.mat.checker_color.z,
+            ];
+            ui.horizontal(|ui| {
+                ui.label("Second color");
+                if ui.color_edit_button_rgb(&mut checker_color).changed() {
+                    let [x, y, z] = checker_color;
+                    sphere.mat.checker_color = Vec3::new(x, y, z);
+                    changed = true;
+                }
+            });
+            changed |= ui
+                .add(
+                    egui::Slider::new(&mut sphere.mat.checker_scale, 0.05..=10.0)
+                        .logarithmic(true)
+                        .text("Square size"),
+                )
+                .changed();
+        }
+
+        if changed {
+            self.custom.set_sphere(index, sphere);
+        }
+    }
+
+    fn render_settings(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Resolution")
+            .selected_text(self.custom.resolution.name())
+            .show_ui(ui, |ui| {
+                for resolution in RenderResolution::ALL {
+                    ui.selectable_value(&mut self.custom.resolution, resolution, resolution.name());
+                }
+            });
+        egui::ComboBox::from_label("Preview filter")
+            .selected_text(self.custom.preview_filter.name())
+            .show_ui(ui, |ui| {
+                for filter in PreviewFilter::ALL {
+                    ui.selectable_value(&mut self.custom.preview_filter, filter, filter.name());
+                }
+            })
+            .response
+            .on_hover_text("How the render is scaled to a viewport of another size");
+
+        ui.add(
+            egui::Slider::new(&mut self.custom.samples_per_pixel, 1..=64).text("Samples per pixel"),
+        );
+
+        // Every frame is an unbiased estimate whatever its sample count, but not its bounce count
+        if ui
+            .add(egui::Slider::new(&mut self.custom.max_bounces, 1..=150).text("Max bounces"))
+            .changed()
+        {
+            self.custom.reset_accumulation();
+        }
+        ui.horizontal(|ui| {
+            ui.label("Render region").on_hover_text(
+                "Shift-drag over the preview to only render part of it, the rest keeps its last \
+                 frame",
+            );
+            let has_region = self.custom.render_region().is_some();
+            if ui
+                .add_enabled(has_region, egui::Button::new("Clear"))
+                .on_hover_text("Render the whole frame again")
+                .clicked()
+            {
+                self.custom.clear_render_region();
+            }
+        });
+
+        ui.horizontal(|ui| {
+            // The time runs on its own while animating
+            let time = ui
+                .add_enabled(
+                    !self.custom.animate,
+                    egui::DragValue::new(&mut self.custom.preview_time).speed(0.01),
+                )
+                .on_hover_text("Time the spheres have moved along their velocity for");
+            ui.label("Time");
+            let animate = ui
+                .checkbox(&mut self.custom.animate, "Animate")
+                .on_hover_text(
+                    "Play the scene in real time, which doesn't accumulate while spheres move",
+                );
+            if time.changed() || animate.changed() {
+                self.custom.reset_accumulation();
+            }
+        });
+        if ui
+            .add(egui::Slider::new(&mut self.custom.shutter, 0.0..=2.0).text("Shutter"))
+            .on_hover_text("How long moving spheres blur over, 0 freezes them")
+            .changed()
+        {
+            self.custom.reset_accumulation();
+        }
+
+        ui.add(
+            egui::Slider::new(&mut self.custom.max_frame_count, 1..=65_536)
+                .logarithmic(true)
+                .text("Max frames"),
+        )
+        .on_hover_text("Accumulation stops after this many frames");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.custom.stop_when_converged, "Stop when converged")
+                .on_hover_text("Stop once a frame barely changes the image");
+            ui.add_enabled(
+                self.custom.stop_when_converged,
+                egui::Slider::new(&mut self.custom.convergence_threshold, 1e-5..=1e-2)
+                    .logarithmic(true)
+                    .text("Threshold"),
+            );
+        });
+
+        ui.checkbox(&mut self.custom.denoise, "Denoise")
+            .on_hover_text("Edge-avoiding À-Trous filter, guided by the first hit of every pixel");
+
+        egui::ComboBox::from_label("Tone mapping")
+            .selected_text(self.custom.tone_mapping.name())
+            .show_ui(ui, |ui| {
+                for tone_mapping in ToneMapping::ALL {
+                    ui.selectable_value(
+                        &mut self.custom.tone_mapping,
+                        tone_mapping,
+                        tone_mapping.name(),
+                    );
+                }
+            });
+        ui.add(egui::Slider::new(&mut self.custom.exposure, -5.0..=5.0).text("Exposure (EV)"));
+    }
+
+    fn camera_settings(&mut self, ui: &mut egui::Ui) {
+        egui::ComboBox::from_label("Camera mode")
+            .selected_text(self.custom.camera_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in CameraMode::ALL {
+                    ui.selectable_value(&mut self.custom.camera_mode, mode, mode.name());
+                }
+            });
+        if self.custom.camera_mode == CameraMode::Fly {
+            ui.add(
+                egui::Slider::new(&mut self.custom.fly_speed, 0.1..=50.0)
+                    .logarithmic(true)
+                    .text("Fly speed"),
+            );
+            ui.label("W/A/S/D to move, Q/E down and up, right drag to look around");
+        }
+
+        let mut camera = self.custom.camera();
+        let mut yaw = camera.yaw().to_degrees();
+        let mut pitch = camera.pitch().to_degrees();
+
+        let yaw_changed = ui
+            .add(egui::Slider::new(&mut yaw, -180.0..=180.0).text("Yaw"))
+            .changed();
+        let pitch_changed = ui
+            .add(egui::Slider::new(&mut pitch, -89.0..=89.0).text("Pitch"))
+            .changed();
+
+        let lens_changed = ui
+            .add(egui::Slider::new(&mut camera.aperture, 0.0..=1.0).text("Aperture"))
+            .changed()
+            | ui.add_enabled(
+                camera.aperture > 0.0,
+                egui::Slider::new(&mut camera.focus_distance, 0.1..=50.0)
+                    .logarithmic(true)
+                    .text("Focus distance"),
+            )
+            .changed();
+
+        let mut fov_y = camera.fov_y.to_degrees();
+        let fov_changed = ui
+            .add(
+                egui::Slider::new(&mut fov_y, MIN_FOV_Y_DEGREES..=MAX_FOV_Y_DEGREES)
+                    .text("Field of view")
+                    .suffix("°"),
+            )
+            .changed();
+        camera.fov_y = fov_y.to_radians();
+
+        if yaw_changed || pitch_changed || lens_changed || fov_changed {
+            camera.set_orientation(yaw.to_radians(), pitch.to_radians());
+            self.custom.set_camera(camera);
+        }
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Copy camera")
+                .on_hover_text("Copy the camera as one line of text to reproduce this shot")
+                .clicked()
+            {
+                let text = self.custom.camera().to_ron_line();
+                ui.output_mut(|output| output.copied_text = text);
+            }
+            ui.add(
+                egui::TextEdit::singleline(&mut self.camera_paste)
+                    .hint_text("Paste a camera")
+                    .desired_width(120.0),
+            );
+            if ui
+                .add_enabled(
+                    !self.camera_paste.trim().is_empty(),
+                    egui::Button::new("Apply"),
+                )
+                .clicked()
+            {
+                match Camera::from_ron(&self.camera_paste) {
+                    Ok(camera) => {
+                        self.custom.set_camera(camera);
+                        self.camera_paste.clear();
+                    }
+                    Err(err) => re_log::error!("Failed to parse the pasted camera: {err}"),
+                }
+            }
+        });
+    }
+
+    fn sun_settings(&mut self, ui: &mut egui::Ui) {
+        let custom = &mut self.custom;
+        let intensity_changed = ui
+            .add(
+                egui::Slider::new(&mut custom.sun_intensity, 0.0..=10.0)
+                    .text("Sun intensity")
+                    .logarithmic(true),
+            )
+            .changed();
+        let sun_changed = ui
+            .add_enabled_ui(custom.sun_intensity > 0.0, |ui| {
+                let azimuth_changed = ui
+                    .add(
+                        egui::Slider::new(&mut custom.sun_azimuth, -180.0..=180.0)
+                            .text("Sun azimuth"),
+                    )
+                    .changed();
+                let elevation_changed = ui
+                    .add(
+                        egui::Slider::new(&mut custom.sun_elevation, 0.0..=90.0)
+                            .text("Sun elevation"),
+                    )
+                    .changed();
+                let color_changed = ui
+                    .horizontal(|ui| {
+                        ui.label("Sun color");
+                        ui.color_edit_button_rgb(&mut custom.sun_color).changed()
+                    })
+                    .inner;
+                azimuth_changed || elevation_changed || color_changed
+            })
+            .inner;
+
+        if intensity_changed || sun_changed {
+            custom.reset_accumulation();
+        }
+    }
+
+    fn group_editor(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Groups");
+        let count = self.custom.groups().len();
+        self.selected_group = clamp_selection(self.selected_group, count);
+        selection(ui, "Group", &mut self.selected_group, count);
+
+        ui.horizontal(|ui| {
+            if ui
+                .button("Add")
+                .on_hover_text("Starts with the selected sphere")
+                .clicked()
+            {
+                let group = Group {
+                    sphere_indices: self.selected_sphere.into_iter().collect(),
+                    ..Default::default()
+                };
+                self.selected_group = Some(self.custom.add_group(group));
+            }
+            if let Some(index) = self.selected_group {
+                if ui
+                    .button("Delete")
+                    .on_hover_text("Keeps the spheres")
+                    .clicked()
+                {
+                    if self.custom.remove_group(index).is_some() {
+                        self.selected_group = Some(index.saturating_sub(1));
+                    }
+                }
+            }
+        });
+
+        // The selection may have just been deleted
+        let selected = self
+            .selected_group
+            .and_then(|index| Some((index, self.custom.groups().get(index)?.clone())));
+        let Some((index, group)) = selected else {
+            return;
+        };
+
+        let previous = group.transform.translation();
+        let mut translation = previous;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Translation");
+            for value in [&mut translation.x, &mut translation.y, &mut translation.z] {
+                changed |= ui.add(egui::DragValue::new(value).speed(0.05)).changed();
+            }
+        });
+        if changed {
+            let delta = Mat4::from_translation(translation - previous);
+            self.custom.transform_group(index, delta);
+        }
+
+        ui.collapsing("Members", |ui| {
+            let mut sphere_indices = group.sphere_indices.clone();
+            for sphere in 0..self.custom.spheres().len() {
+                let mut member = sphere_indices.contains(&sphere);
+                if ui
+                    .checkbox(&mut member, format!("Sphere {sphere}"))
+                    .changed()
+                {
+                    if member {
+                        sphere_indices.push(sphere);
+                    } else {
+                        sphere_indices.retain(|&other| other != sphere);
+                    }
+                }
+            }
+            if sphere_indices != group.sphere_indices {
+                self.custom.set_group_spheres(index, sphere_indices);
+            }
+        });
+    }
+
+    /// Handles dragging the spheres of the selected group along the world axes. Returns the
+    /// arrows to paint over the viewport.
+    fn group_gizmo(&mut self, ui: &mut egui::Ui) -> Vec<egui::Shape> {
+        let mut shapes = Vec::new();
+        let Some(index) = self.selected_group.filter(|_| ui.is_enabled()) else {
+            return shapes;
+        };
+        let Some(center) = self.custom.scene().group_center(index) else {
+            return shapes;
+        };
+        let Some(origin) = self.custom.project(center) else {
+            return shapes;
+        };
+
+        let length = GIZMO_SCALE * (center - self.custom.camera().position).length();
+        let axes = [
+            (Vec3::new(1.0, 0.0, 0.0), egui::Color32::RED),
+            (Vec3::new(0.0, 1.0, 0.0), egui::Color32::GREEN),
+            (Vec3::new(0.0, 0.0, 1.0), egui::Color32::LIGHT_BLUE),
+        ];
+        for (axis_index, (axis, color)) in axes.into_iter().enumerate() {
+            let Some(tip) = self.custom.project(center + axis * length) else {
+                continue;
+            };
+            // Axes pointing at the camera can't be dragged along
+            let along = tip - origin;
+            if along.length_sq() < 1.0 {
+                continue;
+            }
+
+            let handle = egui::Rect::from_center_size(tip, egui::Vec2::splat(GIZMO_HANDLE_SIZE));
+            let id = ui.id().with(("group_gizmo", axis_index));
+            let response = ui.interact(handle, id, egui::Sense::drag());
+            if response.dragged() {
+                let amount = response.drag_delta().dot(along) / along.length_sq() * length;
+                self.custom
+                    .transform_group(index, Mat4::from_translation(axis * amount));
+            }
+
+            let color = if response.hovered() || response.dragged() {
+                egui::Color32::WHITE
+            } else {
+                color
+            };
+            shapes.push(egui::Shape::line_segment([origin, tip], (2.0, color)));
+            shapes.push(egui::Shape::rect_filled(handle.shrink(3.0), 1.0, color));
+        }
+        shapes
+    }
+
+    fn light_editor(&mut self, ui: &mut egui::Ui) {
+        ui.strong("Point lights");
+        let count = self.custom.lights().len();
+        self.selected_light = clamp_selection(self.selected_light, count);
+        selection(ui, "Light", &mut self.selected_light, count);
+
+        ui.horizontal(|ui| {
+            if ui.button("Add").clicked() {
+                let light =
+                    PointLight::new(Vec3::new(3.0, 0.0, 3.0), Vec3::new(1.0, 1.0, 1.0), 10.0);
+                self.selected_light = Some(self.custom.add_light(light));
+            }
+            if let Some(index) = self.selected_light {
+                if ui.button("Delete").clicked() {
+                    self.custom.remove_light(index);
+                    self.selected_light = Some(index.saturating_sub(1));
+                }
+            }
+        });
+
+        // The selection may have just been deleted
+        let selected = self
+            .selected_light
+            .and_then(|index| Some((index, *self.custom.lights().get(index)?)));
+        let Some((index, mut light)) = selected else {
+            return;
+        };
+
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("Position");
+            for value in [
+                &mut light.position.x,
+                &mut light.position.y,
+                &mut light.position.z,
+            ] {
+                changed |= ui.add(egui::DragValue::new(value).speed(0.05)).changed();
+            }
+        });
+
+        let mut color = [light.color.x, light.color.y, light.color.z];
+        ui.horizontal(|ui| {
+            ui.label("Color");
+            if ui.color_edit_button_rgb(&mut color).changed() {
+                let [x, y, z] = color;
+                light.color = Vec3::new(x, y, z);
+                changed = true;
+            }
+        });
+        changed |= ui
+            .add(
+                egui::Slider::new(&mut light.intensity, 0.0..=1000.0)
+                    .logarithmic(true)
+                    .text("Intensity"),
+            )
+            .on_hover_text("Falls off with the square of the distance")
+            .changed();
+
+        if changed {
+            self.custom.set_light(index, light);
+        }
+    }
+
+    fn debug_settings(&mut self, ui: &mut egui::Ui) {
+        let previous_mode = self.custom.debug_mode;
+        egui::ComboBox::from_label("Debug view")
+            .selected_text(previous_mode.name())
+            .show_ui(ui, |ui| {
+                for mode in DebugMode::ALL {
+                    ui.selectable_value(&mut self.custom.debug_mode, mode, mode.name());
+                }
+            });
+        let mut ao_changed = false;
+        if self.custom.debug_mode == DebugMode::AmbientOcclusion {
+            ao_changed = ui
+                .add(
+                    egui::Slider::new(&mut self.custom.ao_radius, 0.01..=10.0)
+                        .logarithmic(true)
+                        .text("AO radius"),
+                )
+                .changed()
+                | ui.add(egui::Slider::new(&mut self.custom.ao_samples, 1..=64).text("AO rays"))
+                    .on_hover_text("Occlusion rays per sample, more converge in fewer frames")
+                    .changed();
+        }
+
+        let previous_center = self.custom.pixel_center;
+        egui::ComboBox::from_label("Pixel center")
+            .selected_text(previous_center.name())
+            .show_ui(ui, |ui| {
+                for center in PixelCenter::ALL {
+                    ui.selectable_value(&mut self.custom.pixel_center, center, center.name());
+                }
+            });
+
+        let previous_background = self.custom.background_mode;
+        egui::ComboBox::from_label("Background")
+            .selected_text(previous_background.name())
+            .show_ui(ui, |ui| {
+                for background in BackgroundMode::ALL {
+                    ui.selectable_value(
+                        &mut self.custom.background_mode,
+                        background,
+                        background.name(),
+                    );
+                }
+            });
+
+        let mut background_color_changed = false;
+        match self.custom.background_mode {
+            BackgroundMode::Color => {
+                ui.horizontal(|ui| {
+                    ui.label("Background color");
+                    background_color_changed = ui
+                        .color_edit_button_rgb(&mut self.custom.background_color)
+                        .changed();
+                });
+            }
+            BackgroundMode::Sky => {
+                ui.horizontal(|ui| {
+                    ui.label("Sky");
+                    background_color_changed = ui
+                        .color_edit_button_rgb(&mut self.custom.sky_top_color)
+                        .on_hover_text("Towards +z")
+                        .changed()
+                        | ui.color_edit_button_rgb(&mut self.custom.sky_bottom_color)
+                            .on_hover_text("Towards -z")
+                            .changed();
+                });
+            }
+            BackgroundMode::Transparent => {}
+            BackgroundMode::Environment => {
+                if ui.button("Load Environment").clicked() {
+                    if let Some(path) = rfd::FileDialog::new()
+                        .add_filter("HDR image", &["hdr", "exr"])
+                        .pick_file()
+                    {
+                        if let Err(err) = self.custom.load_environment(&path) {
+                            re_log::error!("Failed to load {path:?}: {err}");
+                        }
+                    }
+                }
+            }
+        }
+
+        let accumulation_toggled = ui
+            .checkbox(&mut self.custom.accumulate, "Accumulate samples")
+            .changed();
+        ui.add_enabled(
+            self.custom.accumulate,
+            egui::Checkbox::new(
+                &mut self.custom.reproject_on_resize,
+                "Keep samples on resize",
+            ),
+        )
+        .on_hover_text(
+            "Resample the accumulated image to the new size instead of starting over, unless \
+             the aspect ratio changes a lot",
+        );
+        let intersection_toggled = ui
+            .checkbox(
+                &mut self.custom.stable_intersection,
+                "Stable sphere intersection",
+            )
+            .changed();
+        let jitter_toggled = ui
+            .checkbox(&mut self.custom.jitter, "Jitter samples (antialiasing)")
+            .changed();
+        let seed_changed = ui
+            .horizontal(|ui| {
+                ui.checkbox(&mut self.custom.deterministic, "Deterministic")
+                    .on_hover_text(
+                        "Derive the random numbers from a fixed seed, so the scene renders the \
+                         same every time",
+                    )
+                    .changed()
+                    | ui.add_enabled(
+                        self.custom.deterministic,
+                        egui::DragValue::new(&mut self.custom.base_seed).prefix("Seed "),
+                    )
+                    .changed()
+            })
+            .inner;
+        let clamp_changed = ui
+            .horizontal(|ui| {
+                ui.checkbox(&mut self.custom.firefly_clamp, "Clamp fireflies")
+                    .on_hover_text(
+                        "Limit how bright a single sample can be. Removes fireflies but darkens \
+                         bright indirect light a little, so the image is no longer exact",
+                    )
+                    .changed()
+                    | ui.add_enabled(
+                        self.custom.firefly_clamp,
+                        egui::Slider::new(&mut self.custom.max_sample_radiance, 1.0..=100.0)
+                            .logarithmic(true)
+                            .text("Max"),
+                    )
+                    .changed()
+            })
+            .inner;
+        self.workgroup_settings(ui);
+
+        ui.separator();
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.custom.paused, "⏸ Pause")
+                .on_hover_text("Stop rendering new frames once the image has converged");
+            if ui
+                .add_enabled(self.custom.paused, egui::Button::new("Step"))
+                .clicked()
+            {
+                self.custom.step();
+            }
+            if ui.button("Reset accumulation").clicked() {
+                self.custom.reset_accumulation();
+            }
+        });
+        ui.label(format!("Frame: {}", self.custom.frame_count()));
+
+        if self.custom.debug_mode != previous_mode
+            || ao_changed
+            || self.custom.pixel_center != previous_center
+            || self.custom.background_mode != previous_background
+            || background_color_changed
+            || accumulation_toggled
+            || intersection_toggled
+            || jitter_toggled
+            || seed_changed
+            || clamp_changed
+        {
+            self.custom.reset_accumulation();
+        }
+    }
+
+    /// Recompiles the kernel for another workgroup size, next to the time frames take with it.
+    fn workgroup_settings(&mut self, ui: &mut egui::Ui) {
+        let current = self.custom.workgroup_size();
+        let mut workgroup_size = current;
+        ui.horizontal(|ui| {
+            egui::ComboBox::from_label("Workgroup size")
+                .selected_text(current.to_string())
+                .show_ui(ui, |ui| {
+                    for size in WorkgroupSize::PRESETS {
+                        ui.selectable_value(&mut workgroup_size, size, size.to_string());
+                    }
+                });
+            match (self.custom.gpu_time(), self.frame_timer.fps()) {
+                (Some(gpu_time), _) => {
+                    ui.label(format!("{:.2} ms/frame", gpu_time.as_secs_f64() * 1000.0))
+                        .on_hover_text("GPU time of the raytracing compute pass");
+                }
+                (None, Some(fps)) => {
+                    ui.label(format!("{:.2} ms/frame", 1000.0 / fps))
+                        .on_hover_text("Time between frames, GPU time is unavailable");
+                }
+                (None, None) => {}
+            }
+        });
+        if workgroup_size != current {
+            self.custom.set_workgroup_size(workgroup_size);
+        }
+    }
+}
+
+/// Which shader failed to compile and where, then the compiler's message.
+fn shader_error_label(ui: &mut egui::Ui, error: &ShaderError) {
+    let title = match error.line {
+        Some(line) => format!("Failed to compile {} at line {line}", error.shader),
+        None => format!("Failed to compile {}", error.shader),
+    };
+    ui.colored_label(ui.visuals().error_fg_color, title);
+    egui::ScrollArea::vertical().show(ui, |ui| {
+        ui.label(egui::RichText::new(&error.message).monospace());
+    });
+}
+
+/// Parses comma separated exposure stops like "-2, 0, 2".
+fn parse_stops(text: &str) -> Result<Vec<f32>, String> {
+    let stops = text
+        .split(',')
+        .map(str::trim)
+        .filter(|stop| !stop.is_empty())
+        .map(|stop| {
+            stop.parse::<f32>()
+                .ok()
+                .filter(|stop| stop.is_finite())
+                .ok_or_else(|| format!("{stop:?} isn't an exposure stop"))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if stops.is_empty() {
+        return Err("No exposure stops".to_owned());
+    }
+    Ok(stops)
+}
+
+fn tile_size_name(tile_size: Option<u32>) -> String {
+    match tile_size {
+        Some(size) => format!("{size} x {size}"),
+        None => "Whole frames".to_owned(),
+    }
 }
 
-fn selection_buttons(ui: &mut egui::Ui) {
+fn load_png(path: &std::path::Path) -> Result<egui::ColorImage, png::DecodingError> {
+    let mut decoder = png::Decoder::new(std::fs::File::open(path)?);
+    decoder.set_transformations(png::Transformations::EXPAND | png::Transformations::STRIP_16);
+    let mut reader = decoder.read_info()?;
+    let mut buffer = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buffer)?;
+    let pixels = &buffer[..info.buffer_size()];
+
+    let rgba: Vec<u8> = match info.color_type {
+        png::ColorType::Rgba => pixels.to_vec(),
+        png::ColorType::Rgb => pixels
+            .chunks(3)
+            .flat_map(|p| [p[0], p[1], p[2], 255])
+            .collect(),
+        png::ColorType::GrayscaleAlpha => pixels
+            .chunks(2)
+            .flat_map(|p| [p[0], p[0], p[0], p[1]])
+            .collect(),
+        _ => pixels.iter().flat_map(|&v| [v, v, v, 255]).collect(),
+    };
+
+    let size = [info.width as usize, info.height as usize];
+    Ok(egui::ColorImage::from_rgba_unmultiplied(size, &rgba))
+}
+
+/// Previous/next buttons around a combo box picking one of `count` spheres.
+/// Keeps a selection among `count` items in range, selecting nothing when there are none.
+fn clamp_selection(selected: Option<usize>, count: usize) -> Option<usize> {
+    selected
+        .filter(|_| count > 0)
+        .map(|index| index.min(count - 1))
+}
+
+/// Picks one of `count` items named like `Sphere 0`, `Sphere 1`, ... for `kind` "Sphere", or none.
+fn selection(ui: &mut egui::Ui, kind: &str, selected: &mut Option<usize>, count: usize) {
     use egui_extras::{Size, StripBuilder};
 
     const BUTTON_SIZE: f32 = 20.0;
@@ -125,21 +1709,81 @@ fn selection_buttons(ui: &mut egui::Ui) {
             .size(Size::exact(BUTTON_SIZE)) // next
             .horizontal(|mut strip| {
                 strip.cell(|ui| {
-                    let _ = ui.small_button("⏴");
+                    let has_previous = selected.is_some_and(|index| index > 0);
+                    if ui
+                        .add_enabled(has_previous, egui::Button::new("⏴").small())
+                        .clicked()
+                    {
+                        *selected = selected.map(|index| index - 1);
+                    }
                 });
 
                 strip.cell(|ui| {
-                    egui::ComboBox::from_id_source("foo")
+                    let selected_text = match (count, *selected) {
+                        (0, _) => format!("No {}s", kind.to_lowercase()),
+                        (_, None) => format!("No {} selected", kind.to_lowercase()),
+                        (_, Some(index)) => format!("{kind} {index}"),
+                    };
+                    egui::ComboBox::from_id_source((kind, "selection"))
                         .width(ui.available_width())
-                        .selected_text("ComboBox")
+                        .selected_text(selected_text)
                         .show_ui(ui, |ui| {
-                            ui.label("contents");
+                            for index in 0..count {
+                                ui.selectable_value(
+                                    selected,
+                                    Some(index),
+                                    format!("{kind} {index}"),
+                                );
+                            }
                         });
                 });
 
                 strip.cell(|ui| {
-                    let _ = ui.small_button("⏵");
+                    let has_next = selected.map_or(count > 0, |index| index + 1 < count);
+                    if ui
+                        .add_enabled(has_next, egui::Button::new("⏵").small())
+                        .clicked()
+                    {
+                        *selected = Some(selected.map_or(0, |index| index + 1));
+                    }
                 });
             });
     });
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fps_averages_the_last_frames() {
+        let mut timer = FrameTimer::default();
+        assert_eq!(timer.fps(), None);
+
+        timer.record(1.0);
+        for _ in 0..FPS_WINDOW {
+            timer.record(0.01);
+        }
+
+        // The slow first frame fell out of the window
+        let fps = timer.fps().unwrap();
+        assert!((fps - 100.0).abs() < 1e-2, "{fps}");
+    }
+
+    #[test]
+    fn selection_stays_in_range() {
+        assert_eq!(clamp_selection(Some(5), 3), Some(2));
+        assert_eq!(clamp_selection(Some(1), 3), Some(1));
+        assert_eq!(clamp_selection(Some(0), 0), None);
+        assert_eq!(clamp_selection(None, 3), None);
+    }
+
+    #[test]
+    fn exposure_stops_are_comma_separated() {
+        assert_eq!(parse_stops("-2, 0, 2"), Ok(vec![-2.0, 0.0, 2.0]));
+        assert_eq!(parse_stops("+1.5,"), Ok(vec![1.5]));
+        assert!(parse_stops("-2, bright").is_err());
+        assert!(parse_stops(" ").is_err());
+        assert!(parse_stops("inf").is_err());
+    }
+}
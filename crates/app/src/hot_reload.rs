@@ -0,0 +1,57 @@
+//! Watches the kernel's source with the `hot-reload` feature, so editing it doesn't take a rebuild
+//! and a restart.
+
+use std::path::{Path, PathBuf};
+
+use crossbeam::channel::{unbounded, Receiver};
+use notify::{RecursiveMode, Watcher};
+
+pub struct ShaderWatcher {
+    path: PathBuf,
+    /// Stops watching once dropped.
+    _watcher: notify::RecommendedWatcher,
+    rx: Receiver<notify::Event>,
+}
+
+impl ShaderWatcher {
+    /// Watches the shader at `path`, repainting `egui_ctx` on changes so a paused app sees them.
+    pub fn new(path: &Path, egui_ctx: egui::Context) -> notify::Result<Self> {
+        let (tx, rx) = unbounded();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| match event {
+                Ok(event) => {
+                    let _ = tx.send(event);
+                    egui_ctx.request_repaint();
+                }
+                Err(err) => re_log::warn!("Failed to watch the shader: {err}"),
+            })?;
+        // Editors often save by replacing the file, which a watch on the file itself would miss
+        let directory = path.parent().unwrap_or(Path::new("."));
+        watcher.watch(directory, RecursiveMode::NonRecursive)?;
+        re_log::info!("Reloading {path:?} when it changes");
+        Ok(Self {
+            path: path.to_owned(),
+            _watcher: watcher,
+            rx,
+        })
+    }
+
+    /// The shader's new source if it was saved since the last call.
+    pub fn changed_source(&self) -> Option<String> {
+        // Saving fires several events, drain them all so the shader is only reloaded once
+        let mut changed = false;
+        for event in self.rx.try_iter() {
+            changed |= (event.kind.is_create() || event.kind.is_modify())
+                && event
+                    .paths
+                    .iter()
+                    .any(|path| path.file_name() == self.path.file_name());
+        }
+        if !changed {
+            return None;
+        }
+        std::fs::read_to_string(&self.path)
+            .map_err(|err| re_log::error!("Failed to read {:?}: {err}", self.path))
+            .ok()
+    }
+}
@@ -1,5 +1,7 @@
 #![warn(clippy::all, rust_2018_idioms)]
 
 mod app;
-mod renderer;
-pub use app::ExampleApp;
+#[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+mod hot_reload;
+pub mod renderer;
+pub use app::{ErrorApp, ExampleApp};
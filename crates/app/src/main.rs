@@ -5,18 +5,46 @@
 fn main() -> eframe::Result<()> {
     re_log::setup_native_logging();
 
-    let native_options = eframe::NativeOptions {
+    let mut native_options = eframe::NativeOptions {
         initial_window_size: Some([1200.0, 800.0].into()),
         follow_system_theme: false,
         default_theme: eframe::Theme::Dark,
         ..Default::default()
     };
+    let wgpu_options = &mut native_options.wgpu_options;
+    // Same backends as the headless renderer, so both pick the same adapter
+    wgpu_options.backends = renderer::raytracer::backends_from_env();
+    wgpu_options.power_preference = renderer::raytracer::power_preference_from_env();
+    re_log::info!(
+        "Using the {:?} backends, preferring {:?}",
+        wgpu_options.backends,
+        wgpu_options.power_preference
+    );
+    wgpu_options.device_descriptor.features |=
+        app::renderer::optional_features(wgpu_options.backends);
 
-    eframe::run_native(
-        "App",
-        native_options,
-        Box::new(move |cc| Box::new(app::ExampleApp::new(cc))),
-    )
+    let result = eframe::run_native("App", native_options, Box::new(create_app));
+    if let Err(err) = &result {
+        // eframe failed before the app was created, usually because no adapter supports its
+        // backends, so there is no egui to show this in. The console may be hidden too, as in
+        // Windows release builds.
+        rfd::MessageDialog::new()
+            .set_level(rfd::MessageLevel::Error)
+            .set_title("App")
+            .set_description(&format!("Could not start the renderer.\n\n{err}"))
+            .show();
+    }
+    result
+}
+
+fn create_app(cc: &eframe::CreationContext<'_>) -> Box<dyn eframe::App> {
+    match app::ExampleApp::new(cc) {
+        Ok(app) => Box::new(app),
+        Err(err) => {
+            re_log::error!("{err}");
+            Box::new(app::ErrorApp::new(err))
+        }
+    }
 }
 
 #[cfg(target_arch = "wasm32")]
@@ -34,7 +62,7 @@ fn main() {
         eframe::start_web(
             "the_canvas_id", // hardcode it
             web_options,
-            Box::new(move |cc| Box::new(app::ExampleApp::new(cc))),
+            Box::new(create_app),
         )
         .await
         .expect("failed to start eframe");
@@ -2,18 +2,106 @@ use crossbeam::channel::unbounded;
 use crossbeam::channel::{Receiver, Sender};
 use std::borrow::Cow;
 use std::fs::File;
-use std::io::Write;
+use std::io::BufWriter;
 use std::num::{NonZeroU32, NonZeroU8};
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU8, Ordering};
 use std::sync::Arc;
+use std::time::Duration;
+// `std::time::Instant` panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
 
 use egui_wgpu::{self, wgpu};
 
 use bytemuck::{Pod, Zeroable};
 use rand::Rng;
+use renderer::denoiser::Denoiser;
+use renderer::environment::{Environment, EnvironmentMap};
+use renderer::raytracer::{
+    self, get_padded_bytes_per_row_from_width, FrameChange, GBuffer, KernelBuffers, SceneBuffers,
+    SceneCapacity, ShaderError, Tile, WorkgroupSize,
+};
+use serde::{Deserialize, Serialize};
 use wgpu::util::DeviceExt;
 
-enum Message {}
+pub use renderer::scene::{
+    Camera, Group, Mat4, Material, MaterialType, Plane, PointLight, RenderSettings, Scene,
+    SceneInfo, Sphere, Triangle, Vec3, DEFAULT_MAX_BOUNCES, DEFAULT_SAMPLES_PER_PIXEL,
+    DEFAULT_SKY_BOTTOM_COLOR, DEFAULT_SKY_TOP_COLOR,
+};
+
+/// Sent from the paint callbacks, which run on the render pass, back to the UI.
+enum Message {
+    /// A frame was traced. `prepare_time` is the CPU time spent uploading the scene and
+    /// recording the compute pass.
+    FramePrepared {
+        frame_count: u32,
+        prepare_time: Duration,
+    },
+    /// GPU time of the compute pass of a previous frame, read back from the timestamp queries.
+    ComputePassTimed { gpu_time: Duration },
+    /// How much a previous frame changed the accumulated image.
+    FrameChangeMeasured { frame_change: FrameChange },
+}
+
+/// Latest stats reported by the paint callbacks.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct RenderStats {
+    /// Accumulation frame the stats were measured on.
+    pub frame_count: u32,
+    pub prepare_time: Duration,
+}
+
+/// Optional device features the app makes use of, limited to the ones every adapter on
+/// `backends` supports so requesting them can't fail whichever adapter eframe picks.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn optional_features(backends: wgpu::Backends) -> wgpu::Features {
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+        backends,
+        ..Default::default()
+    });
+    instance
+        .enumerate_adapters(backends)
+        .map(|adapter| adapter.features() & wgpu::Features::TIMESTAMP_QUERY)
+        .reduce(|common, features| common & features)
+        .unwrap_or(wgpu::Features::empty())
+}
+
+const INITIAL_CAPACITY: SceneCapacity = SceneCapacity {
+    spheres: 16,
+    planes: 16,
+    triangles: 16,
+    lights: 4,
+};
+const DEFAULT_ORBIT_DISTANCE: f32 = 7.0;
+const DEFAULT_FLY_SPEED: f32 = 3.0;
+const DEFAULT_SUN_AZIMUTH: f32 = 45.0;
+const DEFAULT_SUN_ELEVATION: f32 = 45.0;
+/// How long the wanted render size must stay the same before the render target is reallocated.
+const RESIZE_DEBOUNCE: Duration = Duration::from_millis(100);
+/// How often the GPU resources are rebuilt while the device keeps reporting errors, and how long
+/// the status bar says so.
+const GPU_RECOVERY_INTERVAL: Duration = Duration::from_secs(1);
+/// The running average is stored as half floats, whose 11 bits of precision stop registering new
+/// samples of a converged pixel not long after 2048 frames.
+const DEFAULT_MAX_FRAME_COUNT: u32 = 1024;
+const DEFAULT_MAX_SAMPLE_RADIANCE: f32 = 10.0;
+const DEFAULT_AO_RADIUS: f32 = 1.0;
+const DEFAULT_AO_SAMPLES: u32 = 4;
+/// Time the shutter stays open, over which moving spheres blur.
+const DEFAULT_SHUTTER: f32 = 1.0;
+/// Mean change of a channel per frame below which the image counts as converged. Noise shrinks
+/// with the square root of the frames, so this is reached after a few hundred on typical scenes.
+const DEFAULT_CONVERGENCE_THRESHOLD: f32 = 5e-4;
+/// Largest factor the aspect ratio may change by for a resize to keep the accumulation. Wider
+/// changes would stretch the edges of the old image over much of the new one.
+const MAX_REPROJECTION_ASPECT_CHANGE: f32 = 1.25;
+/// Frames a reprojected accumulation counts as at most, so its resampling blur fades out as new
+/// frames come in.
+const MAX_REPROJECTED_FRAMES: u32 = 64;
 
 #[repr(C)]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
@@ -22,57 +110,818 @@ pub struct Vec2 {
     pub y: f32,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
-pub struct Vec3 {
-    pub x: f32,
-    pub y: f32,
-    pub z: f32,
+/// A primary ray, as generated by `raytracer_kernel.wgsl`.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ray {
+    pub origin: Vec3,
+    pub direction: Vec3,
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
-pub struct Material {
-    pub albedo: Vec3,
-    pub is_mirror: u32,
-    pub unused_buffer: [u32; 0],
+/// Maps a position in egui points to the render's [0, 1] screen space.
+///
+/// `rect` is the letterboxed viewport allocated by `custom_painting`. The render texture is sized
+/// in points, so the mapping doesn't depend on `pixels_per_point`. Returns `None` outside the
+/// viewport.
+pub fn viewport_to_screen(rect: egui::Rect, pos: egui::Pos2) -> Option<Vec2> {
+    if rect.width() <= 0.0 || rect.height() <= 0.0 || !rect.contains(pos) {
+        return None;
+    }
+
+    Some(Vec2 {
+        x: (pos.x - rect.min.x) / rect.width(),
+        y: (pos.y - rect.min.y) / rect.height(),
+    })
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
-pub struct Sphere {
-    pub position: Vec3,
-    pub radius: f32,
-    pub mat: Material,
+/// Builds the unjittered primary ray through a [0, 1] screen position, mirroring `sample` in the
+/// kernel. `aspect` is the render width over its height.
+pub fn primary_ray(camera: &Camera, screen: Vec2, aspect: f32) -> Ray {
+    let height = 2.0 * (camera.fov_y * 0.5).tan();
+    let horizontal_coefficient = (screen.x - 0.5) * height * aspect;
+    let vertical_coefficient = (screen.y - 0.5) * height;
+    let direction = camera.forward()
+        + camera.right() * horizontal_coefficient
+        + camera.up() * vertical_coefficient;
+
+    Ray {
+        origin: camera.position,
+        direction: direction.normalize(),
+    }
+}
+
+/// The [0, 1] screen position `point` is seen at, the inverse of `primary_ray`. `None` when it is
+/// behind the camera.
+pub fn project(camera: &Camera, point: Vec3, aspect: f32) -> Option<Vec2> {
+    let offset = point - camera.position;
+    let depth = offset.dot(camera.forward());
+    if depth <= 1e-4 {
+        return None;
+    }
+    let height = 2.0 * (camera.fov_y * 0.5).tan();
+    Some(Vec2 {
+        x: offset.dot(camera.right()) / (depth * height * aspect) + 0.5,
+        y: offset.dot(camera.up()) / (depth * height) + 0.5,
+    })
+}
+
+/// Distance along `ray` to where it enters `sphere`, or leaves it when starting inside, mirroring
+/// `hit` in the kernel with stable intersection.
+pub fn hit_sphere(ray: Ray, sphere: &Sphere) -> Option<f32> {
+    let oc = ray.origin - sphere.position;
+    let a = ray.direction.dot(ray.direction);
+    let half_b = oc.dot(ray.direction);
+    let c = oc.dot(oc) - sphere.radius * sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+    if discriminant < 0.0 {
+        return None;
+    }
+    let q = -(half_b + half_b.signum() * discriminant.sqrt());
+    // Both roots are 0 when the ray starts on the sphere and only grazes it
+    if q == 0.0 {
+        return None;
+    }
+    let (near, far) = ((q / a).min(c / q), (q / a).max(c / q));
+    [near, far].into_iter().find(|&t| t > 0.0001)
+}
+
+/// Distance along `ray` to `plane`, mirroring `hit_plane` in the kernel.
+pub fn hit_plane(ray: Ray, plane: &Plane) -> Option<f32> {
+    let denominator = plane.normal.dot(ray.direction);
+    if denominator.abs() < 1e-6 {
+        return None;
+    }
+    let t = (plane.point - ray.origin).dot(plane.normal) / denominator;
+    (t >= 0.0001).then_some(t)
+}
+
+/// Distance along `ray` to `triangle`, mirroring `hit_triangle` in the kernel.
+pub fn hit_triangle(ray: Ray, triangle: &Triangle) -> Option<f32> {
+    let edge1 = triangle.v1 - triangle.v0;
+    let edge2 = triangle.v2 - triangle.v0;
+    let p = ray.direction.cross(edge2);
+    let determinant = edge1.dot(p);
+    if determinant.abs() < 1e-8 {
+        return None;
+    }
+    let s = ray.origin - triangle.v0;
+    let u = s.dot(p) / determinant;
+    let q = s.cross(edge1);
+    let v = ray.direction.dot(q) / determinant;
+    if !(0.0..=1.0).contains(&u) || v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(q) / determinant;
+    (t >= 0.0001).then_some(t)
 }
 
+/// Index of the closest sphere `ray` hits with the spheres moved to `time`, `None` when a plane
+/// or a triangle is in front of it. Primitives hidden from the camera are skipped like camera
+/// rays do.
+pub fn pick_sphere(scene: &Scene, ray: Ray, time: f32) -> Option<usize> {
+    let (index, t) = scene
+        .spheres
+        .iter()
+        .enumerate()
+        .filter(|(_, sphere)| sphere.mat.camera_visible == 1)
+        .filter_map(|(index, sphere)| {
+            let mut moved = *sphere;
+            moved.position = sphere.position_at(time);
+            Some((index, hit_sphere(ray, &moved)?))
+        })
+        .min_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let planes = scene
+        .planes
+        .iter()
+        .filter(|plane| plane.mat.camera_visible == 1)
+        .filter_map(|plane| hit_plane(ray, plane));
+    let triangles = scene
+        .triangles
+        .iter()
+        .filter(|triangle| triangle.mat.camera_visible == 1)
+        .filter_map(|triangle| hit_triangle(ray, triangle));
+    planes
+        .chain(triangles)
+        .all(|occluder| occluder >= t)
+        .then_some(index)
+}
+
+/// Uniforms of the screen pass, which maps the HDR render to the display.
 #[repr(C)]
 #[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
-pub struct Camera {
-    pub position: Vec3,
+pub struct DisplaySettings {
+    /// In stops, the color is scaled by `2^exposure` before tone mapping.
+    pub exposure: f32,
+    pub tone_mapping: u32,
+    /// 1 when the target isn't an sRGB format, so the shader has to encode the color itself.
+    pub encode_srgb: u32,
     unused_buffer: [u32; 1],
 }
 
-#[repr(C)]
-#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
-pub struct SceneInfo {
-    pub camera: Camera,
-    pub time: f32,
-    pub sphere_count: u32,
-    pub random_seed: f32,
-    pub frame_count: u32,
+impl DisplaySettings {
+    /// Settings for drawing to a `target_format` texture, whatever format eframe picked.
+    pub fn new(
+        exposure: f32,
+        tone_mapping: ToneMapping,
+        target_format: wgpu::TextureFormat,
+    ) -> Self {
+        Self {
+            exposure,
+            tone_mapping: tone_mapping as u32,
+            encode_srgb: !target_format.describe().srgb as u32,
+            ..Default::default()
+        }
+    }
+}
+
+/// Replaces path tracing with a view of the first hit of every pixel, black where nothing is hit.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    #[default]
+    None,
+    Uv,
+    /// The normal mapped from [-1, 1] to [0, 1] per channel.
+    Normal,
+    /// White up close, fading to black with the distance.
+    Depth,
+    /// White where nothing is within `Custom3d::ao_radius` of the hit, darker the more of the
+    /// hemisphere around the normal is blocked. Ignores materials and lights.
+    AmbientOcclusion,
+}
+
+impl DebugMode {
+    pub const ALL: [DebugMode; 5] = [
+        DebugMode::None,
+        DebugMode::Uv,
+        DebugMode::Normal,
+        DebugMode::Depth,
+        DebugMode::AmbientOcclusion,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            DebugMode::None => "None",
+            DebugMode::Uv => "Sphere UVs",
+            DebugMode::Normal => "Normals",
+            DebugMode::Depth => "Depth",
+            DebugMode::AmbientOcclusion => "Ambient occlusion",
+        }
+    }
+}
+
+/// What rays see when they escape the scene.
+///
+/// `Transparent` only affects camera rays: they write zero color and zero alpha, so the output is
+/// premultiplied and the accumulated alpha is the pixel coverage. Bounced rays still see the sky so
+/// the scene stays lit. Tone mapping must treat the color as premultiplied in that mode.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum BackgroundMode {
+    #[default]
+    Sky,
+    Color,
+    Transparent,
+    /// The equirectangular image loaded by `Custom3d::load_environment`, black until one is.
+    Environment,
+}
+
+impl BackgroundMode {
+    pub const ALL: [BackgroundMode; 4] = [
+        BackgroundMode::Sky,
+        BackgroundMode::Color,
+        BackgroundMode::Transparent,
+        BackgroundMode::Environment,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            BackgroundMode::Sky => "Gradient sky",
+            BackgroundMode::Color => "Flat color",
+            BackgroundMode::Transparent => "Transparent",
+            BackgroundMode::Environment => "Environment map",
+        }
+    }
+}
+
+/// Where inside a pixel the (unjittered) primary ray goes through.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PixelCenter {
+    /// Pixel `i` is sampled at `i + 0.5`, so an image spans exactly [0, 1].
+    #[default]
+    Center,
+    /// Pixel `i` is sampled at `i`, shifting the whole image by half a pixel.
+    Corner,
+}
+
+impl PixelCenter {
+    pub const ALL: [PixelCenter; 2] = [PixelCenter::Center, PixelCenter::Corner];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PixelCenter::Center => "Center (+0.5)",
+            PixelCenter::Corner => "Corner (+0.0)",
+        }
+    }
+
+    pub fn offset(&self) -> f32 {
+        match self {
+            PixelCenter::Center => 0.5,
+            PixelCenter::Corner => 0.0,
+        }
+    }
+}
+
+/// Size of the render texture. Fixed sizes are scaled by the screen pass to fit the viewport.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RenderResolution {
+    /// One texel per point of the viewport.
+    #[default]
+    Viewport,
+    Square256,
+    Square512,
+    Square800,
+    FullHd,
+    Uhd4k,
+}
+
+impl RenderResolution {
+    pub const ALL: [RenderResolution; 6] = [
+        RenderResolution::Viewport,
+        RenderResolution::Square256,
+        RenderResolution::Square512,
+        RenderResolution::Square800,
+        RenderResolution::FullHd,
+        RenderResolution::Uhd4k,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RenderResolution::Viewport => "Match viewport",
+            RenderResolution::Square256 => "256 x 256",
+            RenderResolution::Square512 => "512 x 512",
+            RenderResolution::Square800 => "800 x 800",
+            RenderResolution::FullHd => "1920 x 1080",
+            RenderResolution::Uhd4k => "3840 x 2160 (4K)",
+        }
+    }
+
+    /// Width and height in pixels, `None` when following the viewport.
+    pub fn size(&self) -> Option<(u32, u32)> {
+        match self {
+            RenderResolution::Viewport => None,
+            RenderResolution::Square256 => Some((256, 256)),
+            RenderResolution::Square512 => Some((512, 512)),
+            RenderResolution::Square800 => Some((800, 800)),
+            RenderResolution::FullHd => Some((1920, 1080)),
+            RenderResolution::Uhd4k => Some((3840, 2160)),
+        }
+    }
+}
+
+/// How the screen pass samples the render when it is stretched to a viewport of another size.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PreviewFilter {
+    /// Shows every texel as a sharp square, so a low resolution render looks blocky.
+    #[default]
+    Nearest,
+    /// Blends neighbouring texels, smoothing a low resolution render at the cost of sharpness.
+    Linear,
+}
+
+impl PreviewFilter {
+    pub const ALL: [PreviewFilter; 2] = [PreviewFilter::Nearest, PreviewFilter::Linear];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PreviewFilter::Nearest => "Nearest",
+            PreviewFilter::Linear => "Linear",
+        }
+    }
+
+    pub fn filter_mode(&self) -> wgpu::FilterMode {
+        match self {
+            PreviewFilter::Nearest => wgpu::FilterMode::Nearest,
+            PreviewFilter::Linear => wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+/// How dragging the viewport and the keyboard move the camera.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CameraMode {
+    /// Dragging rotates the camera around a point in front of it, scrolling moves towards it.
+    #[default]
+    Orbit,
+    /// W/A/S/D move the camera, Q/E lower and raise it, and dragging with the right button
+    /// looks around.
+    Fly,
+}
+
+impl CameraMode {
+    pub const ALL: [CameraMode; 2] = [CameraMode::Orbit, CameraMode::Fly];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            CameraMode::Orbit => "Orbit",
+            CameraMode::Fly => "Fly",
+        }
+    }
+}
+
+/// What the G-buffer textures are allocated for. Without a use they are 1x1 placeholders and the
+/// kernel doesn't write them.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+enum GBufferUse {
+    #[default]
+    None,
+    Export,
+    /// Also exportable.
+    Denoise,
+}
+
+/// What `Custom3d::save` writes, picked by the file dialog filter through the extension.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ImageFormat {
+    /// 8-bit sRGB, exposed and tone mapped like the image on screen.
+    Png,
+    /// The raw linear render, with its full dynamic range.
+    Exr,
+}
+
+impl ImageFormat {
+    /// Anything but an `.exr` path is saved as a PNG.
+    fn from_path(path: &std::path::Path) -> Self {
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("exr") => ImageFormat::Exr,
+            _ => ImageFormat::Png,
+        }
+    }
+}
+
+/// Path of the exposure `stop` of a bracket saved at `path`, like `render_+2ev.png` for
+/// `render.png`. Always a PNG, the EXR already holds every exposure.
+fn bracket_path(path: &std::path::Path, stop: f32) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{stem}_{stop:+}ev.png"))
+}
+
+/// A G-buffer texture saved next to the render by `Custom3d::save`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum GBufferLayer {
+    Albedo,
+    Normal,
+    Depth,
+}
+
+impl GBufferLayer {
+    const ALL: [GBufferLayer; 3] = [
+        GBufferLayer::Albedo,
+        GBufferLayer::Normal,
+        GBufferLayer::Depth,
+    ];
+
+    fn name(&self) -> &'static str {
+        match self {
+            GBufferLayer::Albedo => "albedo",
+            GBufferLayer::Normal => "normal",
+            GBufferLayer::Depth => "depth",
+        }
+    }
+
+    /// Path of the layer next to the render saved at `path`, like `render_depth.exr` for
+    /// `render.exr`.
+    fn path(&self, path: &std::path::Path) -> PathBuf {
+        let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+        let mut file_name = format!("{stem}_{}", self.name());
+        if let Some(extension) = path.extension() {
+            file_name = format!("{file_name}.{}", extension.to_string_lossy());
+        }
+        path.with_file_name(file_name)
+    }
+
+    /// Raw linear values of the layer, from the G-buffer textures. Alpha is 1 where the camera
+    /// ray hit something and 0 where it missed.
+    fn pixel(&self, normal_depth: [f32; 4], albedo: [f32; 4]) -> [f32; 4] {
+        let hit = if normal_depth[3] > 0.0 { 1.0 } else { 0.0 };
+        match self {
+            GBufferLayer::Albedo => [albedo[0], albedo[1], albedo[2], hit],
+            GBufferLayer::Normal => [normal_depth[0], normal_depth[1], normal_depth[2], hit],
+            GBufferLayer::Depth => [normal_depth[3], normal_depth[3], normal_depth[3], hit],
+        }
+    }
+
+    /// Maps raw `pixel`s to [0, 1] for an 8-bit image: albedo is sRGB encoded, normals go from
+    /// [-1, 1] to [0, 1], and depth is divided by the furthest hit.
+    fn to_display(&self, pixels: &mut [[f32; 4]]) {
+        let max_depth = pixels.iter().map(|pixel| pixel[0]).fold(0.0, f32::max);
+        for pixel in pixels {
+            for channel in &mut pixel[..3] {
+                *channel = match self {
+                    GBufferLayer::Albedo => linear_to_srgb(*channel),
+                    GBufferLayer::Normal => *channel * 0.5 + 0.5,
+                    GBufferLayer::Depth if max_depth > 0.0 => *channel / max_depth,
+                    GBufferLayer::Depth => 0.0,
+                };
+            }
+        }
+    }
+}
+
+/// Curve compressing the HDR render into the displayable range.
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ToneMapping {
+    /// Clips everything above 1.
+    None,
+    Reinhard,
+    #[default]
+    Aces,
+}
+
+impl ToneMapping {
+    pub const ALL: [ToneMapping; 3] = [ToneMapping::None, ToneMapping::Reinhard, ToneMapping::Aces];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ToneMapping::None => "None (clamp)",
+            ToneMapping::Reinhard => "Reinhard",
+            ToneMapping::Aces => "ACES filmic",
+        }
+    }
+
+    /// Mirrors `tone_map` in `screen_shader.wgsl`.
+    pub fn apply(&self, color: [f32; 3]) -> [f32; 3] {
+        match self {
+            ToneMapping::None => color.map(|channel| channel.clamp(0.0, 1.0)),
+            ToneMapping::Reinhard => color.map(|channel| channel / (1.0 + channel)),
+            ToneMapping::Aces => color.map(|channel| {
+                let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+                ((channel * (a * channel + b)) / (channel * (c * channel + d) + e)).clamp(0.0, 1.0)
+            }),
+        }
+    }
+}
+
+/// The sRGB transfer function, mirrors `linear_to_srgb` in `screen_shader.wgsl`.
+pub fn linear_to_srgb(channel: f32) -> f32 {
+    if channel <= 0.003_130_8 {
+        channel * 12.92
+    } else {
+        1.055 * channel.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Maps a premultiplied HDR pixel to what the screen pass displays, see `frag_main` in
+/// `screen_shader.wgsl`. The result is sRGB encoded.
+pub fn display_color(pixel: [f32; 4], exposure: f32, tone_mapping: ToneMapping) -> [f32; 4] {
+    let alpha = pixel[3];
+    if alpha <= 0.0 {
+        return pixel;
+    }
+
+    let scale = exposure.exp2() / alpha;
+    let [r, g, b] = tone_mapping
+        .apply([pixel[0] * scale, pixel[1] * scale, pixel[2] * scale])
+        .map(linear_to_srgb);
+    [r * alpha, g * alpha, b * alpha, alpha]
+}
+
+/// Everything needed to pick up where the app left off: the scene and camera, and the render and
+/// display settings. Saved as RON, and by eframe between runs.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub scene: Scene,
+    pub resolution: RenderResolution,
+    #[serde(default)]
+    pub preview_filter: PreviewFilter,
+    pub pixel_center: PixelCenter,
+    pub camera_mode: CameraMode,
+    pub fly_speed: f32,
+    pub background_mode: BackgroundMode,
+    pub background_color: [f32; 3],
+    pub sky_top_color: [f32; 3],
+    pub sky_bottom_color: [f32; 3],
+    pub sun_azimuth: f32,
+    pub sun_elevation: f32,
+    pub sun_color: [f32; 3],
+    pub sun_intensity: f32,
+    pub tone_mapping: ToneMapping,
+    pub exposure: f32,
+    pub accumulate: bool,
+    #[serde(default)]
+    pub reproject_on_resize: bool,
+    pub stable_intersection: bool,
+    pub jitter: bool,
+    pub firefly_clamp: bool,
+    pub max_sample_radiance: f32,
+    #[serde(default)]
+    pub deterministic: bool,
+    #[serde(default)]
+    pub base_seed: u64,
+    pub denoise: bool,
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    pub max_frame_count: u32,
+    /// Kernel edited in the app, `None` uses the built-in one.
+    #[serde(default)]
+    pub kernel_source: Option<String>,
+}
+
+impl Session {
+    pub fn load(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(ron::from_str(&std::fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+        let contents = ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())?;
+        std::fs::write(path, contents)?;
+        Ok(())
+    }
+}
+
+/// Holds back resizes of the render target until the wanted size stops changing, so dragging a
+/// window edge doesn't reallocate every texture and buffer each frame.
+#[derive(Default)]
+struct ResizeDebounce {
+    /// The latest wanted size and when it was first wanted.
+    pending: Option<((u32, u32), Instant)>,
+}
+
+impl ResizeDebounce {
+    /// Whether the render target should be resized to `size` now.
+    fn ready(&mut self, size: (u32, u32), now: Instant) -> bool {
+        match self.pending {
+            Some((pending, since)) if pending == size => {
+                let ready = now.duration_since(since) >= RESIZE_DEBOUNCE;
+                if ready {
+                    self.pending = None;
+                }
+                ready
+            }
+            _ => {
+                self.pending = Some((size, now));
+                false
+            }
+        }
+    }
+}
+
+/// What the settings the UI exposes start at. `Custom3d::new` and `reset_to_defaults` both
+/// destructure it, so a setting added here can't be missed by either.
+struct Defaults {
+    drag_sensitivity: f32,
+    orbit_distance: f32,
+    camera_mode: CameraMode,
+    fly_speed: f32,
+    debug_mode: DebugMode,
+    preview_filter: PreviewFilter,
+    ao_radius: f32,
+    ao_samples: u32,
+    preview_time: f32,
+    animate: bool,
+    shutter: f32,
+    resolution: RenderResolution,
+    pixel_center: PixelCenter,
+    background_mode: BackgroundMode,
+    background_color: [f32; 3],
+    sky_top_color: [f32; 3],
+    sky_bottom_color: [f32; 3],
+    sun_azimuth: f32,
+    sun_elevation: f32,
+    sun_color: [f32; 3],
+    sun_intensity: f32,
+    tone_mapping: ToneMapping,
+    exposure: f32,
+    paused: bool,
+    accumulate: bool,
+    reproject_on_resize: bool,
+    stable_intersection: bool,
+    jitter: bool,
+    firefly_clamp: bool,
+    max_sample_radiance: f32,
+    deterministic: bool,
+    base_seed: u64,
+    denoise: bool,
+    export_gbuffer: bool,
+    samples_per_pixel: u32,
+    max_bounces: u32,
+    max_frame_count: u32,
+    stop_when_converged: bool,
+    convergence_threshold: f32,
+}
+
+impl Default for Defaults {
+    fn default() -> Self {
+        Self {
+            drag_sensitivity: 0.005,
+            orbit_distance: DEFAULT_ORBIT_DISTANCE,
+            camera_mode: Default::default(),
+            fly_speed: DEFAULT_FLY_SPEED,
+            debug_mode: Default::default(),
+            preview_filter: Default::default(),
+            ao_radius: DEFAULT_AO_RADIUS,
+            ao_samples: DEFAULT_AO_SAMPLES,
+            preview_time: 0.0,
+            animate: false,
+            shutter: DEFAULT_SHUTTER,
+            resolution: Default::default(),
+            pixel_center: Default::default(),
+            background_mode: Default::default(),
+            background_color: [0.5, 0.5, 0.5],
+            sky_top_color: to_rgb(DEFAULT_SKY_TOP_COLOR),
+            sky_bottom_color: to_rgb(DEFAULT_SKY_BOTTOM_COLOR),
+            sun_azimuth: DEFAULT_SUN_AZIMUTH,
+            sun_elevation: DEFAULT_SUN_ELEVATION,
+            sun_color: [1.0, 1.0, 1.0],
+            sun_intensity: 0.0,
+            tone_mapping: Default::default(),
+            exposure: 0.0,
+            paused: false,
+            accumulate: true,
+            reproject_on_resize: false,
+            stable_intersection: true,
+            jitter: true,
+            firefly_clamp: false,
+            max_sample_radiance: DEFAULT_MAX_SAMPLE_RADIANCE,
+            deterministic: false,
+            base_seed: 0,
+            denoise: false,
+            export_gbuffer: false,
+            samples_per_pixel: DEFAULT_SAMPLES_PER_PIXEL,
+            max_bounces: DEFAULT_MAX_BOUNCES,
+            max_frame_count: DEFAULT_MAX_FRAME_COUNT,
+            stop_when_converged: true,
+            convergence_threshold: DEFAULT_CONVERGENCE_THRESHOLD,
+        }
+    }
 }
 
 pub struct Custom3d {
-    scene_start: std::time::Instant,
     texture_width: u32,
     texture_height: u32,
+    /// The previous render is stretched over the viewport until a resize goes through.
+    resize_debounce: ResizeDebounce,
     device: Arc<wgpu::Device>,
     queue: Arc<wgpu::Queue>,
     random_gen: rand::rngs::ThreadRng,
     scene_info: SceneInfo,
-    tx: Sender<Message>,
+    scene: Scene,
+    /// Set when the triangle buffer doesn't hold the scene's triangles. Meshes can be large, so
+    /// unlike spheres and planes they aren't uploaded every frame.
+    triangles_outdated: bool,
+    /// Frames `render_accumulated` pauses after, until it is reached.
+    frame_limit: Option<u32>,
+    viewport_rect: egui::Rect,
+    /// Radians the camera orbits per point dragged.
+    pub drag_sensitivity: f32,
+    /// Distance in front of the camera of the point it orbits around.
+    orbit_distance: f32,
+    pub camera_mode: CameraMode,
+    /// Units per second the camera moves in fly mode.
+    pub fly_speed: f32,
+    pub debug_mode: DebugMode,
+    /// Applied to the screen pass by `ensure_preview_filter`.
+    pub preview_filter: PreviewFilter,
+    /// What the screen pass samples with.
+    screen_filter: PreviewFilter,
+    /// Distance within which geometry occludes in `DebugMode::AmbientOcclusion`.
+    pub ao_radius: f32,
+    /// Occlusion rays per sample in `DebugMode::AmbientOcclusion`.
+    pub ao_samples: u32,
+    /// Time the preview shows the scene at, which moves spheres along their velocity.
+    pub preview_time: f32,
+    /// Plays the scene in real time, `preview_time` following the time since `scene_start`.
+    pub animate: bool,
+    /// When `animate` counts the time from.
+    scene_start: Instant,
+    /// How long the shutter stays open from `preview_time`, over which spheres blur towards where
+    /// their velocity takes them by its end.
+    pub shutter: f32,
+    pub resolution: RenderResolution,
+    pub pixel_center: PixelCenter,
+    pub background_mode: BackgroundMode,
+    pub background_color: [f32; 3],
+    pub sky_top_color: [f32; 3],
+    pub sky_bottom_color: [f32; 3],
+    /// Compass angle of the sun around +z, in degrees.
+    pub sun_azimuth: f32,
+    /// Angle of the sun above the horizon, in degrees.
+    pub sun_elevation: f32,
+    pub sun_color: [f32; 3],
+    /// 0 turns the sun off.
+    pub sun_intensity: f32,
+    pub tone_mapping: ToneMapping,
+    /// In stops. Applied on display, so changing it doesn't restart accumulation.
+    pub exposure: f32,
+    /// Stop rendering new accumulation frames, leaving the last one on screen. `step` still
+    /// renders a single frame.
+    pub paused: bool,
+    /// Blend frames into the progressive buffer. Off writes each frame's samples directly.
+    pub accumulate: bool,
+    /// Resample the accumulation into the new size on resizes, rather than starting over, as long
+    /// as the aspect ratio stays close.
+    pub reproject_on_resize: bool,
+    /// Solve ray-sphere hits without catastrophic cancellation. Off uses the textbook formula.
+    pub stable_intersection: bool,
+    /// Randomly offset samples within their pixel. Off traces every sample through the pixel
+    /// center, which is aliased but handy to compare against.
+    pub jitter: bool,
+    /// Clamp every sample to `max_sample_radiance`, trading fireflies for a slightly darker, biased
+    /// image. Off by default to conserve energy.
+    pub firefly_clamp: bool,
+    pub max_sample_radiance: f32,
+    /// Derive the random seed of every frame from `base_seed` and the frame count, so the same
+    /// scene renders identically every run. Off draws a new random seed every frame.
+    pub deterministic: bool,
+    pub base_seed: u64,
+    /// Filter the displayed and saved image with the À-Trous denoiser. Off skips the filter and
+    /// the G-buffer it needs.
+    pub denoise: bool,
+    /// Also save the first hit albedo, normal and depth as separate images next to the render.
+    /// The kernel only writes them while this or `denoise` is on.
+    pub export_gbuffer: bool,
+    /// What the GPU resources were created for, `denoise` and `export_gbuffer` are applied by
+    /// `ensure_gbuffer`.
+    gbuffer_use: GBufferUse,
+    /// Set when the G-buffer was just created, so a frame must be traced to fill it even while
+    /// paused.
+    gbuffer_outdated: bool,
+    /// Samples traced per pixel every frame.
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    /// Accumulation stops once this many frames are averaged, as later ones would barely change
+    /// the image.
+    pub max_frame_count: u32,
+    /// Stop accumulating once a frame changes the image by less than `convergence_threshold`.
+    pub stop_when_converged: bool,
+    pub convergence_threshold: f32,
+    /// The last frame change read back from the GPU.
+    frame_change: Option<FrameChange>,
+    step_requested: bool,
+    /// Set by a resize that kept the accumulation, whose new textures are blank until a frame is
+    /// traced.
+    reprojected: bool,
+    /// Pixels the kernel is limited to, `None` renders the whole frame.
+    render_region: Option<Tile>,
+    /// Viewport positions where the render region being drawn starts and ends.
+    region_drag: Option<(egui::Pos2, egui::Pos2)>,
+    rx: Receiver<Message>,
+    stats: Option<RenderStats>,
+    gpu_time: Option<Duration>,
     renderer: Arc<egui::mutex::RwLock<egui_wgpu::Renderer>>,
+    /// Format of the textures egui renders to, which depends on the platform.
+    target_format: wgpu::TextureFormat,
+    /// Set by the device's error handler, the GPU resources are rebuilt on the next frame.
+    gpu_errors: Arc<AtomicBool>,
+    /// When the GPU resources were last rebuilt after an error.
+    recovering_since: Option<Instant>,
+    adapter_info: wgpu::AdapterInfo,
+    /// Source of the kernel the GPU resources are built with, the last one that compiled.
+    kernel_source: String,
+    /// Workgroup size the kernel is compiled with, only changed to compare their speed.
+    workgroup_size: WorkgroupSize,
+    /// Why the kernel failed to compile. The last kernel that compiled keeps tracing, nothing is
+    /// traced if none did.
+    shader_error: Option<ShaderError>,
+    #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+    shader_watcher: Option<crate::hot_reload::ShaderWatcher>,
 }
 
 impl Custom3d {
@@ -82,19 +931,90 @@ impl Custom3d {
         let render_state = cc.wgpu_render_state.as_ref()?;
         let device = &render_state.device;
         let queue = render_state.queue.clone();
+        let adapter_info = render_state.adapter.get_info();
+        re_log::info!(
+            "Rendering on {} ({:?}, {:?})",
+            adapter_info.name,
+            adapter_info.device_type,
+            adapter_info.backend
+        );
 
         let texture_width = 800;
         let texture_height = 800;
-
-        let raytracing_resources =
-            Self::create_raytracing_pipeline(device, texture_width, texture_height);
-        let triangle_resources =
-            Self::create_screen_pipeline(device, &raytracing_resources.storage_texture_view);
+        let kernel_source = raytracer::KERNEL_SOURCE.to_owned();
+        let Defaults {
+            drag_sensitivity,
+            orbit_distance,
+            camera_mode,
+            fly_speed,
+            debug_mode,
+            preview_filter,
+            ao_radius,
+            ao_samples,
+            preview_time,
+            animate,
+            shutter,
+            resolution,
+            pixel_center,
+            background_mode,
+            background_color,
+            sky_top_color,
+            sky_bottom_color,
+            sun_azimuth,
+            sun_elevation,
+            sun_color,
+            sun_intensity,
+            tone_mapping,
+            exposure,
+            paused,
+            accumulate,
+            reproject_on_resize,
+            stable_intersection,
+            jitter,
+            firefly_clamp,
+            max_sample_radiance,
+            deterministic,
+            base_seed,
+            denoise,
+            export_gbuffer,
+            samples_per_pixel,
+            max_bounces,
+            max_frame_count,
+            stop_when_converged,
+            convergence_threshold,
+        } = Defaults::default();
+
+        let raytracing_resources = Self::create_raytracing_pipeline(
+            device,
+            texture_width,
+            texture_height,
+            SceneBuffers::new(device, INITIAL_CAPACITY),
+            GBufferUse::None,
+            &kernel_source,
+            WorkgroupSize::default(),
+        );
+        let triangle_resources = Self::create_screen_pipeline(
+            device,
+            raytracing_resources.display_view(),
+            render_state.target_format,
+            PreviewFilter::default(),
+        );
+        let shader_error = raytracing_resources.shader_error();
         let (tx, rx) = unbounded();
+
+        // The default handler panics, but a reset GPU shouldn't take the app down with it
+        let gpu_errors = Arc::new(AtomicBool::new(false));
+        device.on_uncaptured_error({
+            let gpu_errors = gpu_errors.clone();
+            move |error| {
+                re_log::error!("GPU error: {error}");
+                gpu_errors.store(true, Ordering::Relaxed);
+            }
+        });
         let resources = Resources {
             raytracing_resources,
             screen_resources: triangle_resources,
-            rx,
+            tx,
         };
 
         // Because the graphics pipeline must have the same lifetime as the egui render pass,
@@ -107,195 +1027,1137 @@ impl Custom3d {
             .insert(resources);
 
         Some(Self {
-            scene_start: std::time::Instant::now(),
             texture_width,
             texture_height,
+            resize_debounce: Default::default(),
             device: device.clone(),
             queue,
             scene_info: Default::default(),
+            scene: default_scene(),
+            triangles_outdated: true,
+            frame_limit: None,
+            viewport_rect: egui::Rect::NOTHING,
+            drag_sensitivity,
+            orbit_distance,
+            camera_mode,
+            fly_speed,
+            debug_mode,
+            preview_filter,
+            screen_filter: Default::default(),
+            ao_radius,
+            ao_samples,
+            preview_time,
+            animate,
+            scene_start: Instant::now(),
+            shutter,
+            resolution,
+            pixel_center,
+            background_mode,
+            background_color,
+            sky_top_color,
+            sky_bottom_color,
+            sun_azimuth,
+            sun_elevation,
+            sun_color,
+            sun_intensity,
+            tone_mapping,
+            exposure,
+            paused,
+            accumulate,
+            reproject_on_resize,
+            stable_intersection,
+            jitter,
+            firefly_clamp,
+            max_sample_radiance,
+            deterministic,
+            base_seed,
+            denoise,
+            export_gbuffer,
+            gbuffer_use: GBufferUse::None,
+            gbuffer_outdated: false,
+            samples_per_pixel,
+            max_bounces,
+            max_frame_count,
+            stop_when_converged,
+            convergence_threshold,
+            frame_change: None,
+            step_requested: false,
+            reprojected: false,
+            render_region: None,
+            region_drag: None,
             random_gen: rand::thread_rng(),
-            tx,
+            rx,
+            stats: None,
+            gpu_time: None,
             renderer: render_state.renderer.clone(),
+            target_format: render_state.target_format,
+            gpu_errors,
+            recovering_since: None,
+            adapter_info,
+            kernel_source,
+            workgroup_size: WorkgroupSize::default(),
+            shader_error,
+            #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+            shader_watcher: crate::hot_reload::ShaderWatcher::new(
+                raytracer::KERNEL_PATH.as_ref(),
+                cc.egui_ctx.clone(),
+            )
+            .map_err(|err| re_log::error!("Failed to watch {}: {err}", raytracer::KERNEL_PATH))
+            .ok(),
         })
     }
 
-    pub fn rebuild_pipeline(
+    pub fn reset_accumulation(&mut self) {
+        self.scene_info.frame_count = 0;
+    }
+
+    /// Restores the default scene and render settings, keeping the GPU resources.
+    pub fn reset_to_defaults(&mut self) {
+        let Defaults {
+            drag_sensitivity,
+            orbit_distance,
+            camera_mode,
+            fly_speed,
+            debug_mode,
+            preview_filter,
+            ao_radius,
+            ao_samples,
+            preview_time,
+            animate,
+            shutter,
+            resolution,
+            pixel_center,
+            background_mode,
+            background_color,
+            sky_top_color,
+            sky_bottom_color,
+            sun_azimuth,
+            sun_elevation,
+            sun_color,
+            sun_intensity,
+            tone_mapping,
+            exposure,
+            paused,
+            accumulate,
+            reproject_on_resize,
+            stable_intersection,
+            jitter,
+            firefly_clamp,
+            max_sample_radiance,
+            deterministic,
+            base_seed,
+            denoise,
+            export_gbuffer,
+            samples_per_pixel,
+            max_bounces,
+            max_frame_count,
+            stop_when_converged,
+            convergence_threshold,
+        } = Defaults::default();
+        self.scene = default_scene();
+        self.triangles_outdated = true;
+        self.drag_sensitivity = drag_sensitivity;
+        self.orbit_distance = orbit_distance;
+        self.camera_mode = camera_mode;
+        self.fly_speed = fly_speed;
+        self.frame_limit = None;
+        self.debug_mode = debug_mode;
+        self.preview_filter = preview_filter;
+        self.ao_radius = ao_radius;
+        self.ao_samples = ao_samples;
+        self.preview_time = preview_time;
+        self.animate = animate;
+        self.scene_start = Instant::now();
+        self.shutter = shutter;
+        self.render_region = None;
+        self.resolution = resolution;
+        self.pixel_center = pixel_center;
+        self.background_mode = background_mode;
+        self.background_color = background_color;
+        self.sky_top_color = sky_top_color;
+        self.sky_bottom_color = sky_bottom_color;
+        self.sun_azimuth = sun_azimuth;
+        self.sun_elevation = sun_elevation;
+        self.sun_color = sun_color;
+        self.sun_intensity = sun_intensity;
+        self.tone_mapping = tone_mapping;
+        self.exposure = exposure;
+        self.paused = paused;
+        self.step_requested = false;
+        self.accumulate = accumulate;
+        self.reproject_on_resize = reproject_on_resize;
+        self.stable_intersection = stable_intersection;
+        self.jitter = jitter;
+        self.firefly_clamp = firefly_clamp;
+        self.max_sample_radiance = max_sample_radiance;
+        self.deterministic = deterministic;
+        self.base_seed = base_seed;
+        self.denoise = denoise;
+        self.export_gbuffer = export_gbuffer;
+        self.samples_per_pixel = samples_per_pixel;
+        self.max_bounces = max_bounces;
+        self.max_frame_count = max_frame_count;
+        self.stop_when_converged = stop_when_converged;
+        self.convergence_threshold = convergence_threshold;
+        if self.kernel_source != raytracer::KERNEL_SOURCE
+            || self.workgroup_size != WorkgroupSize::default()
+        {
+            self.compile_kernel(
+                raytracer::KERNEL_SOURCE.to_owned(),
+                WorkgroupSize::default(),
+            );
+        }
+        self.reset_accumulation();
+    }
+
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Replaces the whole scene, growing the GPU buffers if needed on the next frame.
+    pub fn set_scene(&mut self, scene: Scene) {
+        if let Some(settings) = scene.settings {
+            self.samples_per_pixel = settings.samples_per_pixel;
+            self.max_bounces = settings.max_bounces;
+        }
+        self.scene = scene;
+        self.triangles_outdated = true;
+        self.orbit_distance = DEFAULT_ORBIT_DISTANCE;
+        self.frame_limit = None;
+        self.reset_accumulation();
+    }
+
+    /// Loads a `.ron` or `.json` scene file and makes it the current scene.
+    pub fn load_scene(
         &mut self,
-        width: u32,
-        height: u32,
-        render_state: &egui_wgpu::RenderState,
-    ) {
-        let raytracing_resources = Self::create_raytracing_pipeline(&self.device, width, height);
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let scene = Scene::load(path.as_ref())?;
+        self.set_scene(scene);
+        Ok(())
+    }
+
+    /// The scene and settings to restore with `restore_session`.
+    pub fn session(&self) -> Session {
+        Session {
+            scene: self.scene.clone(),
+            resolution: self.resolution,
+            preview_filter: self.preview_filter,
+            pixel_center: self.pixel_center,
+            camera_mode: self.camera_mode,
+            fly_speed: self.fly_speed,
+            background_mode: self.background_mode,
+            background_color: self.background_color,
+            sky_top_color: self.sky_top_color,
+            sky_bottom_color: self.sky_bottom_color,
+            sun_azimuth: self.sun_azimuth,
+            sun_elevation: self.sun_elevation,
+            sun_color: self.sun_color,
+            sun_intensity: self.sun_intensity,
+            tone_mapping: self.tone_mapping,
+            exposure: self.exposure,
+            accumulate: self.accumulate,
+            reproject_on_resize: self.reproject_on_resize,
+            stable_intersection: self.stable_intersection,
+            jitter: self.jitter,
+            firefly_clamp: self.firefly_clamp,
+            max_sample_radiance: self.max_sample_radiance,
+            deterministic: self.deterministic,
+            base_seed: self.base_seed,
+            denoise: self.denoise,
+            samples_per_pixel: self.samples_per_pixel,
+            max_bounces: self.max_bounces,
+            max_frame_count: self.max_frame_count,
+            kernel_source: (self.kernel_source != raytracer::KERNEL_SOURCE)
+                .then(|| self.kernel_source.clone()),
+        }
+    }
 
-        let triangle_resources =
-            Self::create_screen_pipeline(&self.device, &raytracing_resources.storage_texture_view);
+    /// Replaces the scene and settings with a saved session's. The render target and G-buffer
+    /// follow on the next frame.
+    pub fn restore_session(&mut self, session: Session) {
+        let Session {
+            scene,
+            resolution,
+            preview_filter,
+            pixel_center,
+            camera_mode,
+            fly_speed,
+            background_mode,
+            background_color,
+            sky_top_color,
+            sky_bottom_color,
+            sun_azimuth,
+            sun_elevation,
+            sun_color,
+            sun_intensity,
+            tone_mapping,
+            exposure,
+            accumulate,
+            reproject_on_resize,
+            stable_intersection,
+            jitter,
+            firefly_clamp,
+            max_sample_radiance,
+            deterministic,
+            base_seed,
+            denoise,
+            samples_per_pixel,
+            max_bounces,
+            max_frame_count,
+            kernel_source,
+        } = session;
+        // Settings saved with the scene would override the session's
+        self.set_scene(Scene {
+            settings: None,
+            ..scene
+        });
+        self.resolution = resolution;
+        self.preview_filter = preview_filter;
+        self.pixel_center = pixel_center;
+        self.camera_mode = camera_mode;
+        self.fly_speed = fly_speed;
+        self.background_mode = background_mode;
+        self.background_color = background_color;
+        self.sky_top_color = sky_top_color;
+        self.sky_bottom_color = sky_bottom_color;
+        self.sun_azimuth = sun_azimuth;
+        self.sun_elevation = sun_elevation;
+        self.sun_color = sun_color;
+        self.sun_intensity = sun_intensity;
+        self.tone_mapping = tone_mapping;
+        self.exposure = exposure;
+        self.accumulate = accumulate;
+        self.reproject_on_resize = reproject_on_resize;
+        self.stable_intersection = stable_intersection;
+        self.jitter = jitter;
+        self.firefly_clamp = firefly_clamp;
+        self.max_sample_radiance = max_sample_radiance;
+        self.deterministic = deterministic;
+        self.base_seed = base_seed;
+        self.denoise = denoise;
+        self.samples_per_pixel = samples_per_pixel;
+        self.max_bounces = max_bounces;
+        self.max_frame_count = max_frame_count;
+        // A kernel that no longer compiles is reported, the built-in one keeps tracing
+        if let Some(source) = kernel_source {
+            self.reload_kernel(source);
+        }
+    }
 
-        let old_resources = render_state
-            .renderer
-            .write()
-            .paint_callback_resources
-            .remove::<Resources>()
-            .unwrap();
+    /// Adds the triangles of an `.obj` file to the scene, centered on the point the camera
+    /// orbits around.
+    pub fn load_mesh(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut triangles = renderer::obj::load(path.as_ref(), Material::default())?;
+        center_mesh(&mut triangles, self.orbit_target());
+        self.scene.triangles.extend(triangles);
+        self.triangles_outdated = true;
+        self.reset_accumulation();
+        Ok(())
+    }
 
-        let Resources { rx, .. } = old_resources;
+    /// Loads an equirectangular `.hdr` or `.exr` image and shows it as the background, which
+    /// also lights the scene.
+    pub fn load_environment(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let environment = Environment::load(path.as_ref())?;
+        let environment_map = EnvironmentMap::new(&self.device, &self.queue, &environment)?;
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        resources
+            .raytracing_resources
+            .set_environment(&self.device, environment_map);
+        drop(renderer);
+        self.background_mode = BackgroundMode::Environment;
+        self.reset_accumulation();
+        Ok(())
+    }
 
-        let resources = Resources {
-            raytracing_resources,
-            screen_resources: triangle_resources,
-            rx,
+    pub fn spheres(&self) -> &[Sphere] {
+        &self.scene.spheres
+    }
+
+    /// Replaces a sphere of the scene. The sphere buffer is updated in place on the next frame.
+    /// Returns false, changing nothing, when there is no sphere `index`.
+    pub fn set_sphere(&mut self, index: usize, sphere: Sphere) -> bool {
+        let Some(slot) = self.scene.spheres.get_mut(index) else {
+            return false;
         };
+        *slot = sphere;
+        self.reset_accumulation();
+        true
+    }
 
-        render_state
-            .renderer
-            .write()
-            .paint_callback_resources
-            .insert(resources);
+    /// Appends a sphere to the scene and returns its index.
+    pub fn add_sphere(&mut self, sphere: Sphere) -> usize {
+        self.scene.spheres.push(sphere);
+        self.reset_accumulation();
+        self.scene.spheres.len() - 1
+    }
 
-        self.texture_width = width;
-        self.texture_height = height;
+    /// Removes a sphere, and its index from the groups.
+    pub fn remove_sphere(&mut self, index: usize) -> Sphere {
+        let sphere = self.scene.remove_sphere(index);
+        self.reset_accumulation();
+        sphere
     }
 
-    fn create_raytracing_pipeline(
-        device: &wgpu::Device,
-        texture_width: u32,
-        texture_height: u32,
-    ) -> RaytracingRenderResources {
-        let scene_info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&[SceneInfo::default()]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
+    pub fn groups(&self) -> &[Group] {
+        &self.scene.groups
+    }
 
-        let sphere_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: std::mem::size_of::<Sphere>() as u64 * 16,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
+    /// Appends a group and returns its index. Nothing moves until it is transformed.
+    pub fn add_group(&mut self, group: Group) -> usize {
+        self.scene.groups.push(group);
+        self.scene.groups.len() - 1
+    }
 
-        let storage_texture_descriptor =
-            Self::get_storage_texture_descriptor_from_size(texture_width, texture_height);
-        let storage_texture = device.create_texture(&storage_texture_descriptor);
-        let storage_texture_view =
-            storage_texture.create_view(&wgpu::TextureViewDescriptor::default());
+    /// Makes `sphere_indices` the members of group `index`, without moving anything. Like
+    /// `set_sphere`, returns false when there is no group `index`.
+    pub fn set_group_spheres(&mut self, index: usize, sphere_indices: Vec<usize>) -> bool {
+        let Some(group) = self.scene.groups.get_mut(index) else {
+            return false;
+        };
+        group.sphere_indices = sphere_indices;
+        true
+    }
 
-        let progressive_rendering_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (get_padded_bytes_per_row_from_width(texture_width) * texture_height) as u64,
-            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
+    /// Removes a group, leaving its spheres where they are. `None` when there is no group `index`.
+    pub fn remove_group(&mut self, index: usize) -> Option<Group> {
+        (index < self.scene.groups.len()).then(|| self.scene.groups.remove(index))
+    }
 
-        let export_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-            label: None,
-            size: (get_padded_bytes_per_row_from_width(texture_width) * texture_height) as u64,
-            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
-            mapped_at_creation: false,
-        });
+    /// Moves the spheres of group `index` by `transform`. Returns false when there is no group
+    /// `index`.
+    pub fn transform_group(&mut self, index: usize, transform: Mat4) -> bool {
+        if !self.scene.transform_group(index, transform) {
+            return false;
+        }
+        self.reset_accumulation();
+        true
+    }
 
-        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[
-                wgpu::BindGroupLayoutEntry {
-                    binding: 0,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::StorageTexture {
-                        access: wgpu::StorageTextureAccess::WriteOnly,
-                        format: wgpu::TextureFormat::Rgba16Float,
-                        view_dimension: wgpu::TextureViewDimension::D2,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 1,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Uniform,
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 2,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false },
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-                wgpu::BindGroupLayoutEntry {
-                    binding: 3,
-                    visibility: wgpu::ShaderStages::COMPUTE,
-                    ty: wgpu::BindingType::Buffer {
-                        ty: wgpu::BufferBindingType::Storage { read_only: false }, // True?
-                        has_dynamic_offset: false,
-                        min_binding_size: None,
-                    },
-                    count: None,
-                },
-            ],
-            label: None,
-        });
+    pub fn lights(&self) -> &[PointLight] {
+        &self.scene.lights
+    }
 
-        let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &bind_group_layout,
-            label: None,
-            entries: &[
-                wgpu::BindGroupEntry {
-                    binding: 0,
-                    resource: wgpu::BindingResource::TextureView(&storage_texture_view),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 1,
-                    resource: scene_info_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 2,
-                    resource: sphere_buffer.as_entire_binding(),
-                },
-                wgpu::BindGroupEntry {
-                    binding: 3,
-                    resource: progressive_rendering_buffer.as_entire_binding(),
-                },
-            ],
-        });
+    /// Like `set_sphere`, returns false when there is no light `index`.
+    pub fn set_light(&mut self, index: usize, light: PointLight) -> bool {
+        let Some(slot) = self.scene.lights.get_mut(index) else {
+            return false;
+        };
+        *slot = light;
+        self.reset_accumulation();
+        true
+    }
 
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            bind_group_layouts: &[&bind_group_layout],
-            ..Default::default()
-        });
+    /// Appends a light to the scene and returns its index.
+    pub fn add_light(&mut self, light: PointLight) -> usize {
+        self.scene.lights.push(light);
+        self.reset_accumulation();
+        self.scene.lights.len() - 1
+    }
+
+    pub fn remove_light(&mut self, index: usize) -> PointLight {
+        let light = self.scene.lights.remove(index);
+        self.reset_accumulation();
+        light
+    }
+
+    pub fn camera(&self) -> Camera {
+        self.scene.camera
+    }
+
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.scene.camera = camera;
+        self.reset_accumulation();
+    }
+
+    /// The point `orbit_distance` in front of the camera.
+    fn orbit_target(&self) -> Vec3 {
+        let camera = &self.scene.camera;
+        camera.position + camera.forward() * self.orbit_distance
+    }
+
+    /// Rotates the camera around the point `orbit_distance` in front of it.
+    pub fn orbit(&mut self, drag_delta: egui::Vec2) {
+        let mut camera = self.scene.camera;
+        let distance = self.orbit_distance;
+        let focus = self.orbit_target();
+
+        let max_pitch = 89f32.to_radians();
+        let yaw = camera.yaw() - drag_delta.x * self.drag_sensitivity;
+        let pitch =
+            (camera.pitch() + drag_delta.y * self.drag_sensitivity).clamp(-max_pitch, max_pitch);
+        camera.set_orientation(yaw, pitch);
+
+        camera.position = focus - camera.forward() * distance;
+        self.set_camera(camera);
+    }
+
+    /// Turns the camera in place, like looking around with a mouse.
+    pub fn look(&mut self, drag_delta: egui::Vec2) {
+        let mut camera = self.scene.camera;
+        let max_pitch = 89f32.to_radians();
+        let yaw = camera.yaw() - drag_delta.x * self.drag_sensitivity;
+        let pitch =
+            (camera.pitch() - drag_delta.y * self.drag_sensitivity).clamp(-max_pitch, max_pitch);
+        camera.set_orientation(yaw, pitch);
+        self.set_camera(camera);
+    }
+
+    /// Moves the camera by `fly_speed * delta_time` along `direction`, given as amounts along its
+    /// right, forward and up vectors.
+    pub fn fly(&mut self, direction: [f32; 3], delta_time: f32) {
+        let mut camera = self.scene.camera;
+        camera.position = fly_position(&camera, direction, self.fly_speed * delta_time);
+        self.set_camera(camera);
+    }
+
+    /// Moves the camera towards its orbit point, never going past it.
+    pub fn dolly(&mut self, amount: f32) {
+        let amount = amount.min(self.orbit_distance - 0.1);
+        let mut camera = self.scene.camera;
+        camera.position = camera.position + camera.forward() * amount;
+        self.orbit_distance -= amount;
+        self.set_camera(camera);
+    }
+
+    /// Restarts accumulation and pauses once `frames` frames have been accumulated.
+    pub fn render_accumulated(&mut self, frames: u32) {
+        self.frame_limit = Some(frames);
+        self.paused = false;
+        self.reset_accumulation();
+    }
+
+    pub fn frame_count(&self) -> u32 {
+        self.scene_info.frame_count
+    }
+
+    /// Whether accumulation reached `max_frame_count`, or `convergence_threshold` when stopping
+    /// there, and no more frames are dispatched.
+    pub fn converged(&self) -> bool {
+        self.accumulate
+            && (self.scene_info.frame_count >= self.max_frame_count
+                || (self.stop_when_converged && self.convergence() == Some(1.0)))
+    }
+
+    /// Mean change of a channel of a pixel in the last measured frame of the current
+    /// accumulation. Measurements are read back a few frames late and skip some frames.
+    pub fn frame_change(&self) -> Option<f32> {
+        // A measurement from before the accumulation was reset is of a later frame
+        self.frame_change
+            .filter(|change| self.accumulate && change.frame_count <= self.scene_info.frame_count)
+            .and_then(|change| change.mean())
+    }
+
+    /// How close `frame_change` is to `convergence_threshold`, from 0 to 1 once below it.
+    pub fn convergence(&self) -> Option<f32> {
+        self.frame_change()
+            .map(|change| convergence(change, self.convergence_threshold))
+    }
+
+    /// Handles the messages sent by the paint callbacks since the last call.
+    pub fn receive_messages(&mut self) {
+        for message in self.rx.try_iter() {
+            match message {
+                Message::FramePrepared {
+                    frame_count,
+                    prepare_time,
+                } => {
+                    self.stats = Some(RenderStats {
+                        frame_count,
+                        prepare_time,
+                    });
+                }
+                Message::ComputePassTimed { gpu_time } => self.gpu_time = Some(gpu_time),
+                Message::FrameChangeMeasured { frame_change } => {
+                    self.frame_change = Some(frame_change)
+                }
+            }
+        }
+    }
+
+    /// Whether the device can time the compute pass, see `gpu_time`.
+    pub fn gpu_timing_supported(&self) -> bool {
+        self.device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+    }
+
+    /// GPU time of the last timed compute pass.
+    pub fn gpu_time(&self) -> Option<Duration> {
+        self.gpu_time
+    }
+
+    /// Stats of the last traced frame, if any was traced yet.
+    pub fn stats(&self) -> Option<RenderStats> {
+        self.stats
+    }
+
+    /// Width and height of the render texture in pixels.
+    pub fn texture_size(&self) -> (u32, u32) {
+        (self.texture_width, self.texture_height)
+    }
+
+    /// Renders exactly one more accumulation frame while in step mode.
+    pub fn step(&mut self) {
+        self.step_requested = true;
+    }
+
+    pub fn viewport_rect(&self) -> egui::Rect {
+        self.viewport_rect
+    }
+
+    /// Index of the sphere under a pointer position, where the spheres are at the time the
+    /// preview shows.
+    pub fn pick_sphere(&self, pos: egui::Pos2) -> Option<usize> {
+        pick_sphere(&self.scene, self.viewport_ray(pos)?, self.scene_info.time)
+    }
+
+    /// Where `point` shows in the viewport, for drawing gizmos. `None` when it is behind the
+    /// camera, it may be outside the viewport otherwise.
+    pub fn project(&self, point: Vec3) -> Option<egui::Pos2> {
+        let aspect = self.texture_width as f32 / self.texture_height as f32;
+        let screen = project(&self.scene.camera, point, aspect)?;
+        let rect = self.viewport_rect;
+        Some(rect.min + egui::vec2(screen.x * rect.width(), screen.y * rect.height()))
+    }
+
+    /// Primary ray under a pointer position, for picking and gizmos.
+    pub fn viewport_ray(&self, pos: egui::Pos2) -> Option<Ray> {
+        let screen = viewport_to_screen(self.viewport_rect, pos)?;
+        let aspect = self.texture_width as f32 / self.texture_height as f32;
+        Some(primary_ray(&self.scene.camera, screen, aspect))
+    }
+
+    pub fn rebuild_pipeline(
+        &mut self,
+        width: u32,
+        height: u32,
+        render_state: &egui_wgpu::RenderState,
+    ) {
+        // wgpu can't create empty textures, like those of a minimized window. The last resources
+        // are kept until there is something to render again
+        if width == 0 || height == 0 {
+            re_log::debug!("Not resizing the render to {width}x{height}");
+            return;
+        }
+        let Resources {
+            raytracing_resources: old_resources,
+            tx,
+            ..
+        } = render_state
+            .renderer
+            .write()
+            .paint_callback_resources
+            .remove::<Resources>()
+            .unwrap();
+        let old_bytes = old_resources.render_sized_bytes();
+        old_resources.destroy_render_sized();
+        // The scene doesn't depend on the size, its buffers are kept as they are
+        let RaytracingRenderResources {
+            environment,
+            scene_buffers,
+            ..
+        } = old_resources;
+
+        let mut raytracing_resources = Self::create_raytracing_pipeline(
+            &self.device,
+            width,
+            height,
+            scene_buffers,
+            self.wanted_gbuffer_use(),
+            &self.kernel_source,
+            self.workgroup_size,
+        );
+        raytracing_resources.set_environment(&self.device, environment);
+
+        let triangle_resources = Self::create_screen_pipeline(
+            &self.device,
+            raytracing_resources.display_view(),
+            self.target_format,
+            self.screen_filter,
+        );
+
+        re_log::debug!(
+            "Resized the render to {width}x{height}: {:.1} MiB -> {:.1} MiB",
+            old_bytes as f64 / (1024.0 * 1024.0),
+            raytracing_resources.render_sized_bytes() as f64 / (1024.0 * 1024.0)
+        );
+
+        // A kernel that failed to reload stays reported, the resources use the last one that
+        // compiled
+        if let Some(error) = raytracing_resources.shader_error() {
+            self.shader_error = Some(error);
+        }
+
+        let resources = Resources {
+            raytracing_resources,
+            screen_resources: triangle_resources,
+            tx,
+        };
+
+        render_state
+            .renderer
+            .write()
+            .paint_callback_resources
+            .insert(resources);
+
+        self.texture_width = width;
+        self.texture_height = height;
+        // The new textures would stay blank outside of a render region
+        self.render_region = None;
+        self.gbuffer_use = self.wanted_gbuffer_use();
+        self.gbuffer_outdated = self.gbuffer_use != GBufferUse::None;
+    }
+
+    pub fn render_region(&self) -> Option<Tile> {
+        self.render_region
+    }
+
+    /// Goes back to rendering the whole frame.
+    pub fn clear_render_region(&mut self) {
+        self.render_region = None;
+        self.reset_accumulation();
+    }
+
+    /// The render region in viewport coordinates, or the one being drawn, to outline it.
+    pub fn render_region_rect(&self) -> Option<egui::Rect> {
+        if let Some((start, end)) = self.region_drag {
+            return Some(egui::Rect::from_two_pos(start, end).intersect(self.viewport_rect));
+        }
+        let region = self.render_region?;
+        let texture_size = self.texture_size();
+        Some(region_to_viewport(self.viewport_rect, region, texture_size))
+    }
+
+    /// Draws the render region while shift dragging over the viewport, setting it on release.
+    /// Returns whether it is being drawn.
+    fn drag_render_region(&mut self, ui: &egui::Ui, response: &egui::Response) -> bool {
+        if response.drag_started() && ui.input(|input| input.modifiers.shift) {
+            self.region_drag = response.interact_pointer_pos().map(|pos| (pos, pos));
+        }
+        let Some((start, mut end)) = self.region_drag else {
+            return false;
+        };
+        if let Some(pos) = response.interact_pointer_pos() {
+            end = pos;
+            self.region_drag = Some((start, end));
+        }
+        if response.drag_released() {
+            let rect = egui::Rect::from_two_pos(start, end);
+            let texture_size = self.texture_size();
+            if let Some(region) = viewport_to_region(self.viewport_rect, rect, texture_size) {
+                self.render_region = Some(region);
+                self.reset_accumulation();
+            }
+            self.region_drag = None;
+        }
+        true
+    }
+
+    /// Resizes the render, reprojecting the accumulation into the new size when
+    /// `reproject_on_resize` allows it and restarting it otherwise.
+    fn resize(&mut self, width: u32, height: u32, render_state: &egui_wgpu::RenderState) {
+        let from = (self.texture_width, self.texture_height);
+        let accumulation = if self.reproject_on_resize
+            && self.accumulate
+            && self.scene_info.frame_count > 0
+            && can_reproject(from, (width, height))
+        {
+            pollster::block_on(self.read_accumulation())
+        } else {
+            None
+        };
+        self.rebuild_pipeline(width, height, render_state);
+
+        let Some(pixels) = accumulation.filter(|_| (width, height) == self.texture_size()) else {
+            self.scene_info.frame_count = 0;
+            return;
+        };
+        self.write_accumulation(&reproject(&pixels, from, (width, height)));
+        self.scene_info.frame_count = self.scene_info.frame_count.min(MAX_REPROJECTED_FRAMES);
+        self.reprojected = true;
+    }
+
+    /// The running average of the accumulation, read back from the GPU.
+    async fn read_accumulation(&self) -> Option<Vec<[f32; 4]>> {
+        let renderer = self.renderer.read();
+        let resources = renderer
+            .paint_callback_resources
+            .get::<Resources>()
+            .unwrap();
+        let raytracing_resources = &resources.raytracing_resources;
+        let pixels = self
+            .read_texture(raytracing_resources, &raytracing_resources.storage_texture)
+            .await?;
+        Some(
+            bytemuck::cast_slice::<_, [half::f16; 4]>(&pixels)
+                .iter()
+                .map(|pixel| pixel.map(f32::from))
+                .collect(),
+        )
+    }
+
+    /// Uploads `pixels`, which are the size of the render, as the running average the next frame
+    /// blends into.
+    fn write_accumulation(&self, pixels: &[[f32; 4]]) {
+        let width = self.texture_width as usize;
+        let padded_bytes_per_row = get_padded_bytes_per_row_from_width(self.texture_width) as usize;
+        let mut bytes = vec![0; padded_bytes_per_row * self.texture_height as usize];
+        for (row, pixels) in bytes
+            .chunks_mut(padded_bytes_per_row)
+            .zip(pixels.chunks(width))
+        {
+            let values: Vec<half::f16> = pixels
+                .iter()
+                .flatten()
+                .map(|&value| half::f16::from_f32(value))
+                .collect();
+            row[..8 * width].copy_from_slice(bytemuck::cast_slice(&values));
+        }
+
+        let renderer = self.renderer.read();
+        let resources = renderer
+            .paint_callback_resources
+            .get::<Resources>()
+            .unwrap();
+        self.queue.write_buffer(
+            &resources.raytracing_resources.progressive_rendering_buffer,
+            0,
+            &bytes,
+        );
+    }
+
+    /// Rebuilds every GPU resource after the device reported an error, which may have left them
+    /// unusable. Returns whether it did.
+    fn recover_from_gpu_errors(&mut self, render_state: &egui_wgpu::RenderState) -> bool {
+        // A lost device fails again right away, don't rebuild every frame
+        let may_retry = self
+            .recovering_since
+            .is_none_or(|since| since.elapsed() >= GPU_RECOVERY_INTERVAL);
+        if !may_retry || !self.gpu_errors.swap(false, Ordering::Relaxed) {
+            return false;
+        }
+        re_log::warn!("Rebuilding the GPU resources after an error");
+        self.rebuild_pipeline(self.texture_width, self.texture_height, render_state);
+        self.reset_accumulation();
+        self.recovering_since = Some(Instant::now());
+        true
+    }
+
+    /// Whether the GPU resources were rebuilt after an error in the last
+    /// `GPU_RECOVERY_INTERVAL`.
+    pub fn recovering_gpu(&self) -> bool {
+        self.recovering_since
+            .is_some_and(|since| since.elapsed() < GPU_RECOVERY_INTERVAL)
+    }
+
+    /// Source of the kernel tracing the scene, the last one that compiled.
+    pub fn kernel_source(&self) -> &str {
+        &self.kernel_source
+    }
+
+    /// Why the kernel last failed to compile, until one compiles.
+    pub fn shader_error(&self) -> Option<&ShaderError> {
+        self.shader_error.as_ref()
+    }
+
+    /// Whether there is a kernel to trace with, the first one may not have compiled.
+    fn kernel_compiled(&self) -> bool {
+        let renderer = self.renderer.read();
+        let resources: &Resources = renderer.paint_callback_resources.get().unwrap();
+        resources.raytracing_resources.pipeline.is_ok()
+    }
+
+    /// Recompiles the kernel from `source` and restarts the accumulation. A kernel that doesn't
+    /// compile is reported by `shader_error`, the previous one keeps tracing.
+    pub fn reload_kernel(&mut self, source: String) {
+        self.compile_kernel(source, self.workgroup_size);
+    }
+
+    pub fn workgroup_size(&self) -> WorkgroupSize {
+        self.workgroup_size
+    }
+
+    /// Recompiles the kernel with workgroups of `workgroup_size`, to compare the time frames take.
+    pub fn set_workgroup_size(&mut self, workgroup_size: WorkgroupSize) {
+        if workgroup_size != self.workgroup_size {
+            self.compile_kernel(self.kernel_source.clone(), workgroup_size);
+        }
+    }
+
+    /// Replaces the kernel with `source` compiled for `workgroup_size`, keeping the previous
+    /// kernel and size when it doesn't compile.
+    fn compile_kernel(&mut self, source: String, workgroup_size: WorkgroupSize) {
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        let raytracing_resources = &mut resources.raytracing_resources;
+        let pipeline = pollster::block_on(raytracer::validate_shader(
+            &self.device,
+            raytracer::KERNEL_NAME,
+            || {
+                raytracer::create_pipeline_from_source(
+                    &self.device,
+                    &raytracing_resources.bind_group_layout,
+                    &raytracer::with_workgroup_size(&source, workgroup_size),
+                )
+            },
+        ));
+        match pipeline {
+            Ok(pipeline) => {
+                raytracing_resources.pipeline = Ok(pipeline);
+                raytracing_resources.workgroup_size = workgroup_size;
+                drop(renderer);
+                re_log::info!(
+                    "Reloaded {} with {workgroup_size} workgroups",
+                    raytracer::KERNEL_NAME
+                );
+                self.kernel_source = source;
+                self.workgroup_size = workgroup_size;
+                self.shader_error = None;
+                self.reset_accumulation();
+            }
+            Err(error) => {
+                re_log::error!("Failed to compile {error}");
+                self.shader_error = Some(error);
+            }
+        }
+    }
+
+    /// The adapter eframe picked, to tell which GPU and backend the app runs on.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    /// Grows the primitive buffers the scene outgrew, see `SceneBuffers::reserve`.
+    fn ensure_scene_capacity(&mut self) {
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        let raytracing_resources = &mut resources.raytracing_resources;
+        let capacity = raytracing_resources.scene_buffers.capacity();
+        if !raytracing_resources
+            .scene_buffers
+            .reserve(&self.device, &self.scene)
+        {
+            return;
+        }
+        re_log::info!(
+            "Growing the scene buffers from {capacity:?} to {:?}",
+            raytracing_resources.scene_buffers.capacity()
+        );
+        raytracing_resources.rebind(&self.device);
+        // A replaced buffer lost its contents
+        self.triangles_outdated = true;
+    }
 
-        let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+    fn wanted_gbuffer_use(&self) -> GBufferUse {
+        if self.denoise {
+            GBufferUse::Denoise
+        } else if self.export_gbuffer {
+            GBufferUse::Export
+        } else {
+            GBufferUse::None
+        }
+    }
+
+    /// Creates or drops the G-buffer and the denoiser when `denoise` or `export_gbuffer` were
+    /// toggled. The accumulated image is kept, so a converged render can be denoised without
+    /// starting over.
+    fn ensure_gbuffer(&mut self) {
+        let gbuffer_use = self.wanted_gbuffer_use();
+        if gbuffer_use == self.gbuffer_use {
+            return;
+        }
+
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        let raytracing_resources = &mut resources.raytracing_resources;
+        let (gbuffer, denoiser) = Self::create_gbuffer(
+            &self.device,
+            &raytracing_resources.storage_texture_view,
+            self.texture_width,
+            self.texture_height,
+            gbuffer_use,
+        );
+        raytracing_resources.gbuffer = gbuffer;
+        raytracing_resources.denoiser = denoiser;
+        raytracing_resources.rebind(&self.device);
+        resources.screen_resources = Self::create_screen_pipeline(
+            &self.device,
+            raytracing_resources.display_view(),
+            self.target_format,
+            self.screen_filter,
+        );
+
+        self.gbuffer_use = gbuffer_use;
+        self.gbuffer_outdated = gbuffer_use != GBufferUse::None;
+    }
+
+    /// Recreates the screen pass when `preview_filter` was changed. Only the display changes, the
+    /// accumulated image is kept.
+    fn ensure_preview_filter(&mut self) {
+        if self.preview_filter == self.screen_filter {
+            return;
+        }
+
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.screen_resources = Self::create_screen_pipeline(
+            &self.device,
+            resources.raytracing_resources.display_view(),
+            self.target_format,
+            self.preview_filter,
+        );
+        self.screen_filter = self.preview_filter;
+    }
+
+    /// The G-buffer for `gbuffer_use`, and a denoiser reading `color_view` when denoising.
+    fn create_gbuffer(
+        device: &wgpu::Device,
+        color_view: &wgpu::TextureView,
+        width: u32,
+        height: u32,
+        gbuffer_use: GBufferUse,
+    ) -> (GBuffer, Option<Denoiser>) {
+        match gbuffer_use {
+            GBufferUse::None => (GBuffer::disabled(device), None),
+            GBufferUse::Export => (GBuffer::new(device, width, height), None),
+            GBufferUse::Denoise => {
+                let gbuffer = GBuffer::new(device, width, height);
+                let denoiser = Denoiser::new(device, color_view, &gbuffer, width, height);
+                (gbuffer, Some(denoiser))
+            }
+        }
+    }
+
+    fn ensure_triangles(&mut self) {
+        if !std::mem::take(&mut self.triangles_outdated) {
+            return;
+        }
+        let renderer = self.renderer.read();
+        let resources: &Resources = renderer.paint_callback_resources.get().unwrap();
+        resources
+            .raytracing_resources
+            .scene_buffers
+            .write_triangles(&self.queue, &self.scene.triangles);
+    }
+
+    /// Rebuilds the sphere BVH for the shutter from `preview_time` if the spheres moved, see
+    /// `SceneBuffers::write_bvh`.
+    fn ensure_bvh(&mut self) {
+        let mut renderer = self.renderer.write();
+        let resources: &mut Resources = renderer.paint_callback_resources.get_mut().unwrap();
+        resources.raytracing_resources.scene_buffers.write_bvh(
+            &self.queue,
+            &self.scene.spheres,
+            (self.preview_time, self.preview_time + self.shutter),
+        );
+    }
+
+    fn create_raytracing_pipeline(
+        device: &wgpu::Device,
+        texture_width: u32,
+        texture_height: u32,
+        scene_buffers: SceneBuffers,
+        gbuffer_use: GBufferUse,
+        kernel_source: &str,
+        workgroup_size: WorkgroupSize,
+    ) -> RaytracingRenderResources {
+        let scene_info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(include_str!(
-                "./shaders/raytracer_kernel.wgsl"
-            ))),
+            contents: bytemuck::cast_slice(&[SceneInfo::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
 
-        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        let frame_change_buffer = raytracer::create_frame_change_buffer(device);
+        let frame_change_readback = Readback::new(device, frame_change_buffer.size());
+
+        let storage_texture_descriptor =
+            raytracer::storage_texture_descriptor(texture_width, texture_height);
+        let storage_texture = device.create_texture(&storage_texture_descriptor);
+        let storage_texture_view =
+            storage_texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+        let progressive_rendering_buffer =
+            raytracer::create_progressive_buffer(device, texture_width, texture_height);
+
+        let (gbuffer, denoiser) = Self::create_gbuffer(
+            device,
+            &storage_texture_view,
+            texture_width,
+            texture_height,
+            gbuffer_use,
+        );
+
+        let export_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
-            layout: Some(&pipeline_layout),
-            module: &cs_module,
-            entry_point: "main",
+            size: (get_padded_bytes_per_row_from_width(texture_width) * texture_height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
         });
 
+        let bind_group_layout = raytracer::create_bind_group_layout(device);
+        let environment = EnvironmentMap::disabled(device);
+
+        let bind_group = raytracer::create_bind_group(
+            device,
+            &bind_group_layout,
+            &storage_texture_view,
+            scene_buffers.kernel_buffers(
+                &scene_info_buffer,
+                &progressive_rendering_buffer,
+                &frame_change_buffer,
+            ),
+            &gbuffer,
+            &environment,
+        );
+
+        let kernel_source = raytracer::with_workgroup_size(kernel_source, workgroup_size);
+        let pipeline = pollster::block_on(raytracer::validate_shader(
+            device,
+            raytracer::KERNEL_NAME,
+            || raytracer::create_pipeline_from_source(device, &bind_group_layout, &kernel_source),
+        ));
+
+        let gpu_timer = device
+            .features()
+            .contains(wgpu::Features::TIMESTAMP_QUERY)
+            .then(|| GpuTimer::new(device));
+
         RaytracingRenderResources {
+            gpu_timer,
+            bind_group_layout,
             bind_group,
             pipeline,
+            workgroup_size,
+            environment,
             storage_texture_view,
             storage_texture,
             progressive_rendering_buffer,
             export_buffer,
             scene_info_buffer,
-            sphere_buffer,
+            scene_buffers,
+            frame_change_buffer,
+            frame_change_readback,
+            gbuffer,
+            denoiser,
         }
     }
 
+    /// Draws `color_buffer_view` into the egui render pass, which targets `target_format`,
+    /// sampling it with `filter`.
     fn create_screen_pipeline(
         device: &wgpu::Device,
         color_buffer_view: &wgpu::TextureView,
+        target_format: wgpu::TextureFormat,
+        filter: PreviewFilter,
     ) -> ScreenRenderResources {
         let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
             label: None,
@@ -316,14 +2178,31 @@ impl Custom3d {
                     },
                     count: None,
                 },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
             ],
         });
 
+        let display_settings_buffer =
+            device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: None,
+                contents: bytemuck::cast_slice(&[DisplaySettings::default()]),
+                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            });
+
         let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
             mipmap_filter: wgpu::FilterMode::Nearest,
             anisotropy_clamp: NonZeroU8::new(1),
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: filter.filter_mode(),
+            min_filter: filter.filter_mode(),
             ..Default::default()
         });
 
@@ -339,6 +2218,10 @@ impl Custom3d {
                     binding: 1,
                     resource: wgpu::BindingResource::TextureView(color_buffer_view),
                 },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: display_settings_buffer.as_entire_binding(),
+                },
             ],
         });
 
@@ -367,7 +2250,7 @@ impl Custom3d {
                 module: &shader_module,
                 entry_point: "frag_main",
                 targets: &[Some(wgpu::ColorTargetState {
-                    format: wgpu::TextureFormat::Bgra8Unorm, // ??
+                    format: target_format,
                     blend: None,
                     write_mask: wgpu::ColorWrites::default(),
                 })],
@@ -384,36 +2267,21 @@ impl Custom3d {
         ScreenRenderResources {
             pipeline,
             bind_group,
+            display_settings_buffer,
         }
     }
 
-    fn get_storage_texture_descriptor_from_size<'a>(
-        width: u32,
-        height: u32,
-    ) -> wgpu::TextureDescriptor<'a> {
-        wgpu::TextureDescriptor {
-            size: wgpu::Extent3d {
-                width,
-                height,
-                depth_or_array_layers: 1,
-            },
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba16Float,
-            usage: wgpu::TextureUsages::STORAGE_BINDING
-                | wgpu::TextureUsages::RENDER_ATTACHMENT
-                | wgpu::TextureUsages::TEXTURE_BINDING
-                | wgpu::TextureUsages::COPY_SRC,
-            label: None,
-            view_formats: &[],
-        }
-    }
-
-    pub fn custom_painting(&mut self, ui: &mut egui::Ui, frame: &eframe::Frame) {
+    /// Draws the render, returning the viewport's response unless there was no room for it.
+    pub fn custom_painting(
+        &mut self,
+        ui: &mut egui::Ui,
+        frame: &eframe::Frame,
+    ) -> Option<egui::Response> {
+        let fixed_size = self.resolution.size();
         let size_to_allocate = {
             let available_size = ui.available_size();
-            let texture_aspect_ratio = (self.texture_width as f32) / (self.texture_height as f32);
+            let (width, height) = fixed_size.unwrap_or((self.texture_width, self.texture_height));
+            let texture_aspect_ratio = (width as f32) / (height as f32);
 
             let fit_to_x_size =
                 egui::Vec2::new(available_size.x, available_size.x / texture_aspect_ratio);
@@ -428,41 +2296,176 @@ impl Custom3d {
         };
 
         if size_to_allocate.x < 1.0 || size_to_allocate.y < 1.0 {
-            return;
+            return None;
         }
 
-        if size_to_allocate.x as u32 != self.texture_width
-            || size_to_allocate.y as u32 != self.texture_height
+        // A fixed resolution is stretched over the allocated rect by the screen pass
+        let (width, height) =
+            fixed_size.unwrap_or((size_to_allocate.x as u32, size_to_allocate.y as u32));
+        if self.recover_from_gpu_errors(frame.wgpu_render_state().unwrap()) {
+            // Clears the status message once recovered
+            ui.ctx().request_repaint_after(GPU_RECOVERY_INTERVAL);
+        }
+        if width != self.texture_width || height != self.texture_height {
+            let now = Instant::now();
+            if self.resize_debounce.ready((width, height), now) {
+                self.resize(width, height, frame.wgpu_render_state().unwrap());
+            } else {
+                // Nothing else may repaint once the window stops changing
+                ui.ctx().request_repaint_after(RESIZE_DEBOUNCE);
+            }
+        }
+
+        #[cfg(all(feature = "hot-reload", not(target_arch = "wasm32")))]
+        if let Some(source) = self
+            .shader_watcher
+            .as_ref()
+            .and_then(|watcher| watcher.changed_source())
         {
-            self.rebuild_pipeline(
-                size_to_allocate.x as u32,
-                size_to_allocate.y as u32,
-                frame.wgpu_render_state().unwrap(),
-            );
-            self.scene_info.frame_count = 0;
+            self.reload_kernel(source);
+        }
+        if self.animate {
+            self.preview_time = self.scene_start.elapsed().as_secs_f32();
+            // Moving spheres are somewhere else every frame, so nothing accumulates
+            if self
+                .scene
+                .spheres
+                .iter()
+                .any(|sphere| sphere.velocity != Vec3::default())
+            {
+                self.reset_accumulation();
+                ui.ctx().request_repaint();
+            }
+        }
+        self.ensure_scene_capacity();
+        self.ensure_triangles();
+        self.ensure_bvh();
+        self.ensure_gbuffer();
+        self.ensure_preview_filter();
+
+        let (rect, response) =
+            ui.allocate_exact_size(size_to_allocate, egui::Sense::click_and_drag());
+        self.viewport_rect = rect;
+
+        // Shift dragging draws the render region rather than orbiting
+        let drawing_region = self.drag_render_region(ui, &response);
+        match self.camera_mode {
+            CameraMode::Orbit => {
+                if response.dragged() && !drawing_region {
+                    self.orbit(response.drag_delta());
+                }
+                if response.hovered() {
+                    let scroll = ui.input(|input| input.scroll_delta.y);
+                    if scroll != 0.0 {
+                        self.dolly(scroll * 0.01);
+                    }
+                }
+            }
+            CameraMode::Fly => {
+                if response.dragged_by(egui::PointerButton::Secondary) && !drawing_region {
+                    self.look(response.drag_delta());
+                }
+                // Keys typed into a text field shouldn't move the camera
+                if ui.is_enabled() && !ui.ctx().wants_keyboard_input() {
+                    let (direction, delta_time) = ui.input(|input| {
+                        let axis = |positive: egui::Key, negative: egui::Key| {
+                            input.key_down(positive) as i32 as f32
+                                - input.key_down(negative) as i32 as f32
+                        };
+                        let direction = [
+                            axis(egui::Key::D, egui::Key::A),
+                            axis(egui::Key::W, egui::Key::S),
+                            axis(egui::Key::E, egui::Key::Q),
+                        ];
+                        (direction, input.stable_dt)
+                    });
+                    if direction != [0.0; 3] {
+                        self.fly(direction, delta_time);
+                        // Keep moving while the keys are held even if nothing else repaints
+                        ui.ctx().request_repaint();
+                    }
+                }
+            }
         }
 
-        let (rect, _response) = ui.allocate_exact_size(size_to_allocate, egui::Sense::drag());
+        // A finished `render_accumulated` pauses, rather than limiting every later accumulation
+        if self
+            .frame_limit
+            .is_some_and(|limit| self.scene_info.frame_count >= limit)
+        {
+            self.frame_limit = None;
+            self.paused = true;
+        }
 
-        self.scene_info.random_seed = self.random_gen.gen();
-        self.scene_info.time = self.scene_start.elapsed().as_secs_f32();
-        self.scene_info.frame_count += 1;
+        // A reset accumulation always renders its first frame so the view isn't left blank, and so
+        // does a new G-buffer, which the denoiser shows nothing without
+        let wants_frame = !self.paused || std::mem::take(&mut self.step_requested);
+        let below_limit = !self.converged();
+        let gbuffer_outdated = std::mem::take(&mut self.gbuffer_outdated);
+        let reprojected = std::mem::take(&mut self.reprojected);
+        let render_frame = self.kernel_compiled()
+            && ((wants_frame && below_limit)
+                || self.scene_info.frame_count == 0
+                || gbuffer_outdated
+                || reprojected);
+        if render_frame {
+            self.scene_info.random_seed = self.next_seed();
+            // Without accumulation the count only grows, so it must not overflow either
+            self.scene_info.frame_count = self.scene_info.frame_count.saturating_add(1);
+        }
+        self.update_scene_info();
 
         let cb = egui_wgpu::CallbackFn::new()
             .prepare({
                 let texture_width = self.texture_width;
                 let texture_height = self.texture_height;
                 let scene_info = self.scene_info;
+                // Meshes can be large and have their own uploads, only the rest is copied
+                let primitives = render_frame.then(|| {
+                    (
+                        self.scene.spheres.clone(),
+                        self.scene.planes.clone(),
+                        self.scene.lights.clone(),
+                    )
+                });
+                let display_settings =
+                    DisplaySettings::new(self.exposure, self.tone_mapping, self.target_format);
                 move |device, queue, encoder, paint_callback_resources| {
                     let resources: &Resources = paint_callback_resources.get().unwrap();
+                    // Display settings apply to the accumulated image even when no frame is traced
+                    resources.screen_resources.prepare(queue, display_settings);
+                    let gpu_timer = resources.raytracing_resources.gpu_timer.as_ref();
+                    if let Some(gpu_time) = gpu_timer.and_then(|timer| timer.collect(queue)) {
+                        let _ = resources.tx.send(Message::ComputePassTimed { gpu_time });
+                    }
+                    let frame_change_readback =
+                        &resources.raytracing_resources.frame_change_readback;
+                    if let Some(frame_change) = frame_change_readback.collect() {
+                        let _ = resources
+                            .tx
+                            .send(Message::FrameChangeMeasured { frame_change });
+                    }
+                    let Some((spheres, planes, lights)) = &primitives else {
+                        return Vec::with_capacity(0);
+                    };
+                    let start = Instant::now();
                     resources.prepare(
                         device,
                         queue,
                         encoder,
-                        texture_width,
-                        texture_height,
+                        (texture_width, texture_height),
                         scene_info,
+                        Primitives {
+                            spheres,
+                            planes,
+                            lights,
+                        },
                     );
+                    // The UI may already be gone when closing, there is nobody to report to then
+                    let _ = resources.tx.send(Message::FramePrepared {
+                        frame_count: scene_info.frame_count,
+                        prepare_time: start.elapsed(),
+                    });
                     Vec::with_capacity(0)
                 }
             })
@@ -477,15 +2480,240 @@ impl Custom3d {
         };
 
         ui.painter().add(callback);
+        Some(response)
     }
 
-    pub async fn save(&self, save_path: PathBuf) {
+    /// Random seed of the frame after `scene_info.frame_count`.
+    fn next_seed(&mut self) -> f32 {
+        if self.deterministic {
+            raytracer::frame_seed(self.base_seed, self.scene_info.frame_count)
+        } else {
+            self.random_gen.gen()
+        }
+    }
+
+    /// Copies the scene and settings into the `SceneInfo` the kernel reads.
+    fn update_scene_info(&mut self) {
+        self.scene_info.camera = self.scene.camera;
+        self.scene_info.aspect = self.texture_width as f32 / self.texture_height as f32;
+        self.scene_info.sphere_count = self.scene.spheres.len() as u32;
+        self.scene_info.plane_count = self.scene.planes.len() as u32;
+        self.scene_info.triangle_count = self.scene.triangles.len() as u32;
+        self.scene_info.light_count = self.scene.lights.len() as u32;
+        self.scene_info.debug_mode = self.debug_mode as u32;
+        self.scene_info.ao_radius = self.ao_radius;
+        self.scene_info.ao_samples = self.ao_samples;
+        self.scene_info.time = self.preview_time;
+        self.scene_info.shutter = self.shutter;
+        (self.scene_info.tile_offset, self.scene_info.tile_end) = match self.render_region {
+            Some(region) => (
+                [region.x, region.y],
+                [region.x + region.width, region.y + region.height],
+            ),
+            None => ([0, 0], [0, 0]),
+        };
+        self.scene_info.pixel_center = self.pixel_center.offset();
+        self.scene_info.accumulate = self.accumulate as u32;
+        self.scene_info.stable_intersection = self.stable_intersection as u32;
+        self.scene_info.jitter = self.jitter as u32;
+        self.scene_info.max_sample_radiance = if self.firefly_clamp {
+            self.max_sample_radiance
+        } else {
+            0.0
+        };
+        self.scene_info.samples_per_pixel = self.samples_per_pixel;
+        self.scene_info.max_bounces = self.max_bounces;
+        self.scene_info.background_mode = self.background_mode as u32;
+        self.scene_info.background_color = from_rgb(self.background_color);
+        self.scene_info.sky_top_color = from_rgb(self.sky_top_color);
+        self.scene_info.sky_bottom_color = from_rgb(self.sky_bottom_color);
+        self.scene_info.sun_direction = sun_direction(self.sun_azimuth, self.sun_elevation);
+        self.scene_info.sun_color = from_rgb(self.sun_color);
+        self.scene_info.sun_intensity = self.sun_intensity;
+        self.scene_info.write_gbuffer = (self.gbuffer_use != GBufferUse::None) as u32;
+    }
+
+    /// Traces `frames` accumulation frames right away, rather than one per UI frame in the paint
+    /// callback.
+    fn render_frames(&mut self, frames: u32) {
+        self.update_scene_info();
+        self.scene_info.accumulate = 1;
+        self.scene_info.frame_count = 0;
+        for _ in 0..frames {
+            let submission_index = self.submit_next_frame();
+            // Queuing every frame at once could run into the GPU's timeout
+            self.device
+                .poll(wgpu::Maintain::WaitForSubmissionIndex(submission_index));
+        }
+    }
+
+    /// Traces the next accumulation frame right away without waiting for the GPU, so the caller
+    /// can queue the following frame before this one is done and decide when to wait on it.
+    /// `render_frames` waits on every frame instead.
+    pub fn submit_frame(&mut self) -> wgpu::SubmissionIndex {
+        self.update_scene_info();
+        self.submit_next_frame()
+    }
+
+    /// Traces the frame after `scene_info.frame_count` with the uniforms as they are.
+    fn submit_next_frame(&mut self) -> wgpu::SubmissionIndex {
+        self.scene_info.random_seed = self.next_seed();
+        // Without accumulation the count only grows, so it must not overflow either
+        self.scene_info.frame_count = self.scene_info.frame_count.saturating_add(1);
+        let renderer = self.renderer.clone();
+        let renderer = renderer.read();
+        let resources: &Resources = renderer.paint_callback_resources.get().unwrap();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor::default());
+        resources.prepare(
+            &self.device,
+            &self.queue,
+            &mut encoder,
+            (self.texture_width, self.texture_height),
+            self.scene_info,
+            Primitives {
+                spheres: &self.scene.spheres,
+                planes: &self.scene.planes,
+                lights: &self.scene.lights,
+            },
+        );
+        self.queue.submit(Some(encoder.finish()))
+    }
+
+    /// Saves the scene rendered at `width` x `height` with at least `samples` samples per pixel,
+    /// rather than the preview. The GPU resources are rebuilt at that size for the render, then
+    /// back at the preview's size, whose accumulation restarts.
+    pub async fn save_at_size(
+        &mut self,
+        save_path: PathBuf,
+        (width, height): (u32, u32),
+        samples: u32,
+        render_state: &egui_wgpu::RenderState,
+    ) {
+        let (preview_width, preview_height) = (self.texture_width, self.texture_height);
+        self.rebuild_pipeline(width, height, render_state);
+        self.ensure_triangles();
+        self.ensure_bvh();
+        self.ensure_gbuffer();
+        self.render_frames(samples.div_ceil(self.samples_per_pixel.max(1)));
+        self.save(save_path).await;
+
+        self.rebuild_pipeline(preview_width, preview_height, render_state);
+        self.reset_accumulation();
+    }
+
+    pub async fn save(&self, mut save_path: PathBuf) {
+        // Some file dialogs don't add the extension of the chosen filter
+        if save_path.extension().is_none() {
+            save_path.set_extension("png");
+        }
+        let format = ImageFormat::from_path(&save_path);
+
+        let renderer = self.renderer.read();
+        let resources = renderer
+            .paint_callback_resources
+            .get::<Resources>()
+            .unwrap();
+        let raytracing_resources = &resources.raytracing_resources;
+
+        // Read the displayed texture rather than the progressive buffer, which isn't kept up to
+        // date when accumulation is off and is never denoised
+        let Some(pixels) = self
+            .read_texture(raytracing_resources, raytracing_resources.display_texture())
+            .await
+        else {
+            return;
+        };
+        let result = match format {
+            ImageFormat::Png => self.write_png(&save_path, &pixels, self.exposure),
+            ImageFormat::Exr => self.write_exr(&save_path, &pixels),
+        };
+        if let Err(err) = result {
+            re_log::error!("Failed to save {save_path:?}: {err}");
+        }
+
+        if !self.export_gbuffer || self.gbuffer_use == GBufferUse::None {
+            return;
+        }
+        let gbuffer = &raytracing_resources.gbuffer;
+        let Some(normal_depth) = self
+            .read_texture(raytracing_resources, &gbuffer.normal_depth)
+            .await
+        else {
+            return;
+        };
+        let Some(albedo) = self
+            .read_texture(raytracing_resources, &gbuffer.albedo)
+            .await
+        else {
+            return;
+        };
+        let normal_depth = bytemuck::cast_slice::<_, [half::f16; 4]>(&normal_depth);
+        let albedo = bytemuck::cast_slice::<_, [half::f16; 4]>(&albedo);
+
+        for layer in GBufferLayer::ALL {
+            let path = layer.path(&save_path);
+            let mut layer_pixels: Vec<[f32; 4]> = normal_depth
+                .iter()
+                .zip(albedo)
+                .map(|(normal_depth, albedo)| {
+                    layer.pixel(normal_depth.map(f32::from), albedo.map(f32::from))
+                })
+                .collect();
+            let result = if format == ImageFormat::Exr {
+                let values: Vec<half::f16> = layer_pixels
+                    .iter()
+                    .flatten()
+                    .map(|&value| half::f16::from_f32(value))
+                    .collect();
+                self.write_exr(&path, &values)
+            } else {
+                layer.to_display(&mut layer_pixels);
+                let bytes: Vec<u8> = layer_pixels
+                    .iter()
+                    .flatten()
+                    .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+                    .collect();
+                self.write_rgba8_png(&path, &bytes, layer == GBufferLayer::Albedo)
+            };
+            if let Err(err) = result {
+                re_log::error!("Failed to save {path:?}: {err}");
+            }
+        }
+    }
+
+    /// Saves the displayed render once per exposure stop, each relative to `exposure`, without
+    /// rendering it again. See `bracket_path` for the file names.
+    pub async fn save_bracket(&self, save_path: PathBuf, stops: &[f32]) {
         let renderer = self.renderer.read();
         let resources = renderer
             .paint_callback_resources
             .get::<Resources>()
             .unwrap();
+        let raytracing_resources = &resources.raytracing_resources;
+
+        let Some(pixels) = self
+            .read_texture(raytracing_resources, raytracing_resources.display_texture())
+            .await
+        else {
+            return;
+        };
+        for &stop in stops {
+            let path = bracket_path(&save_path, stop);
+            if let Err(err) = self.write_png(&path, &pixels, self.exposure + stop) {
+                re_log::error!("Failed to save {path:?}: {err}");
+            }
+        }
+    }
 
+    /// Copies `texture`, which is the size of the render, back to the CPU through the export
+    /// buffer.
+    async fn read_texture(
+        &self,
+        raytracing_resources: &RaytracingRenderResources,
+        texture: &wgpu::Texture,
+    ) -> Option<Vec<half::f16>> {
         let padded_bytes_per_row = get_padded_bytes_per_row_from_width(self.texture_width) as usize;
         let unpadded_bytes_per_row: usize = 8 * self.texture_width as usize; // Rgba16Float
 
@@ -496,12 +2724,21 @@ impl Custom3d {
                     ..Default::default()
                 });
 
-            encoder.copy_buffer_to_buffer(
-                &resources.raytracing_resources.progressive_rendering_buffer,
-                0,
-                &resources.raytracing_resources.export_buffer,
-                0,
-                padded_bytes_per_row as u64 * (self.texture_height as u64),
+            encoder.copy_texture_to_buffer(
+                texture.as_image_copy(),
+                wgpu::ImageCopyBuffer {
+                    buffer: &raytracing_resources.export_buffer,
+                    layout: wgpu::ImageDataLayout {
+                        bytes_per_row: NonZeroU32::new(padded_bytes_per_row as u32),
+                        offset: 0,
+                        rows_per_image: None,
+                    },
+                },
+                wgpu::Extent3d {
+                    width: self.texture_width,
+                    height: self.texture_height,
+                    depth_or_array_layers: 1,
+                },
             );
 
             encoder.finish()
@@ -509,71 +2746,259 @@ impl Custom3d {
 
         let submission_index = self.queue.submit(Some(command_buffer));
 
-        let buffer_slice = resources
-            .raytracing_resources
-            .export_buffer
-            .slice(..);
+        let buffer_slice = raytracing_resources.export_buffer.slice(..);
 
         let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
         buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
 
         self.device.poll(wgpu::Maintain::WaitForSubmissionIndex(submission_index));
 
-        if let Some(Ok(())) = receiver.receive().await {
-            let mut png_encoder = png::Encoder::new(
-                File::create(save_path).unwrap(),
-                self.texture_width,
-                self.texture_height,
-            );
-            png_encoder.set_depth(png::BitDepth::Eight);
-            png_encoder.set_color(png::ColorType::Rgba);
-
-            let mut png_writer = png_encoder
-                .write_header()
-                .unwrap()
-                .into_stream_writer_with_size(unpadded_bytes_per_row)
-                .unwrap();
-
-            let padded_buffer = buffer_slice.get_mapped_range();
-            for chunk in padded_buffer.chunks(padded_bytes_per_row) {
-                let unpadded_data = &chunk[0..unpadded_bytes_per_row];
-                let data: &[half::f16] = bytemuck::cast_slice(unpadded_data);
-                for value in data {
-                    let byte_value: u8 = (f32::from(*value).clamp(0.0, 1.0) * 255.0).round() as u8;
-                    png_writer.write_all(&[byte_value]).unwrap();
-                }
+        let Some(Ok(())) = receiver.receive().await else {
+            return None;
+        };
+        let padded_buffer = buffer_slice.get_mapped_range();
+        let pixels: Vec<half::f16> = padded_buffer
+            .chunks(padded_bytes_per_row)
+            .flat_map(|chunk| bytemuck::cast_slice(&chunk[0..unpadded_bytes_per_row]))
+            .copied()
+            .collect();
+        drop(padded_buffer);
+        raytracing_resources.export_buffer.unmap();
+        Some(pixels)
+    }
+
+    /// Writes the tone mapped render as an 8-bit sRGB PNG, as it is shown on screen at
+    /// `exposure`.
+    fn write_png(
+        &self,
+        path: &std::path::Path,
+        pixels: &[half::f16],
+        exposure: f32,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let bytes: Vec<u8> = bytemuck::cast_slice::<_, [half::f16; 4]>(pixels)
+            .iter()
+            .flat_map(|pixel| display_color(pixel.map(f32::from), exposure, self.tone_mapping))
+            .map(|value| (value.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect();
+        self.write_rgba8_png(path, &bytes, true)
+    }
+
+    /// Writes 8-bit RGBA `bytes` the size of the render, tagged as sRGB when `srgb` is set.
+    fn write_rgba8_png(
+        &self,
+        path: &std::path::Path,
+        bytes: &[u8],
+        srgb: bool,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut png_encoder = png::Encoder::new(
+            BufWriter::new(File::create(path)?),
+            self.texture_width,
+            self.texture_height,
+        );
+        png_encoder.set_depth(png::BitDepth::Eight);
+        png_encoder.set_color(png::ColorType::Rgba);
+        if srgb {
+            png_encoder.set_srgb(png::SrgbRenderingIntent::Perceptual);
+        }
+
+        let mut png_writer = png_encoder.write_header()?;
+        png_writer.write_image_data(bytes)?;
+        png_writer.finish()?;
+        Ok(())
+    }
+
+    /// Writes the raw linear render, with its full dynamic range and premultiplied alpha.
+    fn write_exr(
+        &self,
+        path: &std::path::Path,
+        pixels: &[half::f16],
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let width = self.texture_width as usize;
+        exr::prelude::write_rgba_file(path, width, self.texture_height as usize, |x, y| {
+            let index = (y * width + x) * 4;
+            (
+                pixels[index],
+                pixels[index + 1],
+                pixels[index + 2],
+                pixels[index + 3],
+            )
+        })?;
+        Ok(())
+    }
+}
+
+struct ScreenRenderResources {
+    pipeline: wgpu::RenderPipeline,
+    bind_group: wgpu::BindGroup,
+    display_settings_buffer: wgpu::Buffer,
+}
+
+struct RaytracingRenderResources {
+    pipeline: Result<wgpu::ComputePipeline, ShaderError>,
+    /// What `pipeline` was compiled with, so the dispatch covers the texture.
+    workgroup_size: WorkgroupSize,
+    /// Sampled by rays escaping the scene in `BackgroundMode::Environment`, kept when the
+    /// render is resized.
+    environment: EnvironmentMap,
+    bind_group_layout: wgpu::BindGroupLayout,
+    bind_group: wgpu::BindGroup,
+    storage_texture_view: wgpu::TextureView,
+    storage_texture: wgpu::Texture,
+    progressive_rendering_buffer: wgpu::Buffer,
+    export_buffer: wgpu::Buffer,
+    scene_info_buffer: wgpu::Buffer,
+    /// The spheres, planes and lights are uploaded every frame, there are only a few of them. The
+    /// BVH and triangles are written by `Custom3d::ensure_bvh` and `ensure_triangles` when they
+    /// change.
+    scene_buffers: SceneBuffers,
+    /// Summed by the kernel over a frame, cleared before the frames that are read back.
+    frame_change_buffer: wgpu::Buffer,
+    frame_change_readback: Readback,
+    /// Placeholder textures unless denoising or exporting the G-buffer.
+    gbuffer: GBuffer,
+    denoiser: Option<Denoiser>,
+    /// Only created when the device supports timestamp queries.
+    gpu_timer: Option<GpuTimer>,
+}
+
+/// A buffer copied to the CPU without stalling the GPU.
+///
+/// A copy can only be mapped once the frame that recorded it was submitted, so it is collected at
+/// the start of a later frame, and no new copy is recorded until the last one was read.
+struct Readback {
+    buffer: wgpu::Buffer,
+    /// One of the `Readback::*` states, shared with the `map_async` callback.
+    state: Arc<AtomicU8>,
+}
+
+impl Readback {
+    /// Nothing is being read back, the next copy can be recorded.
+    const IDLE: u8 = 0;
+    /// The copy was recorded in a frame that may not be submitted yet.
+    const RECORDED: u8 = 1;
+    const MAPPING: u8 = 2;
+    const MAPPED: u8 = 3;
+
+    fn new(device: &wgpu::Device, size: u64) -> Self {
+        Self {
+            buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+                mapped_at_creation: false,
+            }),
+            state: Arc::new(AtomicU8::new(Self::IDLE)),
+        }
+    }
+
+    fn is_idle(&self) -> bool {
+        self.state.load(Ordering::Acquire) == Self::IDLE
+    }
+
+    /// Records a copy of `source`, which must be the size of the readback buffer. Only call it
+    /// while idle.
+    fn copy(&self, encoder: &mut wgpu::CommandEncoder, source: &wgpu::Buffer) {
+        encoder.copy_buffer_to_buffer(source, 0, &self.buffer, 0, self.buffer.size());
+        self.state.store(Self::RECORDED, Ordering::Release);
+    }
+
+    /// Advances the readback of the last copy, returning its contents once they are mapped. Must
+    /// be called from a later frame than the one the copy was recorded in.
+    fn collect<T: Pod>(&self) -> Option<T> {
+        match self.state.load(Ordering::Acquire) {
+            Self::RECORDED => {
+                self.state.store(Self::MAPPING, Ordering::Release);
+                let state = self.state.clone();
+                self.buffer
+                    .slice(..)
+                    .map_async(wgpu::MapMode::Read, move |result| {
+                        let next = if result.is_ok() {
+                            Self::MAPPED
+                        } else {
+                            Self::IDLE
+                        };
+                        state.store(next, Ordering::Release);
+                    });
+                None
+            }
+            Self::MAPPED => {
+                let value = {
+                    let data = self.buffer.slice(..).get_mapped_range();
+                    bytemuck::pod_read_unaligned(&data)
+                };
+                self.buffer.unmap();
+                self.state.store(Self::IDLE, Ordering::Release);
+                Some(value)
             }
+            _ => None,
+        }
+    }
+}
+
+/// Times the compute pass with a pair of timestamp queries, read back at most every other frame.
+struct GpuTimer {
+    query_set: wgpu::QuerySet,
+    resolve_buffer: wgpu::Buffer,
+    readback: Readback,
+}
+
+impl GpuTimer {
+    fn new(device: &wgpu::Device) -> Self {
+        let size = 2 * wgpu::QUERY_SIZE as u64;
+        Self {
+            query_set: device.create_query_set(&wgpu::QuerySetDescriptor {
+                label: Some("compute pass timestamps"),
+                ty: wgpu::QueryType::Timestamp,
+                count: 2,
+            }),
+            resolve_buffer: device.create_buffer(&wgpu::BufferDescriptor {
+                label: None,
+                size,
+                usage: wgpu::BufferUsages::QUERY_RESOLVE | wgpu::BufferUsages::COPY_SRC,
+                mapped_at_creation: false,
+            }),
+            readback: Readback::new(device, size),
+        }
+    }
 
-            png_writer.finish().unwrap();
-            drop(padded_buffer);
-            resources
-                .raytracing_resources
-                .export_buffer
-                .unmap();
+    /// Writes the start timestamp, returning false if the previous measurement is still in flight.
+    fn begin(&self, encoder: &mut wgpu::CommandEncoder) -> bool {
+        if !self.readback.is_idle() {
+            return false;
         }
+        encoder.write_timestamp(&self.query_set, 0);
+        true
     }
-}
 
-struct ScreenRenderResources {
-    pipeline: wgpu::RenderPipeline,
-    bind_group: wgpu::BindGroup,
+    fn end(&self, encoder: &mut wgpu::CommandEncoder) {
+        encoder.write_timestamp(&self.query_set, 1);
+        encoder.resolve_query_set(&self.query_set, 0..2, &self.resolve_buffer, 0);
+        self.readback.copy(encoder, &self.resolve_buffer);
+    }
+
+    /// Advances the measurement started by `begin`, returning its duration once it's read back.
+    /// Must be called from a later frame than the one the timestamps were written in.
+    fn collect(&self, queue: &wgpu::Queue) -> Option<Duration> {
+        let timestamps: [u64; 2] = self.readback.collect()?;
+        let ticks = timestamps[1].saturating_sub(timestamps[0]);
+        let nanoseconds = ticks as f64 * queue.get_timestamp_period() as f64;
+        Some(Duration::from_nanos(nanoseconds as u64))
+    }
 }
 
-struct RaytracingRenderResources {
-    pipeline: wgpu::ComputePipeline,
-    bind_group: wgpu::BindGroup,
-    storage_texture_view: wgpu::TextureView,
-    storage_texture: wgpu::Texture,
-    progressive_rendering_buffer: wgpu::Buffer,
-    export_buffer: wgpu::Buffer,
-    scene_info_buffer: wgpu::Buffer,
-    sphere_buffer: wgpu::Buffer,
+/// The primitives uploaded with every traced frame, there are only a few of them. Meshes can be
+/// large, so the triangles are only uploaded when they change.
+#[derive(Clone, Copy)]
+struct Primitives<'a> {
+    spheres: &'a [Sphere],
+    planes: &'a [Plane],
+    lights: &'a [PointLight],
 }
 
 struct Resources {
     raytracing_resources: RaytracingRenderResources,
     screen_resources: ScreenRenderResources,
-    rx: Receiver<Message>,
+    tx: Sender<Message>,
 }
 
 impl Resources {
@@ -582,91 +3007,17 @@ impl Resources {
         device: &wgpu::Device,
         queue: &wgpu::Queue,
         encoder: &mut wgpu::CommandEncoder,
-        texture_width: u32,
-        texture_height: u32,
-        mut scene_info: SceneInfo,
+        texture_size: (u32, u32),
+        scene_info: SceneInfo,
+        primitives: Primitives<'_>,
     ) {
-        let spheres = [
-            Sphere {
-                position: Vec3 {
-                    x: 10.0,
-                    y: 0.0,
-                    z: 1.0,
-                },
-                radius: 1.0,
-                mat: Material {
-                    albedo: Vec3 {
-                        x: 0.87,
-                        y: 0.87,
-                        z: 0.87,
-                    },
-                    is_mirror: 1,
-                    unused_buffer: Default::default(),
-                },
-            },
-            Sphere {
-                position: Vec3 {
-                    x: 7.3,
-                    y: -1.2,
-                    z: 1.02,
-                },
-                radius: 1.0,
-                mat: Material {
-                    albedo: Vec3 {
-                        x: 0.87,
-                        y: 0.87,
-                        z: 0.87,
-                    },
-                    is_mirror: 1,
-                    unused_buffer: Default::default(),
-                },
-            },
-            Sphere {
-                position: Vec3 {
-                    x: 9.0,
-                    y: 2.2,
-                    z: 1.03,
-                },
-                radius: 1.0,
-                mat: Material {
-                    albedo: Vec3 {
-                        x: 0.97,
-                        y: 0.97,
-                        z: 0.97,
-                    },
-                    is_mirror: 0,
-                    unused_buffer: Default::default(),
-                },
-            },
-            Sphere {
-                position: Vec3 {
-                    x: 10.0,
-                    y: 0.0,
-                    z: 102.0,
-                },
-                radius: 100.0,
-                mat: Material {
-                    albedo: Vec3 {
-                        x: 1.0,
-                        y: 0.5,
-                        z: 0.5,
-                    },
-                    is_mirror: 0,
-                    unused_buffer: Default::default(),
-                },
-            },
-        ];
-
-        scene_info.sphere_count = spheres.len() as u32;
-        scene_info.camera.position.x = 2.0;
-
         self.raytracing_resources.prepare(
             device,
             queue,
             encoder,
-            (texture_width, texture_height),
+            texture_size,
             scene_info,
-            &spheres,
+            primitives,
         );
     }
 
@@ -676,6 +3027,85 @@ impl Resources {
 }
 
 impl RaytracingRenderResources {
+    /// Recreates the bind group after some of the buffers or textures were replaced.
+    fn rebind(&mut self, device: &wgpu::Device) {
+        self.bind_group = raytracer::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            &self.storage_texture_view,
+            self.kernel_buffers(),
+            &self.gbuffer,
+            &self.environment,
+        );
+    }
+
+    fn set_environment(&mut self, device: &wgpu::Device, environment: EnvironmentMap) {
+        self.environment = environment;
+        self.rebind(device);
+    }
+
+    fn kernel_buffers(&self) -> KernelBuffers<'_> {
+        self.scene_buffers.kernel_buffers(
+            &self.scene_info_buffer,
+            &self.progressive_rendering_buffer,
+            &self.frame_change_buffer,
+        )
+    }
+
+    /// Why the kernel failed to compile, logging it.
+    fn shader_error(&self) -> Option<ShaderError> {
+        let error = self.pipeline.as_ref().err()?;
+        re_log::error!("Failed to compile {error}");
+        Some(error.clone())
+    }
+
+    /// The kernel's output, or the denoised image when denoising.
+    fn display_texture(&self) -> &wgpu::Texture {
+        self.denoiser
+            .as_ref()
+            .map_or(&self.storage_texture, Denoiser::output)
+    }
+
+    fn display_view(&self) -> &wgpu::TextureView {
+        self.denoiser
+            .as_ref()
+            .map_or(&self.storage_texture_view, Denoiser::output_view)
+    }
+
+    /// The textures and buffers sized after the render, which make up most of its memory at high
+    /// resolutions.
+    fn render_sized(&self) -> (Vec<&wgpu::Texture>, [&wgpu::Buffer; 2]) {
+        let mut textures = vec![
+            &self.storage_texture,
+            &self.gbuffer.normal_depth,
+            &self.gbuffer.albedo,
+        ];
+        if let Some(denoiser) = &self.denoiser {
+            textures.extend(denoiser.textures());
+        }
+        let buffers = [&self.progressive_rendering_buffer, &self.export_buffer];
+        (textures, buffers)
+    }
+
+    /// Roughly how much video memory the render sized allocations take.
+    fn render_sized_bytes(&self) -> u64 {
+        let (textures, buffers) = self.render_sized();
+        let texture_bytes = textures.into_iter().map(|texture| {
+            let size = texture.size();
+            let block_size = texture.format().describe().block_size as u64;
+            size.width as u64 * size.height as u64 * block_size
+        });
+        texture_bytes.chain(buffers.map(wgpu::Buffer::size)).sum()
+    }
+
+    /// Frees the render sized allocations now, rather than once the last handle to them is
+    /// dropped, so resizing doesn't briefly hold both the old and the new ones.
+    fn destroy_render_sized(&self) {
+        let (textures, buffers) = self.render_sized();
+        textures.into_iter().for_each(wgpu::Texture::destroy);
+        buffers.into_iter().for_each(wgpu::Buffer::destroy);
+    }
+
     fn prepare(
         &self,
         _device: &wgpu::Device,
@@ -683,8 +3113,32 @@ impl RaytracingRenderResources {
         encoder: &mut wgpu::CommandEncoder,
         texture_size: (u32, u32),
         scene_info: SceneInfo,
-        spheres: &[Sphere],
+        primitives: Primitives<'_>,
     ) {
+        // Nothing can be traced until the kernel compiles
+        let Ok(pipeline) = &self.pipeline else {
+            return;
+        };
+        assert!(
+            primitives.spheres.len() <= self.scene_buffers.capacity().spheres,
+            "The sphere buffer is too small for {} spheres",
+            primitives.spheres.len()
+        );
+        assert!(
+            primitives.planes.len() <= self.scene_buffers.capacity().planes,
+            "The plane buffer is too small for {} planes",
+            primitives.planes.len()
+        );
+        assert!(
+            primitives.lights.len() <= self.scene_buffers.capacity().lights,
+            "The light buffer is too small for {} lights",
+            primitives.lights.len()
+        );
+        let gpu_timer = self.gpu_timer.as_ref().filter(|timer| timer.begin(encoder));
+        let measure_change = scene_info.accumulate == 1 && self.frame_change_readback.is_idle();
+        if measure_change {
+            encoder.clear_buffer(&self.frame_change_buffer, 0, None);
+        }
         {
             let mut raytracing_pass = encoder.begin_compute_pass(&Default::default());
             queue.write_buffer(
@@ -692,44 +3146,46 @@ impl RaytracingRenderResources {
                 0,
                 bytemuck::cast_slice(&[scene_info]),
             );
-            queue.write_buffer(&self.sphere_buffer, 0, bytemuck::cast_slice(spheres));
-            raytracing_pass.set_pipeline(&self.pipeline);
+            self.scene_buffers.write_spheres(queue, primitives.spheres);
+            self.scene_buffers.write_planes(queue, primitives.planes);
+            self.scene_buffers.write_lights(queue, primitives.lights);
+            raytracing_pass.set_pipeline(pipeline);
             raytracing_pass.set_bind_group(0, &self.bind_group, &[]);
-            raytracing_pass.dispatch_workgroups(texture_size.0, texture_size.1, 1);
+            let (x, y) = self
+                .workgroup_size
+                .counts(dispatch_size(&scene_info, texture_size));
+            raytracing_pass.dispatch_workgroups(x, y, 1);
         }
-        {
-            let source = wgpu::ImageCopyTexture {
-                texture: &self.storage_texture,
-                aspect: wgpu::TextureAspect::All,
-                mip_level: 0,
-                origin: Default::default(),
-            };
-
-            let destination = wgpu::ImageCopyBuffer {
-                buffer: &self.progressive_rendering_buffer,
-                layout: wgpu::ImageDataLayout {
-                    bytes_per_row: NonZeroU32::new(get_padded_bytes_per_row_from_width(
-                        texture_size.0,
-                    )),
-                    offset: 0,
-                    rows_per_image: None,
-                },
-            };
-
-            encoder.copy_texture_to_buffer(
-                source,
-                destination,
-                wgpu::Extent3d {
-                    width: texture_size.0,
-                    height: texture_size.1,
-                    depth_or_array_layers: 1,
-                },
+        if let Some(timer) = gpu_timer {
+            timer.end(encoder);
+        }
+        if measure_change {
+            self.frame_change_readback
+                .copy(encoder, &self.frame_change_buffer);
+        }
+        if let Some(denoiser) = &self.denoiser {
+            denoiser.dispatch(encoder, texture_size);
+        }
+        if scene_info.accumulate == 1 {
+            raytracer::copy_to_progressive_buffer(
+                encoder,
+                &self.storage_texture,
+                &self.progressive_rendering_buffer,
+                texture_size,
             );
         }
     }
 }
 
 impl ScreenRenderResources {
+    fn prepare(&self, queue: &wgpu::Queue, display_settings: DisplaySettings) {
+        queue.write_buffer(
+            &self.display_settings_buffer,
+            0,
+            bytemuck::cast_slice(&[display_settings]),
+        );
+    }
+
     fn paint<'rp>(&'rp self, render_pass: &mut wgpu::RenderPass<'rp>) {
         render_pass.set_pipeline(&self.pipeline);
         render_pass.set_bind_group(0, &self.bind_group, &[]);
@@ -737,9 +3193,815 @@ impl ScreenRenderResources {
     }
 }
 
-fn get_padded_bytes_per_row_from_width(width: u32) -> u32 {
-    let unpadded_bytes_per_row = 8 * width; // Rgba16Float
-    unpadded_bytes_per_row
-        + (wgpu::COPY_BYTES_PER_ROW_ALIGNMENT
-            - (unpadded_bytes_per_row % wgpu::COPY_BYTES_PER_ROW_ALIGNMENT))
+fn to_rgb(color: Vec3) -> [f32; 3] {
+    [color.x, color.y, color.z]
+}
+
+fn from_rgb([x, y, z]: [f32; 3]) -> Vec3 {
+    Vec3::new(x, y, z)
+}
+
+/// Unit vector towards the sun, from angles in degrees. z is up.
+fn sun_direction(azimuth: f32, elevation: f32) -> Vec3 {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    Vec3::new(
+        elevation.cos() * azimuth.cos(),
+        elevation.cos() * azimuth.sin(),
+        elevation.sin(),
+    )
+}
+
+/// Camera position after moving `distance` along `direction`, given in the camera's basis as
+/// amounts along its right, forward and up vectors. Diagonal moves are normalized so they aren't
+/// faster.
+fn fly_position(camera: &Camera, direction: [f32; 3], distance: f32) -> Vec3 {
+    let length = direction.iter().map(|d| d * d).sum::<f32>().sqrt();
+    if length == 0.0 {
+        return camera.position;
+    }
+    let [right, forward, up] = direction.map(|d| d / length * distance);
+    camera.position + camera.right() * right + camera.forward() * forward + camera.up() * up
+}
+
+/// 1 once a frame changes the image by less than `threshold`, and proportionally less before, as
+/// the change shrinks about as fast as frames are added.
+fn convergence(frame_change: f32, threshold: f32) -> f32 {
+    if frame_change <= threshold {
+        1.0
+    } else {
+        threshold / frame_change
+    }
+}
+
+/// Pixels of a `texture_size` render the kernel is dispatched over from `tile_offset`, those of
+/// the render region or the whole render.
+fn dispatch_size(scene_info: &SceneInfo, texture_size: (u32, u32)) -> (u32, u32) {
+    match scene_info.tile_end {
+        [0, _] => texture_size,
+        [x, y] => (x - scene_info.tile_offset[0], y - scene_info.tile_offset[1]),
+    }
+}
+
+/// Pixels of a `width` x `height` render under `rect`, when the viewport shows the render over
+/// `viewport`. `None` when they don't cover a pixel.
+fn viewport_to_region(
+    viewport: egui::Rect,
+    rect: egui::Rect,
+    (width, height): (u32, u32),
+) -> Option<Tile> {
+    let rect = rect.intersect(viewport);
+    let to_pixel = |pos: egui::Pos2| {
+        let x = (pos.x - viewport.min.x) / viewport.width() * width as f32;
+        let y = (pos.y - viewport.min.y) / viewport.height() * height as f32;
+        (
+            x.round().clamp(0.0, width as f32) as u32,
+            y.round().clamp(0.0, height as f32) as u32,
+        )
+    };
+    let (min_x, min_y) = to_pixel(rect.min);
+    let (max_x, max_y) = to_pixel(rect.max);
+    (max_x > min_x && max_y > min_y).then(|| Tile {
+        x: min_x,
+        y: min_y,
+        width: max_x - min_x,
+        height: max_y - min_y,
+    })
+}
+
+/// Inverse of `viewport_to_region`.
+fn region_to_viewport(
+    viewport: egui::Rect,
+    region: Tile,
+    (width, height): (u32, u32),
+) -> egui::Rect {
+    let to_viewport = |x: u32, y: u32| {
+        viewport.min
+            + egui::vec2(
+                x as f32 / width as f32 * viewport.width(),
+                y as f32 / height as f32 * viewport.height(),
+            )
+    };
+    egui::Rect::from_min_max(
+        to_viewport(region.x, region.y),
+        to_viewport(region.x + region.width, region.y + region.height),
+    )
+}
+
+/// Whether the accumulation of a `from` sized render can be reprojected to `to`, which isn't the
+/// case when the aspect ratio changes by more than `MAX_REPROJECTION_ASPECT_CHANGE`.
+fn can_reproject(from: (u32, u32), to: (u32, u32)) -> bool {
+    let aspect = |(width, height): (u32, u32)| width as f32 / height as f32;
+    let change = aspect(to) / aspect(from);
+    change.max(1.0 / change) <= MAX_REPROJECTION_ASPECT_CHANGE
+}
+
+/// Bilinearly resamples a `from` sized image to `to`, keeping what every pixel sees. The vertical
+/// field of view is fixed, so a wider render sees more on the sides, where the edges of `pixels`
+/// are repeated.
+fn reproject(pixels: &[[f32; 4]], from: (u32, u32), to: (u32, u32)) -> Vec<[f32; 4]> {
+    let (from_width, from_height) = (from.0 as usize, from.1 as usize);
+    // Horizontal screen positions scale with the aspect ratio around the center, see
+    // `primary_ray`
+    let aspect_scale = (to.0 as f32 / to.1 as f32) / (from.0 as f32 / from.1 as f32);
+    let texel = |x: usize, y: usize| pixels[y * from_width + x];
+
+    let mut reprojected = Vec::with_capacity(to.0 as usize * to.1 as usize);
+    for y in 0..to.1 {
+        let screen_y = (y as f32 + 0.5) / to.1 as f32;
+        let source_y = (screen_y * from.1 as f32 - 0.5).clamp(0.0, (from_height - 1) as f32);
+        let y0 = source_y as usize;
+        let y1 = (y0 + 1).min(from_height - 1);
+        for x in 0..to.0 {
+            let screen_x = ((x as f32 + 0.5) / to.0 as f32 - 0.5) * aspect_scale + 0.5;
+            let source_x = (screen_x * from.0 as f32 - 0.5).clamp(0.0, (from_width - 1) as f32);
+            let x0 = source_x as usize;
+            let x1 = (x0 + 1).min(from_width - 1);
+            let top = lerp(texel(x0, y0), texel(x1, y0), source_x.fract());
+            let bottom = lerp(texel(x0, y1), texel(x1, y1), source_x.fract());
+            reprojected.push(lerp(top, bottom, source_y.fract()));
+        }
+    }
+    reprojected
+}
+
+fn lerp(a: [f32; 4], b: [f32; 4], t: f32) -> [f32; 4] {
+    std::array::from_fn(|i| a[i] + (b[i] - a[i]) * t)
+}
+
+/// Moves the mesh so the center of its bounding box lands on `target`.
+fn center_mesh(triangles: &mut [Triangle], target: Vec3) {
+    if triangles.is_empty() {
+        return;
+    }
+    let vertices = || triangles.iter().flat_map(|t| [t.v0, t.v1, t.v2]);
+    let min = vertices().fold(Vec3::splat(f32::INFINITY), Vec3::min);
+    let max = vertices().fold(Vec3::splat(f32::NEG_INFINITY), Vec3::max);
+    let offset = target - (min + max) * 0.5;
+    for triangle in triangles {
+        for vertex in [&mut triangle.v0, &mut triangle.v1, &mut triangle.v2] {
+            *vertex = *vertex + offset;
+        }
+    }
+}
+
+fn default_camera() -> Camera {
+    let position = Vec3::new(2.0, 0.0, 0.0);
+    Camera::new(position, 0.0, 0.0)
+}
+
+/// The test scene the app starts with.
+fn default_scene() -> Scene {
+    let spheres = vec![
+        Sphere::new(
+            Vec3::new(10.0, 0.0, 1.0),
+            1.0,
+            Material {
+                albedo: Vec3::new(0.87, 0.87, 0.87),
+                is_mirror: 1,
+                camera_visible: 1,
+                clearcoat_strength: 0.0,
+                clearcoat_roughness: 0.0,
+                material_type: MaterialType::Diffuse as u32,
+                ior: 1.5,
+                roughness: 0.0,
+                checkerboard: 0,
+                metallic: 1.0,
+                emission: Default::default(),
+                emission_strength: 0.0,
+                checker_color: Default::default(),
+                checker_scale: 1.0,
+            },
+        ),
+        Sphere::new(
+            Vec3::new(7.3, -1.2, 1.02),
+            1.0,
+            Material {
+                albedo: Vec3::new(0.87, 0.87, 0.87),
+                is_mirror: 1,
+                camera_visible: 1,
+                clearcoat_strength: 0.0,
+                clearcoat_roughness: 0.0,
+                material_type: MaterialType::Diffuse as u32,
+                ior: 1.5,
+                roughness: 0.0,
+                checkerboard: 0,
+                metallic: 1.0,
+                emission: Default::default(),
+                emission_strength: 0.0,
+                checker_color: Default::default(),
+                checker_scale: 1.0,
+            },
+        ),
+        Sphere::new(
+            Vec3::new(9.0, 2.2, 1.03),
+            1.0,
+            Material {
+                albedo: Vec3::new(0.97, 0.97, 0.97),
+                is_mirror: 0,
+                camera_visible: 1,
+                clearcoat_strength: 0.0,
+                clearcoat_roughness: 0.0,
+                material_type: MaterialType::Diffuse as u32,
+                ior: 1.5,
+                roughness: 0.0,
+                checkerboard: 0,
+                metallic: 0.0,
+                emission: Default::default(),
+                emission_strength: 0.0,
+                checker_color: Default::default(),
+                checker_scale: 1.0,
+            },
+        ),
+        Sphere::new(
+            Vec3::new(10.0, 0.0, 102.0),
+            100.0,
+            Material {
+                albedo: Vec3::new(1.0, 0.5, 0.5),
+                is_mirror: 0,
+                camera_visible: 1,
+                clearcoat_strength: 0.0,
+                clearcoat_roughness: 0.0,
+                material_type: MaterialType::Diffuse as u32,
+                ior: 1.5,
+                roughness: 0.0,
+                checkerboard: 0,
+                metallic: 0.0,
+                emission: Default::default(),
+                emission_strength: 0.0,
+                checker_color: Default::default(),
+                checker_scale: 1.0,
+            },
+        ),
+    ];
+
+    Scene {
+        camera: default_camera(),
+        spheres,
+        planes: Vec::new(),
+        triangles: Vec::new(),
+        lights: Vec::new(),
+        settings: None,
+        groups: Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // A copy of the running average the kernel blends frames into the progressive buffer with,
+    // `frame_count` including the frame `sample` was traced in. Keep it in sync with `main` in
+    // `raytracer_kernel.wgsl`, which the headless renderer can't test as it doesn't accumulate.
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    fn scene_path(name: &str) -> PathBuf {
+        PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+            .join("scenes")
+            .join(name)
+    }
+
+    #[test]
+    fn viewport_maps_letterboxed_rect_to_unit_square() {
+        let rect = egui::Rect::from_min_size(egui::pos2(100.0, 20.0), egui::vec2(400.0, 200.0));
+
+        let center = viewport_to_screen(rect, egui::pos2(300.0, 120.0)).unwrap();
+        assert_eq!(center, Vec2 { x: 0.5, y: 0.5 });
+
+        let corner = viewport_to_screen(rect, egui::pos2(100.0, 20.0)).unwrap();
+        assert_eq!(corner, Vec2 { x: 0.0, y: 0.0 });
+
+        let subpixel = viewport_to_screen(rect, egui::pos2(100.5, 20.25)).unwrap();
+        assert_close(subpixel.x, 0.5 / 400.0);
+        assert_close(subpixel.y, 0.25 / 200.0);
+
+        assert_eq!(viewport_to_screen(rect, egui::pos2(99.0, 120.0)), None);
+        let empty = egui::Rect::NOTHING;
+        assert_eq!(viewport_to_screen(empty, egui::pos2(0.0, 0.0)), None);
+    }
+
+    #[test]
+    fn primary_ray_through_center_looks_forwards() {
+        let camera = Camera::default();
+        let ray = primary_ray(&camera, Vec2 { x: 0.5, y: 0.5 }, 2.0);
+        assert_eq!(ray.direction, Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn primary_ray_matches_kernel_at_corner() {
+        let camera = Camera::default();
+        let ray = primary_ray(&camera, Vec2 { x: 0.0, y: 0.0 }, 2.0);
+        // The default field of view makes the image plane one unit tall, and twice as wide
+        let length = (1.0f32 + 1.0 + 0.25).sqrt();
+        assert_close(ray.direction.x, 1.0 / length);
+        assert_close(ray.direction.y, 1.0 / length);
+        assert_close(ray.direction.z, -0.5 / length);
+    }
+
+    #[test]
+    fn projection_inverts_primary_rays() {
+        let camera = Camera::new(Vec3::new(1.0, 2.0, 3.0), 0.4, -0.3);
+        for (x, y) in [(0.5, 0.5), (0.1, 0.8), (1.2, -0.3)] {
+            let ray = primary_ray(&camera, Vec2 { x, y }, 1.5);
+            let projected = project(&camera, ray.origin + ray.direction * 4.0, 1.5).unwrap();
+            assert!((projected.x - x).abs() < 1e-5, "{projected:?}");
+            assert!((projected.y - y).abs() < 1e-5, "{projected:?}");
+        }
+        let behind = camera.position - camera.forward();
+        assert_eq!(project(&camera, behind, 1.5), None);
+    }
+
+    #[test]
+    fn primary_rays_keep_pixels_square_at_16_9() {
+        let camera = Camera::default();
+        let (width, height) = (1600.0, 900.0);
+        let angle_from_center = |screen: Vec2| {
+            let direction = primary_ray(&camera, screen, width / height).direction;
+            direction.x.acos()
+        };
+
+        // A sphere in front of the camera covers as many pixels across as it does vertically
+        let offset = 200.0;
+        let horizontal = angle_from_center(Vec2 {
+            x: 0.5 + offset / width,
+            y: 0.5,
+        });
+        let vertical = angle_from_center(Vec2 {
+            x: 0.5,
+            y: 0.5 + offset / height,
+        });
+        assert_close(horizontal, vertical);
+    }
+
+    // Both mirror `hit` in `raytracer_kernel.wgsl`, returning the near root.
+    #[test]
+    fn sphere_hits_are_precise_near_a_large_sphere() {
+        // Like the camera looking at the radius 100 floor sphere from 2 units away, where the
+        // textbook quadratic formula loses digits to cancellation
+        let sphere = Sphere::new(Vec3::default(), 100.0, Material::default());
+        for (origin, direction) in [
+            (Vec3::new(0.0, 0.0, 102.0), Vec3::new(0.0, 0.6, -0.8)),
+            (Vec3::new(0.0, 0.0, 100.5), Vec3::new(0.0, 0.6, -0.8)),
+            (Vec3::new(0.0, 0.0, 102.0), Vec3::new(0.0, 0.0, -1.0)),
+        ] {
+            let dot = |a: Vec3, b: Vec3| {
+                a.x as f64 * b.x as f64 + a.y as f64 * b.y as f64 + a.z as f64 * b.z as f64
+            };
+            let a = dot(direction, direction);
+            let half_b = dot(origin, direction);
+            let c = dot(origin, origin) - 100.0 * 100.0;
+            let expected = (-half_b - (half_b * half_b - a * c).sqrt()) / a;
+
+            let t = hit_sphere(Ray { origin, direction }, &sphere).unwrap() as f64;
+            assert!(
+                (t - expected).abs() / expected < 1e-6,
+                "{t} instead of {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn sphere_hits_are_in_front_of_the_ray() {
+        let sphere = Sphere::new(Vec3::default(), 1.0, Material::default());
+        let ray = |z| Ray {
+            origin: Vec3::new(0.0, 0.0, z),
+            direction: Vec3::new(0.0, 0.0, -1.0),
+        };
+        assert_eq!(hit_sphere(ray(5.0), &sphere), Some(4.0));
+        // From inside, the ray leaves through the far side
+        assert_eq!(hit_sphere(ray(0.0), &sphere), Some(1.0));
+        assert_eq!(hit_sphere(ray(-5.0), &sphere), None);
+    }
+
+    #[test]
+    fn sphere_hits_skip_grazing_rays_from_the_surface() {
+        let ray = Ray {
+            origin: Vec3::new(0.0, 0.0, 1.0),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let sphere = Sphere::new(Vec3::default(), 1.0, Material::default());
+        assert_eq!(hit_sphere(ray, &sphere), None);
+    }
+
+    #[test]
+    fn default_scene_file_matches_default_scene() {
+        let scene = Scene::load(&scene_path("default.ron")).unwrap();
+        let expected = default_scene();
+
+        assert_eq!(scene.spheres, expected.spheres);
+        assert_eq!(scene.planes, expected.planes);
+        assert_eq!(scene.settings, None);
+        assert_eq!(scene.camera.position, expected.camera.position);
+        assert_close(scene.camera.yaw(), expected.camera.yaw());
+        assert_close(scene.camera.pitch(), expected.camera.pitch());
+    }
+
+    #[test]
+    fn example_scenes_parse() {
+        let scene = Scene::load(&scene_path("glass_and_light.ron")).unwrap();
+
+        assert_eq!(scene.spheres.len(), 4);
+        assert_eq!(
+            scene.spheres[0].mat.material_type,
+            MaterialType::Dielectric as u32
+        );
+        assert_eq!(
+            scene.settings,
+            Some(RenderSettings {
+                samples_per_pixel: 16,
+                max_bounces: 50,
+            })
+        );
+
+        let scene = Scene::load(&scene_path("inside_sphere.ron")).unwrap();
+        let room = &scene.spheres[0];
+        assert!((scene.camera.position - room.position).length() < room.radius);
+        assert_eq!(scene.lights.len(), 1);
+    }
+
+    #[test]
+    fn sun_direction_follows_the_angles() {
+        let overhead = sun_direction(123.0, 90.0);
+        assert!(overhead.z > 0.999, "{overhead:?}");
+
+        let east = sun_direction(90.0, 0.0);
+        assert!(
+            east.x.abs() < 1e-6 && (east.y - 1.0).abs() < 1e-6,
+            "{east:?}"
+        );
+    }
+
+    #[test]
+    fn flying_moves_along_the_camera_basis() {
+        let camera = Camera::default();
+        let forward = fly_position(&camera, [0.0, 1.0, 0.0], 2.0);
+        assert_close(forward.x - camera.position.x, camera.forward().x * 2.0);
+        assert_close(forward.y - camera.position.y, camera.forward().y * 2.0);
+        assert_close(forward.z - camera.position.z, camera.forward().z * 2.0);
+
+        // Diagonals cover the same distance as straight moves
+        let diagonal = fly_position(&camera, [1.0, 1.0, 1.0], 2.0);
+        let moved = [
+            diagonal.x - camera.position.x,
+            diagonal.y - camera.position.y,
+            diagonal.z - camera.position.z,
+        ];
+        assert_close(moved.iter().map(|d| d * d).sum::<f32>().sqrt(), 2.0);
+
+        assert_eq!(fly_position(&camera, [0.0; 3], 2.0), camera.position);
+    }
+
+    #[test]
+    fn image_format_follows_the_extension() {
+        let format = |path: &str| ImageFormat::from_path(std::path::Path::new(path));
+        assert_eq!(format("render.exr"), ImageFormat::Exr);
+        assert_eq!(format("render.EXR"), ImageFormat::Exr);
+        assert_eq!(format("render.png"), ImageFormat::Png);
+        assert_eq!(format("render"), ImageFormat::Png);
+    }
+
+    #[test]
+    fn gbuffer_layers_are_saved_next_to_the_render() {
+        let path = std::path::Path::new("renders").join("frame.exr");
+        assert_eq!(
+            GBufferLayer::Depth.path(&path),
+            std::path::Path::new("renders").join("frame_depth.exr")
+        );
+        assert_eq!(
+            GBufferLayer::Albedo.path(std::path::Path::new("frame")),
+            PathBuf::from("frame_albedo")
+        );
+    }
+
+    #[test]
+    fn brackets_are_saved_next_to_the_render() {
+        let renders = std::path::Path::new("renders");
+        let bracket = |stop| bracket_path(&renders.join("frame.png"), stop);
+        assert_eq!(bracket(-2.0), renders.join("frame_-2ev.png"));
+        assert_eq!(bracket(0.0), renders.join("frame_+0ev.png"));
+        assert_eq!(bracket(1.5), renders.join("frame_+1.5ev.png"));
+    }
+
+    #[test]
+    fn gbuffer_layers_map_to_the_display_range() {
+        let mut depths = [[2.0, 2.0, 2.0, 1.0], [4.0, 4.0, 4.0, 1.0], [0.0; 4]];
+        GBufferLayer::Depth.to_display(&mut depths);
+        assert_eq!(
+            depths,
+            [[0.5, 0.5, 0.5, 1.0], [1.0, 1.0, 1.0, 1.0], [0.0; 4]]
+        );
+
+        let mut normals = [GBufferLayer::Normal.pixel([-1.0, 0.0, 1.0, 3.0], [0.0; 4])];
+        GBufferLayer::Normal.to_display(&mut normals);
+        assert_eq!(normals, [[0.0, 0.5, 1.0, 1.0]]);
+    }
+
+    #[test]
+    fn meshes_are_centered_on_the_target() {
+        let mut triangles = vec![
+            Triangle::new(
+                Vec3::new(0.0, 0.0, 0.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 4.0, 0.0),
+                Material::default(),
+            ),
+            Triangle::new(
+                Vec3::new(0.0, 0.0, 6.0),
+                Vec3::new(2.0, 0.0, 0.0),
+                Vec3::new(0.0, 4.0, 0.0),
+                Material::default(),
+            ),
+        ];
+        center_mesh(&mut triangles, Vec3::new(10.0, 10.0, 10.0));
+        assert_eq!(triangles[0].v0, Vec3::new(9.0, 8.0, 7.0));
+        assert_eq!(triangles[1].v0, Vec3::new(9.0, 8.0, 13.0));
+        assert_eq!(triangles[1].v2, Vec3::new(9.0, 12.0, 7.0));
+    }
+
+    #[test]
+    fn tone_mapping_stays_in_display_range() {
+        for tone_mapping in ToneMapping::ALL {
+            for value in [0.0, 0.25, 1.0, 4.0, 1000.0] {
+                for channel in tone_mapping.apply([value; 3]) {
+                    assert!(
+                        (0.0..=1.0).contains(&channel),
+                        "{tone_mapping:?}({value}) = {channel}"
+                    );
+                }
+            }
+        }
+        assert_close(ToneMapping::Reinhard.apply([1.0; 3])[0], 0.5);
+        assert_close(ToneMapping::None.apply([2.0; 3])[0], 1.0);
+    }
+
+    #[test]
+    fn middle_gray_is_encoded_as_srgb() {
+        // 50% sRGB gray is 21.4% linear
+        let linear = 0.214_041_14;
+        let gray = display_color([linear, linear, linear, 1.0], 0.0, ToneMapping::None);
+        assert!((gray[0] - 0.5).abs() < 1e-4, "{gray:?}");
+        assert_eq!((linear_to_srgb(0.5) * 255.0).round(), 188.0);
+        assert_close(linear_to_srgb(0.0), 0.0);
+        assert_close(linear_to_srgb(1.0), 1.0);
+    }
+
+    #[test]
+    fn display_color_keeps_premultiplied_alpha() {
+        let transparent = display_color([0.0; 4], 2.0, ToneMapping::Aces);
+        assert_eq!(transparent, [0.0; 4]);
+
+        // Half covered white is tone mapped and encoded as white, then premultiplied again
+        let half_covered = display_color([0.5, 0.5, 0.5, 0.5], 0.0, ToneMapping::Reinhard);
+        assert_close(half_covered[0], linear_to_srgb(0.5) * 0.5);
+        assert_close(half_covered[3], 0.5);
+
+        let exposed = display_color([0.5, 0.5, 0.5, 1.0], 1.0, ToneMapping::None);
+        assert_close(exposed[0], 1.0);
+    }
+
+    #[test]
+    fn render_regions_map_between_the_viewport_and_the_render() {
+        let viewport = egui::Rect::from_min_max(egui::pos2(10.0, 20.0), egui::pos2(410.0, 320.0));
+        let rect = egui::Rect::from_min_max(egui::pos2(110.0, 70.0), egui::pos2(210.0, 170.0));
+        let region = viewport_to_region(viewport, rect, (800, 600)).unwrap();
+        assert_eq!(
+            region,
+            Tile {
+                x: 200,
+                y: 100,
+                width: 200,
+                height: 200
+            }
+        );
+        assert_eq!(region_to_viewport(viewport, region, (800, 600)), rect);
+
+        // Clamped to the viewport, and empty when it covers no pixel
+        let overhanging = egui::Rect::from_min_max(egui::pos2(0.0, 0.0), egui::pos2(60.0, 45.0));
+        let clamped = viewport_to_region(viewport, overhanging, (800, 600)).unwrap();
+        assert_eq!(
+            (clamped.x, clamped.y, clamped.width, clamped.height),
+            (0, 0, 100, 50)
+        );
+        let sliver = egui::Rect::from_min_max(egui::pos2(50.0, 50.0), egui::pos2(50.1, 90.0));
+        assert_eq!(viewport_to_region(viewport, sliver, (800, 600)), None);
+    }
+
+    #[test]
+    fn render_regions_limit_the_dispatch() {
+        let mut scene_info = SceneInfo::default();
+        assert_eq!(dispatch_size(&scene_info, (800, 600)), (800, 600));
+        scene_info.tile_offset = [200, 100];
+        scene_info.tile_end = [400, 300];
+        assert_eq!(dispatch_size(&scene_info, (800, 600)), (200, 200));
+    }
+
+    #[test]
+    fn reprojection_keeps_close_aspect_ratios() {
+        assert!(can_reproject((800, 600), (800, 600)));
+        assert!(can_reproject((800, 600), (900, 600)));
+        assert!(can_reproject((800, 600), (800, 700)));
+        assert!(!can_reproject((800, 600), (1600, 600)));
+        assert!(!can_reproject((800, 600), (800, 1200)));
+    }
+
+    #[test]
+    fn reprojection_resamples_bilinearly() {
+        let pixels = [[0.0; 4], [1.0; 4], [2.0; 4], [3.0; 4]];
+        assert_eq!(reproject(&pixels, (2, 2), (2, 2)), pixels);
+
+        let upscaled = reproject(&[[0.0; 4], [1.0; 4]], (2, 1), (4, 2));
+        let row: Vec<f32> = upscaled[..4].iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(row, [0.0, 0.25, 0.75, 1.0]);
+        assert_eq!(upscaled[..4], upscaled[4..]);
+    }
+
+    #[test]
+    fn reprojection_widens_the_view() {
+        // Twice as wide at the same height sees twice as much horizontally, with the old image in
+        // the middle and its edges repeated on the sides
+        let pixels = [[0.0; 4], [1.0; 4]];
+        let widened = reproject(&pixels, (2, 1), (4, 1));
+        let row: Vec<f32> = widened.iter().map(|pixel| pixel[0]).collect();
+        assert_eq!(row, [0.0, 0.0, 1.0, 1.0]);
+    }
+
+    #[test]
+    fn resize_waits_for_a_stable_size() {
+        let start = Instant::now();
+        let mut debounce = ResizeDebounce::default();
+
+        assert!(!debounce.ready((800, 600), start));
+        assert!(!debounce.ready((810, 600), start + RESIZE_DEBOUNCE / 2));
+        // Still changing a moment ago, so the timer restarted
+        assert!(!debounce.ready((810, 600), start + RESIZE_DEBOUNCE));
+        assert!(debounce.ready((810, 600), start + RESIZE_DEBOUNCE * 3 / 2));
+
+        // The next change waits again
+        assert!(!debounce.ready((640, 480), start + RESIZE_DEBOUNCE * 2));
+    }
+
+    #[test]
+    fn display_settings_encode_srgb_only_for_linear_targets() {
+        let encode_srgb = |format| DisplaySettings::new(0.0, ToneMapping::None, format).encode_srgb;
+        assert_eq!(encode_srgb(wgpu::TextureFormat::Bgra8Unorm), 1);
+        assert_eq!(encode_srgb(wgpu::TextureFormat::Rgba8Unorm), 1);
+        assert_eq!(encode_srgb(wgpu::TextureFormat::Bgra8UnormSrgb), 0);
+        assert_eq!(encode_srgb(wgpu::TextureFormat::Rgba8UnormSrgb), 0);
+    }
+
+    #[test]
+    fn picking_finds_the_closest_visible_sphere() {
+        let sphere =
+            |x: f32, radius: f32| Sphere::new(Vec3::new(x, 0.0, 0.0), radius, Default::default());
+        let ray = Ray {
+            origin: Vec3::default(),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let mut scene = Scene {
+            spheres: vec![sphere(10.0, 1.0), sphere(5.0, 1.0), sphere(-5.0, 1.0)],
+            ..Default::default()
+        };
+        assert_eq!(pick_sphere(&scene, ray, 0.0), Some(1));
+        assert_close(hit_sphere(ray, &scene.spheres[1]).unwrap(), 4.0);
+
+        scene.spheres[1].mat.camera_visible = 0;
+        assert_eq!(pick_sphere(&scene, ray, 0.0), Some(0));
+
+        let up = Ray {
+            direction: Vec3::new(0.0, 0.0, 1.0),
+            ..ray
+        };
+        assert_eq!(pick_sphere(&scene, up, 0.0), None);
+
+        // From inside, the far side is hit
+        let inside = Scene {
+            spheres: vec![sphere(0.5, 2.0)],
+            ..Default::default()
+        };
+        assert_eq!(pick_sphere(&inside, ray, 0.0), Some(0));
+        assert_close(hit_sphere(ray, &inside.spheres[0]).unwrap(), 2.5);
+    }
+
+    #[test]
+    fn picking_follows_moving_spheres() {
+        let mut sphere = Sphere::new(Vec3::new(5.0, 0.0, 0.0), 1.0, Default::default());
+        sphere.velocity = Vec3::new(0.0, 0.0, 3.0);
+        let scene = Scene {
+            spheres: vec![sphere],
+            ..Default::default()
+        };
+        let ray = Ray {
+            origin: Vec3::default(),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        assert_eq!(pick_sphere(&scene, ray, 0.0), Some(0));
+        assert_eq!(pick_sphere(&scene, ray, 1.0), None);
+    }
+
+    #[test]
+    fn picking_stops_at_planes_and_triangles() {
+        let ray = Ray {
+            origin: Vec3::default(),
+            direction: Vec3::new(1.0, 0.0, 0.0),
+        };
+        let sphere = Sphere::new(Vec3::new(5.0, 0.0, 0.0), 1.0, Default::default());
+        let wall = Plane::new(
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(-1.0, 0.0, 0.0),
+            Default::default(),
+        );
+        let mut scene = Scene {
+            spheres: vec![sphere],
+            planes: vec![wall],
+            ..Default::default()
+        };
+        assert_close(hit_plane(ray, &scene.planes[0]).unwrap(), 2.0);
+        assert_eq!(pick_sphere(&scene, ray, 0.0), None);
+
+        // Unless the camera doesn't see them
+        scene.planes[0].mat.camera_visible = 0;
+        assert_eq!(pick_sphere(&scene, ray, 0.0), Some(0));
+
+        scene.triangles = vec![Triangle::new(
+            Vec3::new(3.0, -1.0, -1.0),
+            Vec3::new(3.0, 1.0, -1.0),
+            Vec3::new(3.0, 0.0, 1.0),
+            Default::default(),
+        )];
+        assert_close(hit_triangle(ray, &scene.triangles[0]).unwrap(), 3.0);
+        assert_eq!(pick_sphere(&scene, ray, 0.0), None);
+
+        // Behind the sphere, it doesn't hide it
+        scene.triangles[0] = Triangle::new(
+            Vec3::new(9.0, -1.0, -1.0),
+            Vec3::new(9.0, 1.0, -1.0),
+            Vec3::new(9.0, 0.0, 1.0),
+            Default::default(),
+        );
+        assert_eq!(pick_sphere(&scene, ray, 0.0), Some(0));
+    }
+
+    #[test]
+    fn session_survives_ron_round_trip() {
+        let mut scene = default_scene();
+        scene.camera.fov_y = 0.5;
+        let session = Session {
+            scene,
+            resolution: RenderResolution::FullHd,
+            preview_filter: PreviewFilter::Linear,
+            pixel_center: PixelCenter::Corner,
+            camera_mode: CameraMode::Fly,
+            fly_speed: 7.0,
+            background_mode: BackgroundMode::Color,
+            background_color: [0.1, 0.2, 0.3],
+            sky_top_color: to_rgb(DEFAULT_SKY_TOP_COLOR),
+            sky_bottom_color: to_rgb(DEFAULT_SKY_BOTTOM_COLOR),
+            sun_azimuth: 10.0,
+            sun_elevation: 20.0,
+            sun_color: [1.0, 0.9, 0.8],
+            sun_intensity: 2.0,
+            tone_mapping: ToneMapping::Aces,
+            exposure: -1.5,
+            accumulate: true,
+            reproject_on_resize: true,
+            stable_intersection: false,
+            jitter: true,
+            firefly_clamp: true,
+            max_sample_radiance: 20.0,
+            deterministic: true,
+            base_seed: 42,
+            denoise: false,
+            samples_per_pixel: 4,
+            max_bounces: 8,
+            max_frame_count: 256,
+            kernel_source: Some("@compute @workgroup_size(1) fn main() {}".to_owned()),
+        };
+        let text = ron::ser::to_string_pretty(&session, Default::default()).unwrap();
+        let parsed: Session = ron::from_str(&text).unwrap();
+        assert_eq!(parsed.resolution, session.resolution);
+        assert_eq!(parsed.preview_filter, session.preview_filter);
+        assert_eq!(parsed.kernel_source, session.kernel_source);
+        assert_eq!(parsed.tone_mapping, session.tone_mapping);
+        assert_eq!(parsed.exposure, session.exposure);
+        assert_eq!(parsed.scene.spheres, session.scene.spheres);
+        assert_close(parsed.scene.camera.fov_y, session.scene.camera.fov_y);
+    }
+
+    #[test]
+    fn convergence_reaches_one_at_the_threshold() {
+        assert_eq!(convergence(0.0, 1e-3), 1.0);
+        assert_eq!(convergence(1e-3, 1e-3), 1.0);
+        assert_close(convergence(4e-3, 1e-3), 0.25);
+        assert_eq!(
+            FrameChange {
+                sum: 32,
+                pixels: 4,
+                frame_count: 2
+            }
+            .mean(),
+            Some(0.5)
+        );
+        assert_eq!(FrameChange::default().mean(), None);
+    }
 }
@@ -1,6 +1,14 @@
 @group(0) @binding(0) var screen_sampler : sampler;
 @group(0) @binding(1) var color_buffer : texture_2d<f32>;
 
+struct DisplaySettings {
+    exposure: f32,
+    tone_mapping: u32,
+    encode_srgb: u32,
+}
+
+@group(0) @binding(2) var<uniform> display_settings : DisplaySettings;
+
 struct VertexOutput {
     @builtin(position) Position : vec4<f32>,
     @location(0) TexCoord : vec2<f32>,
@@ -36,5 +44,51 @@ fn vert_main(@builtin(vertex_index) VertexIndex : u32) -> VertexOutput {
 @fragment
 fn frag_main(@location(0) TexCoord : vec2<f32>) -> @location(0) vec4<f32> {
     let col: vec4<f32> = textureSample(color_buffer, screen_sampler, TexCoord);
-    return col;
+    if (col.a <= 0.0) {
+        return col;
+    }
+    // The color is premultiplied by the coverage, tone map the straight color
+    let exposed = col.rgb / col.a * exp2(display_settings.exposure);
+    var color = tone_map(exposed);
+    // The render is linear, sRGB surfaces encode it on write but others need it encoded here
+    if (display_settings.encode_srgb == 1u) {
+        color = linear_to_srgb(color);
+    }
+    return vec4<f32>(color * col.a, col.a);
+}
+
+// Must match `linear_to_srgb` in renderer.rs
+fn linear_to_srgb(color: vec3<f32>) -> vec3<f32> {
+    let low = color * 12.92;
+    let high = 1.055 * pow(color, vec3<f32>(1.0 / 2.4)) - 0.055;
+    return select(high, low, color <= vec3<f32>(0.0031308));
+}
+
+// Must match `ToneMapping::apply` in renderer.rs
+fn tone_map(color: vec3<f32>) -> vec3<f32> {
+    switch display_settings.tone_mapping {
+        case 1u: {
+            return reinhard(color);
+        }
+        case 2u: {
+            return aces(color);
+        }
+        default: {
+            return clamp(color, vec3<f32>(0.0), vec3<f32>(1.0));
+        }
+    }
+}
+
+fn reinhard(color: vec3<f32>) -> vec3<f32> {
+    return color / (vec3<f32>(1.0) + color);
+}
+
+// Krzysztof Narkowicz's fit of the ACES filmic curve
+fn aces(color: vec3<f32>) -> vec3<f32> {
+    let a = 2.51;
+    let b = 0.03;
+    let c = 2.43;
+    let d = 0.59;
+    let e = 0.14;
+    return clamp((color * (a * color + b)) / (color * (c * color + d) + e), vec3<f32>(0.0), vec3<f32>(1.0));
 }
@@ -0,0 +1,110 @@
+//! Renders a sequence of frames sweeping `SceneInfo.time`, written as numbered PNGs so they can be
+//! assembled into a video.
+
+use std::path::{Path, PathBuf};
+
+use crate::renderer::{to_rgba8, Renderer};
+use crate::scene::{RenderSettings, Scene};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Animation {
+    /// Time of the first frame, in seconds.
+    pub start_time: f32,
+    /// Time between the first and the last frame, in seconds.
+    pub duration: f32,
+    pub frame_count: u32,
+    /// Samples per pixel of every frame, replacing the scene's setting.
+    pub samples_per_frame: u32,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            start_time: 0.0,
+            duration: 2.0,
+            frame_count: 60,
+            samples_per_frame: 64,
+        }
+    }
+}
+
+impl Animation {
+    /// Time of frame `index`. The first and last frames land exactly on the start and end times.
+    pub fn time(&self, index: u32) -> f32 {
+        if self.frame_count <= 1 {
+            return self.start_time;
+        }
+        self.start_time + self.duration * index as f32 / (self.frame_count - 1) as f32
+    }
+
+    /// Path of frame `index` in `directory`, zero padded so the files sort in order.
+    pub fn frame_path(directory: &Path, index: u32) -> PathBuf {
+        directory.join(format!("frame_{index:05}.png"))
+    }
+
+    /// Renders every frame of `scene` and writes them to `directory`, calling `progress` with
+    /// the number of frames written so far after each one, and fractions of a frame as the tiles
+    /// of a tiled renderer are done.
+    pub fn render(
+        &self,
+        renderer: &mut Renderer,
+        scene: &Scene,
+        directory: &Path,
+        mut progress: impl FnMut(f32),
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let mut scene = scene.clone();
+        scene.settings = Some(RenderSettings {
+            samples_per_pixel: self.samples_per_frame,
+            ..scene.settings.unwrap_or_default()
+        });
+
+        for index in 0..self.frame_count {
+            let submission = renderer.submit_with_progress(&scene, self.time(index), |done, total| {
+                progress(index as f32 + done as f32 / total as f32);
+            });
+            let (view, dimensions) =
+                pollster::block_on(renderer.read_output(submission)).ok_or("Could not read back the frame")?;
+            let pixels = to_rgba8(&view, dimensions);
+            image::save_buffer(
+                Self::frame_path(directory, index),
+                &pixels,
+                dimensions.width as u32,
+                dimensions.height as u32,
+                image::ColorType::Rgba8,
+            )?;
+            progress((index + 1) as f32);
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frames_span_the_start_and_end_times() {
+        let animation = Animation {
+            start_time: 1.0,
+            duration: 2.0,
+            frame_count: 5,
+            ..Default::default()
+        };
+        assert_eq!(animation.time(0), 1.0);
+        assert_eq!(animation.time(2), 2.0);
+        assert_eq!(animation.time(4), 3.0);
+
+        let still = Animation {
+            frame_count: 1,
+            ..animation
+        };
+        assert_eq!(still.time(0), 1.0);
+    }
+
+    #[test]
+    fn frame_paths_sort_in_order() {
+        let directory = Path::new("frames");
+        assert_eq!(Animation::frame_path(directory, 7), directory.join("frame_00007.png"));
+        assert!(Animation::frame_path(directory, 9) < Animation::frame_path(directory, 10));
+    }
+}
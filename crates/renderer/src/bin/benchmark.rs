@@ -0,0 +1,175 @@
+//! Renders a fixed scene for a fixed number of frames without a window and prints how long they
+//! took, to compare the raytracer's performance between changes.
+//!
+//! ```text
+//! cargo run --release --features benchmark --bin benchmark -- --scene crates/app/scenes/default.ron
+//! ```
+//!
+//! Frame `i` is rendered at time `i`, which seeds its random numbers, so every run traces the
+//! same rays.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+use std::time::{Duration, Instant};
+
+use renderer::renderer::Renderer;
+use renderer::scene::Scene;
+
+const USAGE: &str =
+    "Usage: benchmark [--scene <path.ron>] [--width <pixels>] [--height <pixels>] [--frames <count>] [--warmup <count>]";
+
+struct Args {
+    scene: Option<PathBuf>,
+    width: usize,
+    height: usize,
+    frames: u32,
+    /// Frames rendered before timing starts, so shader compilation and caches don't skew it.
+    warmup: u32,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut parsed = Self {
+            scene: None,
+            width: 800,
+            height: 800,
+            frames: 100,
+            warmup: 5,
+        };
+
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {flag}"));
+            match flag.as_str() {
+                "--scene" => parsed.scene = Some(PathBuf::from(value()?)),
+                "--width" => parsed.width = parse_value(&flag, &value()?)?,
+                "--height" => parsed.height = parse_value(&flag, &value()?)?,
+                "--frames" => parsed.frames = parse_value(&flag, &value()?)?,
+                "--warmup" => parsed.warmup = parse_value(&flag, &value()?)?,
+                _ => return Err(format!("Unknown argument {flag}")),
+            }
+        }
+
+        if parsed.width == 0 || parsed.height == 0 {
+            return Err("The width and height must be at least 1".to_owned());
+        }
+        if parsed.frames == 0 {
+            return Err("At least 1 frame must be rendered".to_owned());
+        }
+        Ok(parsed)
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {flag}: {value}"))
+}
+
+#[derive(Debug, PartialEq)]
+struct Summary {
+    total: Duration,
+    mean: Duration,
+    min: Duration,
+    max: Duration,
+}
+
+impl Summary {
+    /// `None` without any frame times.
+    fn new(frame_times: &[Duration]) -> Option<Self> {
+        let min = *frame_times.iter().min()?;
+        let max = *frame_times.iter().max()?;
+        let total: Duration = frame_times.iter().sum();
+        Some(Self {
+            total,
+            mean: total / frame_times.len() as u32,
+            min,
+            max,
+        })
+    }
+}
+
+fn milliseconds(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let scene = match &args.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("Could not load {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Scene::default(),
+    };
+
+    let mut renderer = match pollster::block_on(Renderer::new(args.width, args.height)) {
+        Ok(renderer) => renderer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let adapter = renderer.adapter_info();
+    println!("Benchmarking on {} ({:?})", adapter.name, adapter.backend);
+
+    for frame in 0..args.warmup {
+        let index = renderer.submit(&scene, frame as f32);
+        renderer.wait(index);
+    }
+
+    let frame_times: Vec<Duration> = (0..args.frames)
+        .map(|frame| {
+            let start = Instant::now();
+            let index = renderer.submit(&scene, frame as f32);
+            renderer.wait(index);
+            start.elapsed()
+        })
+        .collect();
+
+    let summary = Summary::new(&frame_times).expect("At least 1 frame is rendered");
+    println!(
+        "{} frames of {}x{} in {:.3} s: {:.3} ms per frame (min {:.3} ms, max {:.3} ms)",
+        args.frames,
+        args.width,
+        args.height,
+        summary.total.as_secs_f64(),
+        milliseconds(summary.mean),
+        milliseconds(summary.min),
+        milliseconds(summary.max),
+    );
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn defaults_and_flags() {
+        let args = Args::parse(std::iter::empty()).unwrap();
+        assert_eq!((args.width, args.height, args.frames, args.warmup), (800, 800, 100, 5));
+
+        let args = Args::parse(["--frames", "10", "--warmup", "0"].map(String::from).into_iter()).unwrap();
+        assert_eq!((args.frames, args.warmup), (10, 0));
+        assert!(Args::parse(["--frames", "0"].map(String::from).into_iter()).is_err());
+    }
+
+    #[test]
+    fn summarizes_frame_times() {
+        let frame_times = [2, 4, 9].map(Duration::from_millis);
+        let summary = Summary::new(&frame_times).unwrap();
+        assert_eq!(summary.total, Duration::from_millis(15));
+        assert_eq!(summary.mean, Duration::from_millis(5));
+        assert_eq!(summary.min, Duration::from_millis(2));
+        assert_eq!(summary.max, Duration::from_millis(9));
+        assert_eq!(Summary::new(&[]), None);
+    }
+}
@@ -0,0 +1,220 @@
+//! Bounding volume hierarchy over the spheres, so the kernel doesn't test every sphere for every
+//! ray. It is built on the CPU and uploaded as two storage buffers: the nodes, and the sphere
+//! indices the leaves point into.
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::scene::{Sphere, Vec3};
+
+/// Leaves hold at most this many spheres.
+pub const MAX_LEAF_SIZE: usize = 2;
+
+/// An axis aligned box around a subtree, laid out like `BvhNode` in the kernel.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
+pub struct BvhNode {
+    pub min: Vec3,
+    /// First index into `Bvh::indices` of a leaf, or the left child of an inner node. The right
+    /// child always follows the left one.
+    pub left_or_first: u32,
+    pub max: Vec3,
+    /// Number of spheres in a leaf, 0 for inner nodes.
+    pub count: u32,
+}
+
+impl BvhNode {
+    pub fn is_leaf(&self) -> bool {
+        self.count > 0
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Bvh {
+    /// The root comes first. An empty scene still has a root so the buffer isn't empty, but the
+    /// kernel doesn't traverse it when there are no spheres.
+    pub nodes: Vec<BvhNode>,
+    /// Sphere indices, sorted so each leaf's spheres are contiguous.
+    pub indices: Vec<u32>,
+}
+
+impl Bvh {
+    /// Builds the hierarchy by splitting at the median sphere along the axis their centers spread
+    /// the most along. This keeps the tree balanced, so its depth stays logarithmic.
+    ///
+    /// Moving spheres are bounded wherever they are while the shutter is open, from time
+    /// `shutter.0` to `shutter.1`.
+    pub fn build(spheres: &[Sphere], shutter: (f32, f32)) -> Self {
+        let boxes: Vec<(Vec3, Vec3)> = spheres.iter().map(|sphere| swept_bounds(sphere, shutter)).collect();
+        let mut bvh = Self {
+            nodes: vec![BvhNode::default()],
+            indices: (0..spheres.len() as u32).collect(),
+        };
+        if !spheres.is_empty() {
+            bvh.subdivide(&boxes, 0, 0, spheres.len());
+        }
+        bvh
+    }
+
+    /// Most nodes a hierarchy over `sphere_count` spheres can have, to size its buffer.
+    pub fn max_node_count(sphere_count: usize) -> usize {
+        (2 * sphere_count).max(1)
+    }
+
+    fn subdivide(&mut self, boxes: &[(Vec3, Vec3)], node_index: usize, first: usize, count: usize) {
+        let indices = &mut self.indices[first..first + count];
+        let (min, max) = bounds(indices.iter().map(|&i| boxes[i as usize]));
+        self.nodes[node_index].min = min;
+        self.nodes[node_index].max = max;
+
+        if count <= MAX_LEAF_SIZE {
+            self.nodes[node_index].left_or_first = first as u32;
+            self.nodes[node_index].count = count as u32;
+            return;
+        }
+
+        let center = |i: u32| {
+            let (min, max) = boxes[i as usize];
+            (min + max) * 0.5
+        };
+        let (center_min, center_max) = bounds(indices.iter().map(|&i| (center(i), center(i))));
+        let extent = [
+            center_max.x - center_min.x,
+            center_max.y - center_min.y,
+            center_max.z - center_min.z,
+        ];
+        let axis = (0..3).fold(
+            0,
+            |widest, axis| if extent[axis] > extent[widest] { axis } else { widest },
+        );
+
+        let middle = count / 2;
+        indices.select_nth_unstable_by(middle, |&a, &b| {
+            component(center(a), axis).total_cmp(&component(center(b), axis))
+        });
+
+        let left = self.nodes.len();
+        self.nodes.push(BvhNode::default());
+        self.nodes.push(BvhNode::default());
+        self.nodes[node_index].left_or_first = left as u32;
+        self.nodes[node_index].count = 0;
+
+        self.subdivide(boxes, left, first, middle);
+        self.subdivide(boxes, left + 1, first + middle, count - middle);
+    }
+}
+
+fn component(v: Vec3, axis: usize) -> f32 {
+    match axis {
+        0 => v.x,
+        1 => v.y,
+        _ => v.z,
+    }
+}
+
+/// Box around `sphere` while the shutter is open. It moves in a straight line, so it is enough to
+/// bound where it starts and ends.
+fn swept_bounds(sphere: &Sphere, (open, close): (f32, f32)) -> (Vec3, Vec3) {
+    let radius = Vec3::splat(sphere.radius.abs());
+    let (start, end) = (sphere.position_at(open), sphere.position_at(close));
+    (start.min(end) - radius, start.max(end) + radius)
+}
+
+/// Smallest box around all the `(min, max)` boxes.
+fn bounds(boxes: impl Iterator<Item = (Vec3, Vec3)>) -> (Vec3, Vec3) {
+    boxes.fold(
+        (Vec3::splat(f32::INFINITY), Vec3::splat(f32::NEG_INFINITY)),
+        |(min, max), (box_min, box_max)| (min.min(box_min), max.max(box_max)),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sphere_grid(size: usize) -> Vec<Sphere> {
+        (0..size * size)
+            .map(|i| {
+                let position = Vec3::new((i % size) as f32 * 3.0, (i / size) as f32 * 3.0, 0.0);
+                Sphere::new(position, 1.0, Default::default())
+            })
+            .collect()
+    }
+
+    fn contains(node: &BvhNode, sphere: &Sphere) -> bool {
+        let (min, max) = (
+            sphere.position - Vec3::splat(sphere.radius),
+            sphere.position + Vec3::splat(sphere.radius),
+        );
+        node.min.x <= min.x
+            && node.min.y <= min.y
+            && node.min.z <= min.z
+            && node.max.x >= max.x
+            && node.max.y >= max.y
+            && node.max.z >= max.z
+    }
+
+    /// Sphere indices under `node_index`, checking every node bounds the spheres below it.
+    fn leaves(bvh: &Bvh, spheres: &[Sphere], node_index: usize, depth: usize) -> Vec<u32> {
+        assert!(depth < 32, "The kernel's traversal stack is 32 deep");
+        let node = &bvh.nodes[node_index];
+        let indices = if node.is_leaf() {
+            let first = node.left_or_first as usize;
+            assert!(node.count as usize <= MAX_LEAF_SIZE);
+            bvh.indices[first..first + node.count as usize].to_vec()
+        } else {
+            let left = node.left_or_first as usize;
+            let mut indices = leaves(bvh, spheres, left, depth + 1);
+            indices.extend(leaves(bvh, spheres, left + 1, depth + 1));
+            indices
+        };
+        for &i in &indices {
+            assert!(
+                contains(node, &spheres[i as usize]),
+                "Node {node_index} doesn't bound sphere {i}"
+            );
+        }
+        indices
+    }
+
+    #[test]
+    fn every_sphere_is_in_exactly_one_leaf() {
+        let spheres = sphere_grid(17);
+        let bvh = Bvh::build(&spheres, (0.0, 0.0));
+        assert!(bvh.nodes.len() <= Bvh::max_node_count(spheres.len()));
+
+        let mut indices = leaves(&bvh, &spheres, 0, 0);
+        indices.sort_unstable();
+        assert_eq!(indices, (0..spheres.len() as u32).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn small_scenes_are_a_single_leaf() {
+        let bvh = Bvh::build(&sphere_grid(1), (0.0, 0.0));
+        assert_eq!(bvh.nodes.len(), 1);
+        assert!(bvh.nodes[0].is_leaf());
+
+        let empty = Bvh::build(&[], (0.0, 0.0));
+        assert_eq!(empty.nodes.len(), 1);
+        assert!(empty.indices.is_empty());
+    }
+
+    #[test]
+    fn moving_spheres_are_bounded_while_the_shutter_is_open() {
+        let mut spheres = sphere_grid(5);
+        for (i, sphere) in spheres.iter_mut().enumerate() {
+            sphere.velocity = Vec3::new(i as f32, -2.0, 0.5);
+        }
+        let bvh = Bvh::build(&spheres, (1.0, 2.0));
+        for time in [1.0, 1.5, 2.0] {
+            let moved: Vec<Sphere> = spheres
+                .iter()
+                .map(|sphere| {
+                    let mut moved = *sphere;
+                    moved.position = sphere.position_at(time);
+                    moved
+                })
+                .collect();
+            leaves(&bvh, &moved, 0, 0);
+        }
+    }
+}
@@ -0,0 +1,234 @@
+//! Edge-avoiding À-Trous wavelet denoiser, from "Edge-Avoiding À-Trous Wavelet Transform for fast
+//! Global Illumination Filtering" by Dammertz et al.
+//!
+//! Each iteration blurs the image with a 5x5 kernel whose taps are spread twice as far apart as in
+//! the previous one, weighted down across edges found in the color and in the `GBuffer` the kernel
+//! writes when `SceneInfo::write_gbuffer` is set.
+//!
+//! The filter has its own bind group layout, separate from the kernel's:
+//! 0. the color to filter, read with `textureLoad`
+//! 1. the `Rgba16Float` storage texture the filtered color is written to
+//! 2. the `GBuffer` normals and depth
+//! 3. the `GBuffer` albedo
+//! 4. the `AtrousSettings` uniform of the iteration
+//!
+//! Every iteration has its own bind group and settings buffer. The first one reads the kernel's
+//! output, then they alternate between two textures the size of the render.
+
+use std::borrow::Cow;
+
+use bytemuck::{Pod, Zeroable};
+use wgpu::util::DeviceExt;
+
+use crate::raytracer::{self, workgroup_count, GBuffer};
+
+pub const ATROUS_SOURCE: &str = include_str!("shaders/atrous.wgsl");
+
+/// Number of filter iterations, the last one spreading its taps `2^(ITERATIONS - 1)` pixels apart.
+pub const ITERATIONS: u32 = 5;
+
+/// Weights of the edge stopping functions. Smaller values stop the blur at smaller differences.
+const COLOR_PHI: f32 = 0.5;
+const NORMAL_PHI: f32 = 0.1;
+/// Relative to the depth of the filtered pixel.
+const DEPTH_PHI: f32 = 0.05;
+const ALBEDO_PHI: f32 = 0.05;
+
+/// Laid out like `AtrousSettings` in the filter.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
+struct AtrousSettings {
+    step_width: i32,
+    color_phi: f32,
+    normal_phi: f32,
+    depth_phi: f32,
+    albedo_phi: f32,
+    unused_buffer: [u32; 3],
+}
+
+impl AtrousSettings {
+    /// Later iterations blur further but less across color differences, as the noise they would
+    /// preserve was already smoothed out by the previous ones.
+    fn for_iteration(iteration: u32) -> Self {
+        Self {
+            step_width: 1 << iteration,
+            color_phi: COLOR_PHI / (1 << iteration) as f32,
+            normal_phi: NORMAL_PHI,
+            depth_phi: DEPTH_PHI,
+            albedo_phi: ALBEDO_PHI,
+            ..Default::default()
+        }
+    }
+}
+
+pub struct Denoiser {
+    pipeline: wgpu::ComputePipeline,
+    /// One per iteration.
+    bind_groups: Vec<wgpu::BindGroup>,
+    /// Kept alive for the bind groups.
+    _settings_buffers: Vec<wgpu::Buffer>,
+    textures: [wgpu::Texture; 2],
+    views: [wgpu::TextureView; 2],
+}
+
+impl Denoiser {
+    /// Creates a denoiser filtering `input`, a `width` by `height` texture, guided by `gbuffer`.
+    pub fn new(device: &wgpu::Device, input: &wgpu::TextureView, gbuffer: &GBuffer, width: u32, height: u32) -> Self {
+        let bind_group_layout = create_bind_group_layout(device);
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            bind_group_layouts: &[&bind_group_layout],
+            ..Default::default()
+        });
+        let module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("atrous"),
+            source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(ATROUS_SOURCE)),
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("atrous"),
+            layout: Some(&pipeline_layout),
+            module: &module,
+            entry_point: "main",
+        });
+
+        let textures = [(); 2].map(|_| device.create_texture(&raytracer::storage_texture_descriptor(width, height)));
+        let views = [0, 1].map(|i| textures[i].create_view(&wgpu::TextureViewDescriptor::default()));
+
+        let settings_buffers: Vec<wgpu::Buffer> = (0..ITERATIONS)
+            .map(|iteration| {
+                device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: None,
+                    contents: bytemuck::cast_slice(&[AtrousSettings::for_iteration(iteration)]),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                })
+            })
+            .collect();
+
+        let bind_groups = (0..ITERATIONS as usize)
+            .map(|iteration| {
+                let input = match iteration {
+                    0 => input,
+                    _ => &views[(iteration - 1) % 2],
+                };
+                device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    label: None,
+                    layout: &bind_group_layout,
+                    entries: &[
+                        wgpu::BindGroupEntry {
+                            binding: 0,
+                            resource: wgpu::BindingResource::TextureView(input),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 1,
+                            resource: wgpu::BindingResource::TextureView(&views[iteration % 2]),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 2,
+                            resource: wgpu::BindingResource::TextureView(&gbuffer.normal_depth_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 3,
+                            resource: wgpu::BindingResource::TextureView(&gbuffer.albedo_view),
+                        },
+                        wgpu::BindGroupEntry {
+                            binding: 4,
+                            resource: settings_buffers[iteration].as_entire_binding(),
+                        },
+                    ],
+                })
+            })
+            .collect();
+
+        Self {
+            pipeline,
+            bind_groups,
+            _settings_buffers: settings_buffers,
+            textures,
+            views,
+        }
+    }
+
+    /// Texture the last iteration writes the denoised image to.
+    pub fn output(&self) -> &wgpu::Texture {
+        &self.textures[Self::output_index()]
+    }
+
+    pub fn output_view(&self) -> &wgpu::TextureView {
+        &self.views[Self::output_index()]
+    }
+
+    /// Both ping-pong textures, the output included.
+    pub fn textures(&self) -> &[wgpu::Texture; 2] {
+        &self.textures
+    }
+
+    fn output_index() -> usize {
+        (ITERATIONS as usize - 1) % 2
+    }
+
+    /// Records every iteration, in a single compute pass.
+    pub fn dispatch(&self, encoder: &mut wgpu::CommandEncoder, texture_size: (u32, u32)) {
+        let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor { label: Some("atrous") });
+        pass.set_pipeline(&self.pipeline);
+        for bind_group in &self.bind_groups {
+            pass.set_bind_group(0, bind_group, &[]);
+            pass.dispatch_workgroups(workgroup_count(texture_size.0), workgroup_count(texture_size.1), 1);
+        }
+    }
+}
+
+fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    let texture = |binding| wgpu::BindGroupLayoutEntry {
+        binding,
+        visibility: wgpu::ShaderStages::COMPUTE,
+        ty: wgpu::BindingType::Texture {
+            sample_type: wgpu::TextureSampleType::Float { filterable: false },
+            view_dimension: wgpu::TextureViewDimension::D2,
+            multisampled: false,
+        },
+        count: None,
+    };
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        label: Some("atrous"),
+        entries: &[
+            texture(0),
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: raytracer::OUTPUT_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            texture(2),
+            texture(3),
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+        ],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn iterations_spread_further_and_blur_colors_less() {
+        let settings: Vec<_> = (0..ITERATIONS).map(AtrousSettings::for_iteration).collect();
+        assert_eq!(settings[0].step_width, 1);
+        assert_eq!(settings[ITERATIONS as usize - 1].step_width, 16);
+        for pair in settings.windows(2) {
+            assert_eq!(pair[1].step_width, 2 * pair[0].step_width);
+            assert!(pair[1].color_phi < pair[0].color_phi);
+        }
+    }
+}
@@ -0,0 +1,143 @@
+//! Equirectangular environment maps, which rays that escape the scene sample instead of the sky
+//! when `SceneInfo::background_mode` is 3. Being the background of every bounce, they also light
+//! the scene.
+//!
+//! The map wraps around z, the up axis: u goes around from +x towards +y and v from +z down to -z.
+
+use std::path::Path;
+
+use half::f16;
+
+use crate::scene::Vec3;
+
+/// Format of the environment texture, which the kernel samples bilinearly.
+pub const ENVIRONMENT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// An HDR image in linear rgb, loaded on the CPU.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Environment {
+    pub width: u32,
+    pub height: u32,
+    /// Rows from the top, rgba per pixel.
+    pub pixels: Vec<[f32; 4]>,
+}
+
+impl Environment {
+    /// Loads a `.hdr` or `.exr` image, or any other format the `image` crate reads.
+    pub fn load(path: &Path) -> Result<Self, image::ImageError> {
+        let image = image::open(path)?.into_rgba32f();
+        Ok(Self {
+            width: image.width(),
+            height: image.height(),
+            pixels: image.pixels().map(|pixel| pixel.0).collect(),
+        })
+    }
+
+    /// Where `direction` lands on the map. Must match `environment_radiance` in the kernel.
+    pub fn uv(direction: Vec3) -> [f32; 2] {
+        let direction = direction.normalize();
+        let u = 0.5 + direction.y.atan2(direction.x) / std::f32::consts::TAU;
+        let v = direction.z.clamp(-1.0, 1.0).acos() / std::f32::consts::PI;
+        [u, v]
+    }
+}
+
+/// The environment texture and the sampler the kernel reads it with, see bindings 12 and 13.
+pub struct EnvironmentMap {
+    pub texture: wgpu::Texture,
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl EnvironmentMap {
+    /// Uploads `environment`, failing when it is larger than textures can be on `device`.
+    pub fn new(device: &wgpu::Device, queue: &wgpu::Queue, environment: &Environment) -> Result<Self, String> {
+        let max_size = device.limits().max_texture_dimension_2d;
+        if environment.width > max_size || environment.height > max_size {
+            return Err(format!(
+                "The environment is {} x {}, larger than the {max_size} x {max_size} the GPU supports",
+                environment.width, environment.height
+            ));
+        }
+
+        let map = Self::with_size(device, environment.width, environment.height);
+        // Brighter values, like the sun in some maps, would turn infinite as f16
+        let pixels: Vec<[f16; 4]> = environment
+            .pixels
+            .iter()
+            .map(|pixel| pixel.map(|channel| f16::from_f32(channel.min(f16::MAX.to_f32()))))
+            .collect();
+        queue.write_texture(
+            map.texture.as_image_copy(),
+            bytemuck::cast_slice(&pixels),
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: std::num::NonZeroU32::new(8 * environment.width),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d {
+                width: environment.width,
+                height: environment.height,
+                depth_or_array_layers: 1,
+            },
+        );
+        Ok(map)
+    }
+
+    /// Placeholder for when there is no environment. Textures start out zeroed, so it is black.
+    pub fn disabled(device: &wgpu::Device) -> Self {
+        Self::with_size(device, 1, 1)
+    }
+
+    fn with_size(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: None,
+            size: wgpu::Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: ENVIRONMENT_FORMAT,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+        // Wraps around the vertical axis, but not over the poles
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            ..Default::default()
+        });
+        Self {
+            view: texture.create_view(&Default::default()),
+            texture,
+            sampler,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_uv(direction: Vec3, expected: [f32; 2]) {
+        let [u, v] = Environment::uv(direction);
+        assert!(
+            (u - expected[0]).abs() < 1e-6 && (v - expected[1]).abs() < 1e-6,
+            "{u}, {v}"
+        );
+    }
+
+    #[test]
+    fn directions_map_around_the_up_axis() {
+        assert_uv(Vec3::new(1.0, 0.0, 0.0), [0.5, 0.5]);
+        assert_uv(Vec3::new(0.0, 2.0, 0.0), [0.75, 0.5]);
+        assert_uv(Vec3::new(0.0, -1.0, 0.0), [0.25, 0.5]);
+        assert_eq!(Environment::uv(Vec3::new(0.0, 0.0, 1.0))[1], 0.0);
+        assert_eq!(Environment::uv(Vec3::new(0.0, 0.0, -3.0))[1], 1.0);
+    }
+}
@@ -1 +1,8 @@
+pub mod animation;
+pub mod bvh;
+pub mod denoiser;
+pub mod environment;
+pub mod obj;
+pub mod raytracer;
 pub mod renderer;
+pub mod scene;
@@ -0,0 +1,193 @@
+//! Renders a single frame without a window and writes it to a PNG.
+//!
+//! ```text
+//! renderer --scene crates/app/scenes/default.ron --width 800 --height 600 --output frame.png
+//! ```
+//!
+//! Very large frames, like 7680 x 4320, can be rendered in tiles with `--tile-size 1024`.
+//!
+//! The PNG is the raytracer's output clamped to [0, 1], without the app's tone mapping.
+
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use renderer::renderer::{to_rgba8, Renderer};
+use renderer::scene::Scene;
+
+const USAGE: &str =
+    "Usage: renderer [--scene <path.ron>] [--width <pixels>] [--height <pixels>] [--time <seconds>] [--tile-size <pixels>] --output <path.png>";
+
+struct Args {
+    scene: Option<PathBuf>,
+    width: usize,
+    height: usize,
+    time: f32,
+    tile_size: Option<u32>,
+    output: PathBuf,
+}
+
+impl Args {
+    fn parse(mut args: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut scene = None;
+        let mut width = 800;
+        let mut height = 800;
+        let mut time = 0.0;
+        let mut tile_size = None;
+        let mut output = None;
+
+        while let Some(flag) = args.next() {
+            let mut value = || args.next().ok_or_else(|| format!("Missing value for {flag}"));
+            match flag.as_str() {
+                "--scene" => scene = Some(PathBuf::from(value()?)),
+                "--width" => width = parse_value(&flag, &value()?)?,
+                "--height" => height = parse_value(&flag, &value()?)?,
+                "--time" => time = parse_value(&flag, &value()?)?,
+                "--tile-size" => tile_size = Some(parse_value(&flag, &value()?)?),
+                "--output" => output = Some(PathBuf::from(value()?)),
+                _ => return Err(format!("Unknown argument {flag}")),
+            }
+        }
+
+        if width == 0 || height == 0 {
+            return Err("The width and height must be at least 1".to_owned());
+        }
+        if tile_size == Some(0) {
+            return Err("The tile size must be at least 1".to_owned());
+        }
+
+        Ok(Self {
+            scene,
+            width,
+            height,
+            time,
+            tile_size,
+            output: output.ok_or("Missing --output")?,
+        })
+    }
+}
+
+fn parse_value<T: std::str::FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value.parse().map_err(|_| format!("Invalid value for {flag}: {value}"))
+}
+
+fn main() -> ExitCode {
+    let args = match Args::parse(std::env::args().skip(1)) {
+        Ok(args) => args,
+        Err(err) => {
+            eprintln!("{err}\n{USAGE}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let scene = match &args.scene {
+        Some(path) => match Scene::load(path) {
+            Ok(scene) => scene,
+            Err(err) => {
+                eprintln!("Could not load {}: {err}", path.display());
+                return ExitCode::FAILURE;
+            }
+        },
+        None => Scene::default(),
+    };
+
+    let mut renderer = match pollster::block_on(Renderer::new(args.width, args.height)) {
+        Ok(renderer) => renderer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let adapter = renderer.adapter_info();
+    eprintln!("Rendering on {} ({:?})", adapter.name, adapter.backend);
+    renderer.set_tile_size(args.tile_size);
+    let index = renderer.submit_with_progress(&scene, args.time, |done, total| {
+        eprint!("\rTile {done} of {total} done");
+    });
+    if args.tile_size.is_some() {
+        eprintln!();
+    }
+    let Some((view, dimensions)) = pollster::block_on(renderer.read_output(index)) else {
+        eprintln!("Could not read back the frame");
+        return ExitCode::FAILURE;
+    };
+
+    let pixels = to_rgba8(&view, dimensions);
+    let result = image::save_buffer(
+        &args.output,
+        &pixels,
+        dimensions.width as u32,
+        dimensions.height as u32,
+        image::ColorType::Rgba8,
+    );
+    if let Err(err) = result {
+        eprintln!("Could not write {}: {err}", args.output.display());
+        return ExitCode::FAILURE;
+    }
+
+    ExitCode::SUCCESS
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use renderer::renderer::BufferDimensions;
+
+    fn args(args: &[&str]) -> Result<Args, String> {
+        Args::parse(args.iter().map(|arg| arg.to_string()))
+    }
+
+    #[test]
+    fn parses_every_flag() {
+        let args = args(&[
+            "--scene",
+            "scene.ron",
+            "--width",
+            "64",
+            "--height",
+            "32",
+            "--time",
+            "1.5",
+            "--tile-size",
+            "256",
+            "--output",
+            "out.png",
+        ])
+        .unwrap();
+
+        assert_eq!(args.scene, Some(PathBuf::from("scene.ron")));
+        assert_eq!(args.width, 64);
+        assert_eq!(args.height, 32);
+        assert_eq!(args.time, 1.5);
+        assert_eq!(args.tile_size, Some(256));
+        assert_eq!(args.output, PathBuf::from("out.png"));
+    }
+
+    #[test]
+    fn rejects_missing_output_and_bad_values() {
+        assert!(args(&["--width", "64"]).is_err());
+        assert!(args(&["--width", "wide", "--output", "out.png"]).is_err());
+        assert!(args(&["--output"]).is_err());
+        assert!(args(&["--tile-size", "0", "--output", "out.png"]).is_err());
+        assert!(args(&["--verbose", "--output", "out.png"]).is_err());
+    }
+
+    #[test]
+    fn drops_row_padding() {
+        // 2 pixels of 8 bytes, padded to a 256 byte row
+        let dimensions = BufferDimensions {
+            width: 2,
+            height: 2,
+            unpadded_bytes_per_row: 16,
+            padded_bytes_per_row: 256,
+        };
+        let mut data = vec![0u8; 512];
+        let white = [half::f16::ONE; 4];
+        data[256 + 8..256 + 16].copy_from_slice(bytemuck::cast_slice(&white));
+
+        let pixels = to_rgba8(&data, dimensions);
+
+        assert_eq!(pixels.len(), 2 * 2 * 4);
+        assert_eq!(&pixels[..12], &[0; 12]);
+        assert_eq!(&pixels[12..], &[255; 4]);
+    }
+}
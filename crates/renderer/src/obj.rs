@@ -0,0 +1,130 @@
+//! A minimal Wavefront OBJ reader, only keeping the vertex positions and faces.
+//!
+//! Faces with more than three vertices are triangulated as fans, so they should be convex.
+//! Texture coordinates, normals, groups and materials are ignored.
+
+use std::fmt;
+
+use crate::scene::{Material, Triangle, Vec3};
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ObjError {
+    /// 1-based, like editors show it.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl std::error::Error for ObjError {}
+
+/// Reads the triangles of an `.obj` file, all with the material `mat`.
+pub fn load(path: &std::path::Path, mat: Material) -> Result<Vec<Triangle>, Box<dyn std::error::Error>> {
+    let contents = std::fs::read_to_string(path)?;
+    Ok(parse(&contents, mat)?)
+}
+
+pub fn parse(source: &str, mat: Material) -> Result<Vec<Triangle>, ObjError> {
+    let mut vertices = Vec::new();
+    let mut triangles = Vec::new();
+    for (index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError {
+            line: index + 1,
+            message,
+        };
+        let mut tokens = line.split_whitespace();
+        match tokens.next() {
+            Some("v") => {
+                let mut coordinates = [0.0; 3];
+                for coordinate in &mut coordinates {
+                    let token = tokens
+                        .next()
+                        .ok_or_else(|| error("Expected 3 coordinates".to_string()))?;
+                    *coordinate = token
+                        .parse()
+                        .map_err(|_| error(format!("Invalid coordinate {token:?}")))?;
+                }
+                let [x, y, z] = coordinates;
+                vertices.push(Vec3::new(x, y, z));
+            }
+            Some("f") => {
+                let face = tokens
+                    .map(|token| vertex_index(token, vertices.len()).map(|i| vertices[i]))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                if face.len() < 3 {
+                    return Err(error(format!("Faces need at least 3 vertices, got {}", face.len())));
+                }
+                for i in 1..face.len() - 1 {
+                    triangles.push(Triangle::new(face[0], face[i], face[i + 1], mat));
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(triangles)
+}
+
+/// Resolves a face vertex like `3`, `3/1`, `3//2` or `-1` to an index into the vertices read so
+/// far. Indices are 1-based, negative ones count back from the last vertex.
+fn vertex_index(token: &str, vertex_count: usize) -> Result<usize, String> {
+    let position = token.split('/').next().unwrap_or_default();
+    let index: i64 = position
+        .parse()
+        .map_err(|_| format!("Invalid vertex index {token:?}"))?;
+    let resolved = match index {
+        0 => None,
+        1.. => Some(index - 1),
+        _ => Some(vertex_count as i64 + index),
+    };
+    resolved
+        .filter(|&i| (0..vertex_count as i64).contains(&i))
+        .map(|i| i as usize)
+        .ok_or_else(|| format!("Vertex index {index} is out of range, {vertex_count} vertices were defined"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const QUAD: &str = "
+# A unit square
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vn 0 0 1
+f 1//1 2//1 3//1 4//1
+";
+
+    #[test]
+    fn quads_are_split_into_two_triangles() {
+        let triangles = parse(QUAD, Material::default()).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[0].v0, Vec3::new(0.0, 0.0, 0.0));
+        assert_eq!(triangles[0].v2, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(triangles[1].v1, Vec3::new(1.0, 1.0, 0.0));
+        assert_eq!(triangles[1].v2, Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn negative_indices_count_from_the_end() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nf -3/1 -2/2 -1/3\n";
+        let triangles = parse(source, Material::default()).unwrap();
+        assert_eq!(triangles.len(), 1);
+        assert_eq!(triangles[0].v1, Vec3::new(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn errors_point_at_the_line() {
+        let error = parse("v 0 0 0\nv 1 0 0\nf 1 2 3\n", Material::default()).unwrap_err();
+        assert_eq!(error.line, 3);
+
+        let error = parse("v 0 zero 0\n", Material::default()).unwrap_err();
+        assert_eq!(error.line, 1);
+    }
+}
@@ -0,0 +1,876 @@
+//! The compute raytracer shared by the app and the headless `Renderer`.
+//!
+//! The kernel's bindings are:
+//! 0. the `Rgba16Float` storage texture it writes to
+//! 1. the `SceneInfo` uniform
+//! 2. the spheres
+//! 3. the previous frame, copied from the texture, which the kernel blends with when accumulating
+//! 4. the planes
+//! 5. the nodes of the sphere `Bvh`
+//! 6. the sphere indices the `Bvh` leaves point into
+//! 7. the triangles
+//! 8. the `GBuffer` normals and depth
+//! 9. the `GBuffer` albedo
+//! 10. the point lights
+//! 11. the `FrameChange` of the running average, summed over the pixels
+//! 12. the `EnvironmentMap` texture
+//! 13. the `EnvironmentMap` sampler
+
+use bytemuck::{Pod, Zeroable};
+use std::borrow::Cow;
+
+use crate::bvh::{Bvh, BvhNode};
+use crate::environment::EnvironmentMap;
+use crate::scene::{Plane, PointLight, Scene, Sphere, Triangle, Vec3};
+
+pub const KERNEL_SOURCE: &str = include_str!("shaders/raytracer_kernel.wgsl");
+/// File name of the kernel, naming it in `ShaderError`s.
+pub const KERNEL_NAME: &str = "raytracer_kernel.wgsl";
+/// Where the kernel's source is on disk, for reloading it while it is being edited.
+pub const KERNEL_PATH: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders/raytracer_kernel.wgsl");
+
+/// Format of the texture the kernel writes to.
+pub const OUTPUT_FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba16Float;
+
+/// Backends used when `WGPU_BACKEND` isn't set. These are eframe's defaults, so the app and the
+/// headless renderer pick the same adapter.
+pub const DEFAULT_BACKENDS: wgpu::Backends = wgpu::Backends::PRIMARY.union(wgpu::Backends::GL);
+
+/// The backends listed in the `WGPU_BACKEND` environment variable, like `vulkan,metal`, or
+/// `DEFAULT_BACKENDS`.
+pub fn backends_from_env() -> wgpu::Backends {
+    wgpu::util::backend_bits_from_env().unwrap_or(DEFAULT_BACKENDS)
+}
+
+/// Power preference used when `WGPU_POWER_PREF` isn't set, the discrete GPU on laptops that have
+/// one.
+pub const DEFAULT_POWER_PREFERENCE: wgpu::PowerPreference = wgpu::PowerPreference::HighPerformance;
+
+/// The power preference from the `WGPU_POWER_PREF` environment variable, `low` for the integrated
+/// GPU or `high`, or `DEFAULT_POWER_PREFERENCE`.
+pub fn power_preference_from_env() -> wgpu::PowerPreference {
+    wgpu::util::power_preference_from_env().unwrap_or(DEFAULT_POWER_PREFERENCE)
+}
+
+/// Width and height of the raytracer's workgroups, see `@workgroup_size` in the kernel.
+pub const WORKGROUP_SIZE: u32 = 8;
+
+/// Number of workgroups covering `pixels` pixels, the last one overhanging if needed.
+pub fn workgroup_count(pixels: u32) -> u32 {
+    pixels.div_ceil(WORKGROUP_SIZE)
+}
+
+/// Width and height of the kernel's workgroups. The kernel declares `WORKGROUP_SIZE` a side, other
+/// sizes are substituted into its source by `with_workgroup_size` to find the fastest on a GPU.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct WorkgroupSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for WorkgroupSize {
+    fn default() -> Self {
+        Self {
+            width: WORKGROUP_SIZE,
+            height: WORKGROUP_SIZE,
+        }
+    }
+}
+
+impl WorkgroupSize {
+    /// Sizes worth comparing, all within the 256 invocations every device supports.
+    pub const PRESETS: [WorkgroupSize; 7] = [
+        WorkgroupSize::new(4, 4),
+        WorkgroupSize::new(8, 4),
+        WorkgroupSize::new(8, 8),
+        WorkgroupSize::new(16, 8),
+        WorkgroupSize::new(16, 16),
+        WorkgroupSize::new(32, 1),
+        WorkgroupSize::new(64, 1),
+    ];
+
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    /// Workgroups covering a `width` x `height` texture, the last ones overhanging if needed.
+    pub fn counts(&self, (width, height): (u32, u32)) -> (u32, u32) {
+        (width.div_ceil(self.width), height.div_ceil(self.height))
+    }
+}
+
+impl std::fmt::Display for WorkgroupSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} x {}", self.width, self.height)
+    }
+}
+
+/// `source` with its first `@workgroup_size` replaced by `size`. WGSL sizes are constants and wgpu
+/// can't override them when creating the pipeline, so the text is edited instead. A source that
+/// declares none is returned as is.
+pub fn with_workgroup_size(source: &str, size: WorkgroupSize) -> Cow<'_, str> {
+    const ATTRIBUTE: &str = "@workgroup_size(";
+    let Some(start) = source.find(ATTRIBUTE).map(|start| start + ATTRIBUTE.len()) else {
+        return Cow::Borrowed(source);
+    };
+    let Some(length) = source[start..].find(')') else {
+        return Cow::Borrowed(source);
+    };
+    Cow::Owned(format!(
+        "{}{}, {}, 1{}",
+        &source[..start],
+        size.width,
+        size.height,
+        &source[start + length..]
+    ))
+}
+
+/// A rectangle of the render dispatched on its own, see `tiles`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// Splits a `width` x `height` render into tiles, row by row, so a large frame can be dispatched
+/// over several submissions. Tiles are at most `tile_size` pixels a side, rounded up to whole
+/// workgroups so only the tiles on the edges overhang the texture.
+pub fn tiles(width: u32, height: u32, tile_size: u32) -> impl Iterator<Item = Tile> {
+    let tile_size = workgroup_count(tile_size.max(1)) * WORKGROUP_SIZE;
+    (0..height).step_by(tile_size as usize).flat_map(move |y| {
+        (0..width).step_by(tile_size as usize).map(move |x| Tile {
+            x,
+            y,
+            width: tile_size.min(width - x),
+            height: tile_size.min(height - y),
+        })
+    })
+}
+
+/// Row stride of an `OUTPUT_FORMAT` texture copied to a buffer, which is also the stride the
+/// kernel reads the previous frame with.
+pub fn get_padded_bytes_per_row_from_width(width: u32) -> u32 {
+    let unpadded_bytes_per_row = 8 * width; // Rgba16Float
+    let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    unpadded_bytes_per_row + (align - unpadded_bytes_per_row % align) % align
+}
+
+pub fn storage_texture_descriptor<'a>(width: u32, height: u32) -> wgpu::TextureDescriptor<'a> {
+    wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: OUTPUT_FORMAT,
+        usage: wgpu::TextureUsages::STORAGE_BINDING
+            | wgpu::TextureUsages::RENDER_ATTACHMENT
+            | wgpu::TextureUsages::TEXTURE_BINDING
+            | wgpu::TextureUsages::COPY_SRC,
+        label: None,
+        view_formats: &[],
+    }
+}
+
+/// How much a frame changed the running average, in units of `1 / FRAME_CHANGE_SCALE`.
+pub const FRAME_CHANGE_SCALE: f32 = 16.0;
+
+/// Sum of how much each pixel's running average changed in a frame, see binding 11. The kernel
+/// only adds to it, so it must be cleared before every frame that should be measured.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct FrameChange {
+    /// Mean absolute change of the rgb channels times `FRAME_CHANGE_SCALE`, summed over the pixels.
+    pub sum: u32,
+    /// Pixels blended with a previous frame, the first frame after a reset has none.
+    pub pixels: u32,
+    /// The frame the change was measured on.
+    pub frame_count: u32,
+}
+
+impl FrameChange {
+    /// Average change of a channel of a pixel, `None` when no pixel was blended.
+    pub fn mean(&self) -> Option<f32> {
+        (self.pixels > 0).then(|| self.sum as f32 / (self.pixels as f32 * FRAME_CHANGE_SCALE))
+    }
+}
+
+pub fn create_frame_change_buffer(device: &wgpu::Device) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: std::mem::size_of::<FrameChange>() as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+/// First hit of every pixel, written by the kernel when `SceneInfo::write_gbuffer` is set. The
+/// kernel always binds the textures, so they are 1x1 when nothing writes them.
+pub struct GBuffer {
+    /// World normal facing the camera in rgb, distance along the camera ray in alpha. Misses are 0.
+    pub normal_depth: wgpu::Texture,
+    pub normal_depth_view: wgpu::TextureView,
+    pub albedo: wgpu::Texture,
+    pub albedo_view: wgpu::TextureView,
+}
+
+impl GBuffer {
+    pub fn new(device: &wgpu::Device, width: u32, height: u32) -> Self {
+        let normal_depth = device.create_texture(&storage_texture_descriptor(width, height));
+        let albedo = device.create_texture(&storage_texture_descriptor(width, height));
+        Self {
+            normal_depth_view: normal_depth.create_view(&Default::default()),
+            normal_depth,
+            albedo_view: albedo.create_view(&Default::default()),
+            albedo,
+        }
+    }
+
+    /// Placeholder textures for when the kernel doesn't write the G-buffer.
+    pub fn disabled(device: &wgpu::Device) -> Self {
+        Self::new(device, 1, 1)
+    }
+}
+
+pub fn create_storage_buffer(device: &wgpu::Device, size: usize) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: size as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+/// Buffers sized for the `Bvh` of `sphere_capacity` spheres, see bindings 5 and 6.
+pub fn create_bvh_buffers(device: &wgpu::Device, sphere_capacity: usize) -> (wgpu::Buffer, wgpu::Buffer) {
+    let nodes = create_storage_buffer(
+        device,
+        std::mem::size_of::<BvhNode>() * Bvh::max_node_count(sphere_capacity),
+    );
+    // Storage buffers can't be empty
+    let indices = create_storage_buffer(device, std::mem::size_of::<u32>() * sphere_capacity.max(1));
+    (nodes, indices)
+}
+
+/// How many primitives of each kind `SceneBuffers` have room for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SceneCapacity {
+    pub spheres: usize,
+    pub planes: usize,
+    pub triangles: usize,
+    pub lights: usize,
+}
+
+/// The primitives of a scene on the GPU, see bindings 2, 4, 5, 6, 7 and 10. They are kept between
+/// frames and written only where the scene changed, so moving a sphere doesn't upload every mesh.
+pub struct SceneBuffers {
+    pub spheres: wgpu::Buffer,
+    pub planes: wgpu::Buffer,
+    pub bvh_nodes: wgpu::Buffer,
+    pub bvh_indices: wgpu::Buffer,
+    pub triangles: wgpu::Buffer,
+    pub lights: wgpu::Buffer,
+    capacity: SceneCapacity,
+    /// Sphere centers when the shutter opens and closes, and radii, the uploaded BVH was built from.
+    /// `None` when the BVH buffers don't hold an up to date one.
+    bvh_geometry: Option<Vec<(Vec3, Vec3, f32)>>,
+}
+
+impl SceneBuffers {
+    pub fn new(device: &wgpu::Device, capacity: SceneCapacity) -> Self {
+        // Storage buffers can't be empty
+        let (bvh_nodes, bvh_indices) = create_bvh_buffers(device, capacity.spheres);
+        Self {
+            spheres: create_storage_buffer(device, std::mem::size_of::<Sphere>() * capacity.spheres.max(1)),
+            planes: create_storage_buffer(device, std::mem::size_of::<Plane>() * capacity.planes.max(1)),
+            bvh_nodes,
+            bvh_indices,
+            triangles: create_storage_buffer(device, std::mem::size_of::<Triangle>() * capacity.triangles.max(1)),
+            lights: create_storage_buffer(device, std::mem::size_of::<PointLight>() * capacity.lights.max(1)),
+            capacity,
+            bvh_geometry: None,
+        }
+    }
+
+    pub fn capacity(&self) -> SceneCapacity {
+        self.capacity
+    }
+
+    /// Grows the buffers `scene` doesn't fit in to the next power of two, so a growing scene rarely
+    /// reallocates. Returns whether a buffer was replaced, which loses its contents and must be
+    /// bound again.
+    pub fn reserve(&mut self, device: &wgpu::Device, scene: &Scene) -> bool {
+        let grown = |capacity: usize, count: usize| {
+            if count > capacity {
+                count.next_power_of_two()
+            } else {
+                capacity
+            }
+        };
+        let capacity = SceneCapacity {
+            spheres: grown(self.capacity.spheres, scene.spheres.len()),
+            planes: grown(self.capacity.planes, scene.planes.len()),
+            triangles: grown(self.capacity.triangles, scene.triangles.len()),
+            lights: grown(self.capacity.lights, scene.lights.len()),
+        };
+        if capacity == self.capacity {
+            return false;
+        }
+
+        // The BVH buffers are sized for as many spheres as the sphere buffer
+        if capacity.spheres != self.capacity.spheres {
+            self.spheres = create_storage_buffer(device, std::mem::size_of::<Sphere>() * capacity.spheres);
+            (self.bvh_nodes, self.bvh_indices) = create_bvh_buffers(device, capacity.spheres);
+            self.bvh_geometry = None;
+        }
+        if capacity.planes != self.capacity.planes {
+            self.planes = create_storage_buffer(device, std::mem::size_of::<Plane>() * capacity.planes);
+        }
+        if capacity.triangles != self.capacity.triangles {
+            self.triangles = create_storage_buffer(device, std::mem::size_of::<Triangle>() * capacity.triangles);
+        }
+        if capacity.lights != self.capacity.lights {
+            self.lights = create_storage_buffer(device, std::mem::size_of::<PointLight>() * capacity.lights);
+        }
+        self.capacity = capacity;
+        true
+    }
+
+    /// Overwrites sphere `index` alone. The BVH follows once `write_bvh` is called.
+    pub fn write_sphere(&self, queue: &wgpu::Queue, index: usize, sphere: &Sphere) {
+        let offset = (std::mem::size_of::<Sphere>() * index) as u64;
+        queue.write_buffer(&self.spheres, offset, bytemuck::bytes_of(sphere));
+    }
+
+    pub fn write_spheres(&self, queue: &wgpu::Queue, spheres: &[Sphere]) {
+        queue.write_buffer(&self.spheres, 0, bytemuck::cast_slice(spheres));
+    }
+
+    pub fn write_planes(&self, queue: &wgpu::Queue, planes: &[Plane]) {
+        queue.write_buffer(&self.planes, 0, bytemuck::cast_slice(planes));
+    }
+
+    pub fn write_triangles(&self, queue: &wgpu::Queue, triangles: &[Triangle]) {
+        queue.write_buffer(&self.triangles, 0, bytemuck::cast_slice(triangles));
+    }
+
+    pub fn write_lights(&self, queue: &wgpu::Queue, lights: &[PointLight]) {
+        queue.write_buffer(&self.lights, 0, bytemuck::cast_slice(lights));
+    }
+
+    /// Rebuilds and uploads the BVH of `spheres` if they moved, were resized or the shutter changed
+    /// since it was last built. Material edits don't need a rebuild.
+    pub fn write_bvh(&mut self, queue: &wgpu::Queue, spheres: &[Sphere], shutter: (f32, f32)) {
+        let geometry: Vec<(Vec3, Vec3, f32)> = spheres
+            .iter()
+            .map(|sphere| {
+                (
+                    sphere.position_at(shutter.0),
+                    sphere.position_at(shutter.1),
+                    sphere.radius,
+                )
+            })
+            .collect();
+        if self.bvh_geometry.as_ref() == Some(&geometry) {
+            return;
+        }
+
+        let bvh = Bvh::build(spheres, shutter);
+        queue.write_buffer(&self.bvh_nodes, 0, bytemuck::cast_slice(&bvh.nodes));
+        queue.write_buffer(&self.bvh_indices, 0, bytemuck::cast_slice(&bvh.indices));
+        self.bvh_geometry = Some(geometry);
+    }
+
+    /// The buffers the kernel binds, with these primitives.
+    pub fn kernel_buffers<'a>(
+        &'a self,
+        scene_info: &'a wgpu::Buffer,
+        progressive_rendering: &'a wgpu::Buffer,
+        frame_change: &'a wgpu::Buffer,
+    ) -> KernelBuffers<'a> {
+        KernelBuffers {
+            scene_info,
+            spheres: &self.spheres,
+            progressive_rendering,
+            planes: &self.planes,
+            bvh_nodes: &self.bvh_nodes,
+            bvh_indices: &self.bvh_indices,
+            triangles: &self.triangles,
+            lights: &self.lights,
+            frame_change,
+        }
+    }
+}
+
+/// Buffer the texture is copied to between frames, see binding 3.
+pub fn create_progressive_buffer(device: &wgpu::Device, width: u32, height: u32) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: (get_padded_bytes_per_row_from_width(width) * height) as u64,
+        usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    })
+}
+
+pub fn create_bind_group_layout(device: &wgpu::Device) -> wgpu::BindGroupLayout {
+    device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+        entries: &[
+            wgpu::BindGroupLayoutEntry {
+                binding: 0,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: OUTPUT_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 1,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Uniform,
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 2,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 3,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false }, // True?
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 4,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 5,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 6,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 7,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 8,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: OUTPUT_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 9,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::StorageTexture {
+                    access: wgpu::StorageTextureAccess::WriteOnly,
+                    format: OUTPUT_FORMAT,
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 10,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: true },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 11,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Buffer {
+                    ty: wgpu::BufferBindingType::Storage { read_only: false },
+                    has_dynamic_offset: false,
+                    min_binding_size: None,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 12,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Texture {
+                    sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                    view_dimension: wgpu::TextureViewDimension::D2,
+                    multisampled: false,
+                },
+                count: None,
+            },
+            wgpu::BindGroupLayoutEntry {
+                binding: 13,
+                visibility: wgpu::ShaderStages::COMPUTE,
+                ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                count: None,
+            },
+        ],
+        label: None,
+    })
+}
+
+/// The buffers the kernel binds, see the module docs.
+pub struct KernelBuffers<'a> {
+    pub scene_info: &'a wgpu::Buffer,
+    pub spheres: &'a wgpu::Buffer,
+    pub progressive_rendering: &'a wgpu::Buffer,
+    pub planes: &'a wgpu::Buffer,
+    pub bvh_nodes: &'a wgpu::Buffer,
+    pub bvh_indices: &'a wgpu::Buffer,
+    pub triangles: &'a wgpu::Buffer,
+    pub lights: &'a wgpu::Buffer,
+    pub frame_change: &'a wgpu::Buffer,
+}
+
+pub fn create_bind_group(
+    device: &wgpu::Device,
+    layout: &wgpu::BindGroupLayout,
+    storage_texture_view: &wgpu::TextureView,
+    buffers: KernelBuffers<'_>,
+    gbuffer: &GBuffer,
+    environment: &EnvironmentMap,
+) -> wgpu::BindGroup {
+    device.create_bind_group(&wgpu::BindGroupDescriptor {
+        layout,
+        label: None,
+        entries: &[
+            wgpu::BindGroupEntry {
+                binding: 0,
+                resource: wgpu::BindingResource::TextureView(storage_texture_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 1,
+                resource: buffers.scene_info.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 2,
+                resource: buffers.spheres.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 3,
+                resource: buffers.progressive_rendering.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 4,
+                resource: buffers.planes.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 5,
+                resource: buffers.bvh_nodes.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 6,
+                resource: buffers.bvh_indices.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 7,
+                resource: buffers.triangles.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 8,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.normal_depth_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 9,
+                resource: wgpu::BindingResource::TextureView(&gbuffer.albedo_view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 10,
+                resource: buffers.lights.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 11,
+                resource: buffers.frame_change.as_entire_binding(),
+            },
+            wgpu::BindGroupEntry {
+                binding: 12,
+                resource: wgpu::BindingResource::TextureView(&environment.view),
+            },
+            wgpu::BindGroupEntry {
+                binding: 13,
+                resource: wgpu::BindingResource::Sampler(&environment.sampler),
+            },
+        ],
+    })
+}
+
+/// A shader that failed validation when its pipeline was created.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ShaderError {
+    /// File name of the shader, like `KERNEL_NAME`.
+    pub shader: String,
+    /// The error reported by wgpu, including naga's diagnostic.
+    pub message: String,
+    /// Line of the shader the diagnostic points at, when it points at one.
+    pub line: Option<u32>,
+}
+
+impl ShaderError {
+    fn new(shader: &str, error: wgpu::Error) -> Self {
+        let message = error.to_string();
+        Self {
+            shader: shader.to_owned(),
+            line: diagnostic_line(&message),
+            message,
+        }
+    }
+}
+
+impl std::fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.line {
+            Some(line) => write!(f, "{}:{line}: {}", self.shader, self.message),
+            None => write!(f, "{}: {}", self.shader, self.message),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// The line of the first `wgsl:<line>:<column>` location in a naga diagnostic.
+pub fn diagnostic_line(message: &str) -> Option<u32> {
+    let (_, location) = message.split_once("wgsl:")?;
+    let line = location.split(|c: char| !c.is_ascii_digit()).next()?;
+    line.parse().ok()
+}
+
+/// Calls `create`, which creates the modules and pipelines of `shader`, in an error scope. A
+/// shader that fails validation is returned as a `ShaderError` instead of reaching the device's
+/// uncaptured error handler, which panics by default. What `create` returned is unusable then.
+pub async fn validate_shader<T>(
+    device: &wgpu::Device,
+    shader: &str,
+    create: impl FnOnce() -> T,
+) -> Result<T, ShaderError> {
+    device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let created = create();
+    match device.pop_error_scope().await {
+        Some(error) => Err(ShaderError::new(shader, error)),
+        None => Ok(created),
+    }
+}
+
+pub fn create_pipeline(device: &wgpu::Device, bind_group_layout: &wgpu::BindGroupLayout) -> wgpu::ComputePipeline {
+    create_pipeline_from_source(device, bind_group_layout, KERNEL_SOURCE)
+}
+
+/// Creates the kernel's pipeline from `source`, like an edited copy of `KERNEL_SOURCE`.
+pub fn create_pipeline_from_source(
+    device: &wgpu::Device,
+    bind_group_layout: &wgpu::BindGroupLayout,
+    source: &str,
+) -> wgpu::ComputePipeline {
+    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+        bind_group_layouts: &[bind_group_layout],
+        ..Default::default()
+    });
+
+    let cs_module = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+        label: None,
+        source: wgpu::ShaderSource::Wgsl(Cow::Borrowed(source)),
+    });
+
+    device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+        label: None,
+        layout: Some(&pipeline_layout),
+        module: &cs_module,
+        entry_point: "main",
+    })
+}
+
+/// Records the copy of the kernel's output into the buffer it reads the previous frame from.
+pub fn copy_to_progressive_buffer(
+    encoder: &mut wgpu::CommandEncoder,
+    storage_texture: &wgpu::Texture,
+    progressive_rendering_buffer: &wgpu::Buffer,
+    texture_size: (u32, u32),
+) {
+    encoder.copy_texture_to_buffer(
+        storage_texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: progressive_rendering_buffer,
+            layout: wgpu::ImageDataLayout {
+                bytes_per_row: std::num::NonZeroU32::new(get_padded_bytes_per_row_from_width(texture_size.0)),
+                offset: 0,
+                rows_per_image: None,
+            },
+        },
+        wgpu::Extent3d {
+            width: texture_size.0,
+            height: texture_size.1,
+            depth_or_array_layers: 1,
+        },
+    );
+}
+
+/// Random seed in [0, 1) of the frame after `frame_count` when rendering deterministically, a
+/// SplitMix64 hash of `base_seed` and `frame_count` so it is the same on every run and platform.
+pub fn frame_seed(base_seed: u64, frame_count: u32) -> f32 {
+    let mut z = base_seed.wrapping_add((frame_count as u64 + 1).wrapping_mul(0x9E37_79B9_7F4A_7C15));
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^= z >> 31;
+    // The top 24 bits, as many as an f32 holds exactly
+    (z >> 40) as f32 / (1 << 24) as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frame_seeds_repeat_for_the_same_base_seed() {
+        let seeds = |base_seed| (0..100).map(move |frame| frame_seed(base_seed, frame));
+        assert!(seeds(7).eq(seeds(7)));
+        assert!(seeds(7).zip(seeds(8)).all(|(a, b)| a != b));
+        assert!(seeds(7).all(|seed| (0.0..1.0).contains(&seed)));
+
+        // Consecutive frames don't reuse a seed
+        let mut sorted: Vec<f32> = seeds(0).collect();
+        sorted.sort_by(f32::total_cmp);
+        sorted.dedup();
+        assert_eq!(sorted.len(), 100);
+    }
+
+    #[test]
+    fn diagnostics_point_at_their_line() {
+        let message = "Validation Error\n\nCaused by:\n    In Device::create_shader_module\n\nShader \
+                       '' parsing error: unknown type: `vec5`\n   ┌─ wgsl:42:13\n   │\n42 │     let v: vec5<f32>;\n";
+        assert_eq!(diagnostic_line(message), Some(42));
+        assert_eq!(diagnostic_line("Validation Error: no location"), None);
+
+        let error = ShaderError {
+            shader: KERNEL_NAME.to_owned(),
+            message: "unknown type".to_owned(),
+            line: Some(42),
+        };
+        assert_eq!(error.to_string(), "raytracer_kernel.wgsl:42: unknown type");
+    }
+
+    #[test]
+    fn workgroups_cover_every_pixel() {
+        assert_eq!(workgroup_count(800), 100);
+        assert_eq!(workgroup_count(801), 101);
+        assert_eq!(workgroup_count(7), 1);
+        assert_eq!(workgroup_count(1), 1);
+        assert_eq!(workgroup_count(0), 0);
+    }
+
+    #[test]
+    fn workgroup_sizes_replace_the_kernels() {
+        let default = with_workgroup_size(KERNEL_SOURCE, WorkgroupSize::default());
+        assert!(default.contains("@workgroup_size(8, 8, 1)"));
+
+        let wide = with_workgroup_size(KERNEL_SOURCE, WorkgroupSize::new(32, 1));
+        assert!(wide.contains("@workgroup_size(32, 1, 1)"));
+        assert!(!wide.contains("@workgroup_size(8, 8, 1)"));
+        assert_eq!(WorkgroupSize::new(32, 1).counts((800, 600)), (25, 600));
+        assert_eq!(WorkgroupSize::new(16, 16).counts((801, 1)), (51, 1));
+
+        assert_eq!(
+            with_workgroup_size("fn main() {}", WorkgroupSize::default()),
+            "fn main() {}"
+        );
+    }
+
+    #[test]
+    fn tiles_cover_every_pixel_once() {
+        let grid: Vec<Tile> = tiles(100, 50, 30).collect();
+        // 30 rounds up to 32 pixels, 4 columns and 2 rows
+        assert_eq!(grid.len(), 8);
+        assert_eq!(
+            grid[0],
+            Tile {
+                x: 0,
+                y: 0,
+                width: 32,
+                height: 32
+            }
+        );
+        assert_eq!(
+            grid[3],
+            Tile {
+                x: 96,
+                y: 0,
+                width: 4,
+                height: 32
+            }
+        );
+        assert_eq!(
+            grid[7],
+            Tile {
+                x: 96,
+                y: 32,
+                width: 4,
+                height: 18
+            }
+        );
+        let area: u32 = grid.iter().map(|tile| tile.width * tile.height).sum();
+        assert_eq!(area, 100 * 50);
+
+        assert_eq!(tiles(64, 64, 1024).count(), 1);
+        assert_eq!(tiles(0, 64, 16).count(), 0);
+    }
+
+    #[test]
+    fn padded_bytes_per_row_is_aligned() {
+        // 32 pixels * 8 bytes is exactly one alignment block, so no padding is added
+        assert_eq!(get_padded_bytes_per_row_from_width(32), 256);
+        assert_eq!(get_padded_bytes_per_row_from_width(800), 6400);
+        assert_eq!(get_padded_bytes_per_row_from_width(1), 256);
+        assert_eq!(get_padded_bytes_per_row_from_width(33), 512);
+        assert_eq!(get_padded_bytes_per_row_from_width(801), 6656);
+    }
+}
@@ -1,90 +1,187 @@
-use bytemuck::{Pod, Zeroable};
 use std::num::NonZeroU32;
-use wgpu::{util::DeviceExt, BindGroup, Buffer, BufferView, Device, Extent3d, Queue, RenderPipeline, Texture};
+use wgpu::{
+    util::DeviceExt, BindGroup, BindGroupLayout, Buffer, BufferView, ComputePipeline, Device, Extent3d, Queue,
+    SubmissionIndex, Texture, TextureView,
+};
 
-#[repr(C)]
-#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable)]
-struct Uniforms {
-    time: f32,
+use crate::environment::EnvironmentMap;
+use crate::raytracer::{self, workgroup_count, GBuffer, SceneBuffers, SceneCapacity, ShaderError, Tile};
+use crate::scene::{Camera, Scene, SceneInfo, Sphere, DEFAULT_SKY_BOTTOM_COLOR, DEFAULT_SKY_TOP_COLOR};
+
+/// Why a `Renderer` couldn't be created.
+#[derive(Debug)]
+pub enum RendererError {
+    /// The render has no pixels, which wgpu can't create textures for.
+    EmptySize {
+        width: usize,
+        height: usize,
+    },
+    /// No adapter supports the backends the renderer asks for.
+    NoAdapter,
+    RequestDevice(wgpu::RequestDeviceError),
+    /// The kernel failed validation.
+    Shader(ShaderError),
+}
+
+impl std::fmt::Display for RendererError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RendererError::EmptySize { width, height } => {
+                write!(
+                    f,
+                    "Cannot render {width}x{height} images, both sizes must be at least 1"
+                )
+            }
+            RendererError::NoAdapter => write!(f, "No compatible GPU adapter was found"),
+            RendererError::RequestDevice(err) => write!(f, "Could not create the GPU device: {err}"),
+            RendererError::Shader(err) => write!(f, "Could not compile the kernel: {err}"),
+        }
+    }
+}
+
+impl std::error::Error for RendererError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RendererError::EmptySize { .. } | RendererError::NoAdapter => None,
+            RendererError::RequestDevice(err) => Some(err),
+            RendererError::Shader(err) => Some(err),
+        }
+    }
+}
+
+/// How `Renderer::read_output` waits for the GPU.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PollMode {
+    /// Block the calling thread until the submission is done.
+    #[default]
+    Wait,
+    /// Poll the device without blocking, yielding the thread between polls. Useful when many
+    /// renderers share a few threads.
+    Yield,
+}
+
+/// Kernel settings the app exposes in its UI, which scenes don't carry.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct KernelSettings {
+    /// Where in its pixel the unjittered camera ray goes through, 0.5 being the center.
+    pub pixel_center: f32,
+    /// Offsets every sample randomly within its pixel.
+    pub jitter: bool,
+    /// Intersects spheres without the cancellation of the textbook quadratic formula.
+    pub stable_intersection: bool,
+    /// See `SceneInfo::debug_mode`.
+    pub debug_mode: u32,
+    /// See `SceneInfo::max_sample_radiance`.
+    pub max_sample_radiance: f32,
 }
 
+impl Default for KernelSettings {
+    fn default() -> Self {
+        Self {
+            pixel_center: 0.5,
+            jitter: true,
+            stable_intersection: true,
+            debug_mode: 0,
+            max_sample_radiance: 0.0,
+        }
+    }
+}
+
+/// A headless renderer owning its own device and queue.
+///
+/// `Renderer` is `Send`, so it can be moved to a dedicated worker thread. Rendering takes
+/// `&mut self`, so a single instance renders one frame at a time; render concurrently by creating
+/// one `Renderer` per thread.
+///
+/// The renderer keeps the last scene it was given on the GPU. `set_sphere` and `set_camera` edit
+/// it in place and `render_accumulated` refines it frame after frame, so a host app can drive an
+/// interactive render loop without uploading the whole scene every frame.
 pub struct Renderer {
     device: Device,
     output_buffer: Buffer,
-    texture: Texture,
+    storage_texture: Texture,
+    storage_texture_view: TextureView,
     dimensions: BufferDimensions,
     queue: Queue,
     texture_extent: Extent3d,
-    render_pipeline: RenderPipeline,
-    uniforms_bind_group: BindGroup,
-    uniform_buffer: Buffer,
+    pipeline: ComputePipeline,
+    bind_group_layout: BindGroupLayout,
+    bind_group: BindGroup,
+    scene_info_buffer: Buffer,
+    scene_buffers: SceneBuffers,
+    /// The scene as uploaded to `scene_buffers`.
+    scene: Scene,
+    /// Frames blended into the running average since the scene or the time changed.
+    frame_count: u32,
+    /// Time the running average is of.
+    accumulated_time: f32,
+    /// Summed into by the kernel when accumulating, but never read back.
+    frame_change_buffer: Buffer,
+    progressive_rendering_buffer: Buffer,
+    /// Never written, the renderer doesn't denoise.
+    gbuffer: GBuffer,
+    /// Black, scenes are rendered with the sky or a flat background.
+    environment: EnvironmentMap,
     is_mapped: bool,
+    poll_mode: PollMode,
+    /// Largest side of the tiles frames are dispatched in, `None` dispatches whole frames.
+    tile_size: Option<u32>,
+    kernel_settings: KernelSettings,
+    adapter_info: wgpu::AdapterInfo,
 }
 
 impl Renderer {
-    pub async fn new(width: usize, height: usize) -> Self {
+    /// Creates a renderer on the backends from `raytracer::backends_from_env`, preferring the GPU
+    /// from `raytracer::power_preference_from_env`.
+    pub async fn new(width: usize, height: usize) -> Result<Self, RendererError> {
+        Self::with_adapter_options(
+            width,
+            height,
+            raytracer::backends_from_env(),
+            raytracer::power_preference_from_env(),
+        )
+        .await
+    }
+
+    pub async fn with_adapter_options(
+        width: usize,
+        height: usize,
+        backends: wgpu::Backends,
+        power_preference: wgpu::PowerPreference,
+    ) -> Result<Self, RendererError> {
+        if width == 0 || height == 0 {
+            return Err(RendererError::EmptySize { width, height });
+        }
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
+            backends,
             dx12_shader_compiler: wgpu::Dx12Compiler::default(),
         });
 
         let adapter = instance
             .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::HighPerformance,
+                power_preference,
                 compatible_surface: None,
                 force_fallback_adapter: false,
             })
             .await
-            .expect("Could not create adapter");
+            .ok_or(RendererError::NoAdapter)?;
+        let adapter_info = adapter.get_info();
 
         let (device, queue) = adapter
             .request_device(
                 &wgpu::DeviceDescriptor {
                     label: None,
                     features: wgpu::Features::empty(),
-                    limits: wgpu::Limits::downlevel_defaults(),
+                    // The kernel binds more storage buffers than the downlevel defaults allow
+                    limits: wgpu::Limits {
+                        max_storage_buffers_per_shader_stage: adapter.limits().max_storage_buffers_per_shader_stage,
+                        ..wgpu::Limits::downlevel_defaults()
+                    },
                 },
                 None,
             )
             .await
-            .unwrap();
-
-        let shader = device.create_shader_module(wgpu::include_wgsl!("shaders/basic.wgsl"));
-
-        let uniform_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: None,
-            contents: bytemuck::cast_slice(&[Uniforms { time: 0.0 }]),
-            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        });
-
-        let uniforms_bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: None,
-        });
-
-        let uniforms_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-            layout: &uniforms_bind_group_layout,
-            entries: &[wgpu::BindGroupEntry {
-                binding: 0,
-                resource: uniform_buffer.as_entire_binding(),
-            }],
-            label: None,
-        });
-
-        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-            label: Some("Render Pipeline Layout"),
-            bind_group_layouts: &[&uniforms_bind_group_layout],
-            push_constant_ranges: &[],
-        });
+            .map_err(RendererError::RequestDevice)?;
 
         let dimensions = BufferDimensions::new(width, height);
         let texture_extent = Extent3d {
@@ -93,48 +190,46 @@ impl Renderer {
             depth_or_array_layers: 1,
         };
 
-        let texture_desc = wgpu::TextureDescriptor {
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        let scene_info_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: None,
-            view_formats: &[],
-        };
-
-        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-            label: Some("Render Pipeline"),
-            layout: Some(&pipeline_layout),
-            vertex: wgpu::VertexState {
-                module: &shader,
-                entry_point: "vs_main",
-                buffers: &[],
-            },
-            fragment: Some(wgpu::FragmentState {
-                module: &shader,
-                entry_point: "fs_main",
-                targets: &[Some(wgpu::ColorTargetState {
-                    format: texture_desc.format,
-                    blend: None,
-                    write_mask: wgpu::ColorWrites::ALL,
-                })],
-            }),
-            primitive: wgpu::PrimitiveState {
-                front_face: wgpu::FrontFace::Ccw,
-                cull_mode: Some(wgpu::Face::Back),
-                polygon_mode: wgpu::PolygonMode::Fill,
-                ..Default::default()
-            },
-            depth_stencil: None,
-            multisample: wgpu::MultisampleState {
-                count: 1,
-                mask: !0,
-                alpha_to_coverage_enabled: false,
-            },
-            multiview: None,
+            contents: bytemuck::cast_slice(&[SceneInfo::default()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
         });
+        let scene_buffers = SceneBuffers::new(
+            &device,
+            SceneCapacity {
+                spheres: 1,
+                planes: 1,
+                triangles: 1,
+                lights: 1,
+            },
+        );
+        let frame_change_buffer = raytracer::create_frame_change_buffer(&device);
+        let progressive_rendering_buffer =
+            raytracer::create_progressive_buffer(&device, texture_extent.width, texture_extent.height);
+
+        let storage_texture = device.create_texture(&raytracer::storage_texture_descriptor(
+            texture_extent.width,
+            texture_extent.height,
+        ));
+        let storage_texture_view = storage_texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let gbuffer = GBuffer::disabled(&device);
+        let environment = EnvironmentMap::disabled(&device);
+
+        let bind_group_layout = raytracer::create_bind_group_layout(&device);
+        let bind_group = raytracer::create_bind_group(
+            &device,
+            &bind_group_layout,
+            &storage_texture_view,
+            scene_buffers.kernel_buffers(&scene_info_buffer, &progressive_rendering_buffer, &frame_change_buffer),
+            &gbuffer,
+            &environment,
+        );
+        let pipeline = raytracer::validate_shader(&device, raytracer::KERNEL_NAME, || {
+            raytracer::create_pipeline(&device, &bind_group_layout)
+        })
+        .await
+        .map_err(RendererError::Shader)?;
 
         let output_buffer = device.create_buffer(&wgpu::BufferDescriptor {
             label: None,
@@ -143,64 +238,225 @@ impl Renderer {
             mapped_at_creation: false,
         });
 
-        let texture = device.create_texture(&wgpu::TextureDescriptor {
-            size: texture_extent,
-            mip_level_count: 1,
-            sample_count: 1,
-            dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Rgba32Float,
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
-            label: None,
-            view_formats: &[],
-        });
-
-        Self {
+        Ok(Self {
             device,
             output_buffer,
-            texture,
+            storage_texture,
+            storage_texture_view,
             dimensions,
             queue,
             texture_extent,
-            render_pipeline,
-            uniforms_bind_group,
-            uniform_buffer,
+            pipeline,
+            bind_group_layout,
+            bind_group,
+            scene_info_buffer,
+            scene_buffers,
+            scene: Scene::default(),
+            frame_count: 0,
+            accumulated_time: 0.0,
+            frame_change_buffer,
+            progressive_rendering_buffer,
+            gbuffer,
+            environment,
             is_mapped: false,
+            poll_mode: PollMode::default(),
+            tile_size: None,
+            kernel_settings: KernelSettings::default(),
+            adapter_info,
+        })
+    }
+
+    /// The adapter the renderer picked, to tell which GPU and backend it runs on.
+    pub fn adapter_info(&self) -> &wgpu::AdapterInfo {
+        &self.adapter_info
+    }
+
+    pub fn set_poll_mode(&mut self, poll_mode: PollMode) {
+        self.poll_mode = poll_mode;
+    }
+
+    /// Dispatches frames in tiles of at most `tile_size` pixels a side, one submission each, so
+    /// very large frames don't run into the GPU's watchdog in a single dispatch. The image is the
+    /// same as without tiles.
+    pub fn set_tile_size(&mut self, tile_size: Option<u32>) {
+        self.tile_size = tile_size;
+    }
+
+    pub fn set_kernel_settings(&mut self, kernel_settings: KernelSettings) {
+        self.kernel_settings = kernel_settings;
+    }
+
+    /// The scene as last given to `set_scene`, `submit` or `render`, with the edits since.
+    pub fn scene(&self) -> &Scene {
+        &self.scene
+    }
+
+    /// Uploads all of `scene`, restarting the running average.
+    pub fn set_scene(&mut self, scene: &Scene) {
+        self.scene.clone_from(scene);
+        if self.scene_buffers.reserve(&self.device, &self.scene) {
+            self.rebind();
+        }
+        self.scene_buffers.write_spheres(&self.queue, &self.scene.spheres);
+        self.scene_buffers.write_planes(&self.queue, &self.scene.planes);
+        self.scene_buffers.write_triangles(&self.queue, &self.scene.triangles);
+        self.scene_buffers.write_lights(&self.queue, &self.scene.lights);
+        self.frame_count = 0;
+    }
+
+    /// Replaces a sphere, uploading only that sphere, and restarts the running average. Returns
+    /// false, changing nothing, when there is no sphere `index`.
+    pub fn set_sphere(&mut self, index: usize, sphere: Sphere) -> bool {
+        let Some(slot) = self.scene.spheres.get_mut(index) else {
+            return false;
+        };
+        *slot = sphere;
+        self.scene_buffers.write_sphere(&self.queue, index, &sphere);
+        self.frame_count = 0;
+        true
+    }
+
+    /// Moves the camera and restarts the running average. The camera is part of the uniforms, so
+    /// nothing is uploaded until the next frame.
+    pub fn set_camera(&mut self, camera: Camera) {
+        self.scene.camera = camera;
+        self.frame_count = 0;
+    }
+
+    /// Frames blended into the running average so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frame_count
+    }
+
+    /// Renders a frame of `scene` and blocks until its output can be read back.
+    pub async fn render(&mut self, scene: &Scene, time: f32) -> Option<(BufferView<'_>, BufferDimensions)> {
+        let index = self.submit(scene, time);
+        self.read_output(index).await
+    }
+
+    /// Submits a frame without waiting for the GPU, so the caller can decide when to wait on it
+    /// with `read_output`. `scene` replaces the renderer's scene like `set_scene`.
+    ///
+    /// The frame is traced with the same kernel as the app, but isn't accumulated with previous
+    /// ones: raise the scene's `samples_per_pixel` or use `render_accumulated` for a cleaner image.
+    /// The random seed is derived from `time`, so rendering the same scene at the same time gives
+    /// the same image.
+    pub fn submit(&mut self, scene: &Scene, time: f32) -> SubmissionIndex {
+        self.submit_with_progress(scene, time, |_, _| {})
+    }
+
+    /// Like `submit`, but when rendering in tiles, waits for each tile before submitting the next
+    /// and calls `progress` with the number of tiles done and the tile count. The last tile is
+    /// left for `read_output` to wait on.
+    pub fn submit_with_progress(
+        &mut self,
+        scene: &Scene,
+        time: f32,
+        progress: impl FnMut(usize, usize),
+    ) -> SubmissionIndex {
+        self.set_scene(scene);
+        self.submit_frame(time, false, progress)
+    }
+
+    /// Blends `frames` more frames of the scene at `time` into the running average, then blocks
+    /// until it can be read back. At least one frame is traced. Changing the time restarts the
+    /// average, like editing the scene does.
+    pub async fn render_accumulated(&mut self, frames: u32, time: f32) -> Option<(BufferView<'_>, BufferDimensions)> {
+        let index = self.submit_accumulated(frames, time);
+        self.read_output(index).await
+    }
+
+    /// Like `render_accumulated`, without waiting for the last frame.
+    pub fn submit_accumulated(&mut self, frames: u32, time: f32) -> SubmissionIndex {
+        for _ in 1..frames {
+            let index = self.submit_frame(time, true, |_, _| {});
+            // Queuing every frame at once could run into the GPU's timeout
+            self.wait(index);
         }
+        self.submit_frame(time, true, |_, _| {})
     }
 
-    pub async fn render(&mut self, time: f32) -> Option<(BufferView, BufferDimensions)> {
+    /// Traces a frame of the uploaded scene, as the next frame of the running average when
+    /// `accumulate`, and copies it to the output buffer.
+    fn submit_frame(&mut self, time: f32, accumulate: bool, mut progress: impl FnMut(usize, usize)) -> SubmissionIndex {
         if self.is_mapped {
             self.output_buffer.unmap();
+            self.is_mapped = false;
+        }
+
+        let frame_count = if accumulate {
+            if time != self.accumulated_time {
+                self.frame_count = 0;
+                self.accumulated_time = time;
+            }
+            self.frame_count = self.frame_count.saturating_add(1);
+            self.frame_count
+        } else {
+            1
+        };
+        self.scene_buffers
+            .write_bvh(&self.queue, &self.scene.spheres, (time, time));
+
+        let scene = &self.scene;
+        let settings = scene.settings.unwrap_or_default();
+        let mut scene_info = SceneInfo::default();
+        scene_info.camera = scene.camera;
+        scene_info.time = time;
+        // Every frame of the running average needs samples of its own
+        scene_info.random_seed = time + raytracer::frame_seed(0, frame_count - 1);
+        scene_info.sphere_count = scene.spheres.len() as u32;
+        scene_info.plane_count = scene.planes.len() as u32;
+        scene_info.triangle_count = scene.triangles.len() as u32;
+        scene_info.light_count = scene.lights.len() as u32;
+        scene_info.frame_count = frame_count;
+        scene_info.accumulate = accumulate as u32;
+        scene_info.pixel_center = self.kernel_settings.pixel_center;
+        scene_info.stable_intersection = self.kernel_settings.stable_intersection as u32;
+        scene_info.debug_mode = self.kernel_settings.debug_mode;
+        scene_info.max_sample_radiance = self.kernel_settings.max_sample_radiance;
+        scene_info.jitter = self.kernel_settings.jitter as u32;
+        scene_info.sky_top_color = DEFAULT_SKY_TOP_COLOR;
+        scene_info.sky_bottom_color = DEFAULT_SKY_BOTTOM_COLOR;
+        scene_info.samples_per_pixel = settings.samples_per_pixel;
+        scene_info.max_bounces = settings.max_bounces;
+        scene_info.aspect = self.texture_extent.width as f32 / self.texture_extent.height as f32;
+
+        let (width, height) = (self.texture_extent.width, self.texture_extent.height);
+        let tiles: Vec<Tile> = match self.tile_size {
+            Some(tile_size) => raytracer::tiles(width, height, tile_size).collect(),
+            None => vec![Tile {
+                x: 0,
+                y: 0,
+                width,
+                height,
+            }],
+        };
+        let (last_tile, first_tiles) = tiles.split_last().expect("The texture is at least 1x1");
+
+        // Every tile needs its own submission, as writes to the `SceneInfo` only land between them
+        for (index, tile) in first_tiles.iter().enumerate() {
+            let encoder = self.encode_tile(scene_info, tile);
+            let submission = self.queue.submit(Some(encoder.finish()));
+            self.wait(submission);
+            progress(index + 1, tiles.len());
         }
 
         let command_buffer = {
-            let mut encoder = self
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
-            let texture_view = &self.texture.create_view(&wgpu::TextureViewDescriptor::default());
-            {
-                let render_pass_desc = wgpu::RenderPassDescriptor {
-                    label: None,
-                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                        view: texture_view,
-                        resolve_target: None,
-                        ops: wgpu::Operations {
-                            load: wgpu::LoadOp::Clear(wgpu::Color::RED),
-                            store: true,
-                        },
-                    })],
-                    depth_stencil_attachment: None,
-                };
-
-                let mut render_pass = encoder.begin_render_pass(&render_pass_desc);
-                render_pass.set_pipeline(&self.render_pipeline);
-                render_pass.set_bind_group(0, &self.uniforms_bind_group, &[]);
-                render_pass.draw(0..3, 0..1);
+            let mut encoder = self.encode_tile(scene_info, last_tile);
+
+            // The next frame blends with this one once every tile is traced
+            if accumulate {
+                raytracer::copy_to_progressive_buffer(
+                    &mut encoder,
+                    &self.storage_texture,
+                    &self.progressive_rendering_buffer,
+                    (width, height),
+                );
             }
 
             // Copy the data from the texture to the buffer
             encoder.copy_texture_to_buffer(
-                self.texture.as_image_copy(),
+                self.storage_texture.as_image_copy(),
                 wgpu::ImageCopyBuffer {
                     buffer: &self.output_buffer,
                     layout: wgpu::ImageDataLayout {
@@ -215,15 +471,65 @@ impl Renderer {
             encoder.finish()
         };
 
+        self.queue.submit(Some(command_buffer))
+    }
+
+    /// Writes `scene_info` for `tile` and records its dispatch.
+    fn encode_tile(&self, mut scene_info: SceneInfo, tile: &Tile) -> wgpu::CommandEncoder {
+        scene_info.tile_offset = [tile.x, tile.y];
         self.queue
-            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(&[Uniforms { time }]));
-        let index = self.queue.submit(Some(command_buffer));
+            .write_buffer(&self.scene_info_buffer, 0, bytemuck::cast_slice(&[scene_info]));
 
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        {
+            let mut compute_pass = encoder.begin_compute_pass(&Default::default());
+            compute_pass.set_pipeline(&self.pipeline);
+            compute_pass.set_bind_group(0, &self.bind_group, &[]);
+            compute_pass.dispatch_workgroups(workgroup_count(tile.width), workgroup_count(tile.height), 1);
+        }
+        encoder
+    }
+
+    /// Recreates the bind group after the scene buffers were replaced.
+    fn rebind(&mut self) {
+        self.bind_group = raytracer::create_bind_group(
+            &self.device,
+            &self.bind_group_layout,
+            &self.storage_texture_view,
+            self.scene_buffers.kernel_buffers(
+                &self.scene_info_buffer,
+                &self.progressive_rendering_buffer,
+                &self.frame_change_buffer,
+            ),
+            &self.gbuffer,
+            &self.environment,
+        );
+    }
+
+    /// Blocks until the frame of the given submission is rendered, without reading it back.
+    pub fn wait(&self, index: SubmissionIndex) {
+        self.device.poll(wgpu::Maintain::WaitForSubmissionIndex(index));
+    }
+
+    /// Waits for the frame of the given submission and maps its output buffer.
+    pub async fn read_output(&mut self, index: SubmissionIndex) -> Option<(BufferView<'_>, BufferDimensions)> {
         let buffer_slice = self.output_buffer.slice(..);
         let (sender, receiver) = futures_intrusive::channel::shared::oneshot_channel();
         buffer_slice.map_async(wgpu::MapMode::Read, move |v| sender.send(v).unwrap());
         self.is_mapped = true;
-        self.device.poll(wgpu::Maintain::WaitForSubmissionIndex(index));
+        match self.poll_mode {
+            PollMode::Wait => {
+                self.device.poll(wgpu::Maintain::WaitForSubmissionIndex(index));
+            }
+            PollMode::Yield => {
+                // `poll` returns true once the queue is empty, which includes our submission
+                while !self.device.poll(wgpu::Maintain::Poll) {
+                    std::thread::yield_now();
+                }
+            }
+        }
 
         match receiver.receive().await {
             Some(Ok(())) => Some((buffer_slice.get_mapped_range(), self.dimensions)),
@@ -232,6 +538,15 @@ impl Renderer {
     }
 }
 
+/// Converts the padded `Rgba16Float` rows of the output buffer to tightly packed 8-bit RGBA.
+pub fn to_rgba8(data: &[u8], dimensions: BufferDimensions) -> Vec<u8> {
+    data.chunks(dimensions.padded_bytes_per_row)
+        .take(dimensions.height)
+        .flat_map(|row| bytemuck::cast_slice::<u8, half::f16>(&row[..dimensions.unpadded_bytes_per_row]))
+        .map(|channel| (channel.to_f32().clamp(0.0, 1.0) * 255.0).round() as u8)
+        .collect()
+}
+
 #[allow(unused)]
 #[derive(Clone, Copy)]
 pub struct BufferDimensions {
@@ -243,7 +558,7 @@ pub struct BufferDimensions {
 
 impl BufferDimensions {
     fn new(width: usize, height: usize) -> Self {
-        let bytes_per_pixel = 16;
+        let bytes_per_pixel = 8; // Rgba16Float
         let unpadded_bytes_per_row = width * bytes_per_pixel;
         let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT as usize;
         let padded_bytes_per_row_padding = (align - unpadded_bytes_per_row % align) % align;
@@ -0,0 +1,981 @@
+//! The scene description shared by the app and the headless renderer.
+//!
+//! Every `#[repr(C)]` type here is uploaded as is, so its layout must match its counterpart in
+//! `raytracer_kernel.wgsl`, padding included.
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+use bytemuck::{Pod, Zeroable};
+use serde::{Deserialize, Serialize};
+
+pub const DEFAULT_SAMPLES_PER_PIXEL: u32 = 8;
+pub const DEFAULT_MAX_BOUNCES: u32 = 150;
+pub const DEFAULT_FOCUS_DISTANCE: f32 = 7.0;
+/// Vertical field of view in radians, `2 * atan(0.5)`: the image plane one unit in front of the
+/// camera is one unit tall.
+pub const DEFAULT_FOV_Y: f32 = 0.927_295_2;
+/// Range of vertical fields of view in degrees, wider ones distort too much to be useful.
+pub const MIN_FOV_Y_DEGREES: f32 = 10.0;
+pub const MAX_FOV_Y_DEGREES: f32 = 120.0;
+/// Sky seen by rays going towards +z.
+pub const DEFAULT_SKY_TOP_COLOR: Vec3 = Vec3::new(0.5, 0.7, 1.0);
+/// Sky seen by rays going towards -z.
+pub const DEFAULT_SKY_BOTTOM_COLOR: Vec3 = Vec3::new(1.0, 1.0, 1.0);
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct Vec3 {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+}
+
+impl Vec3 {
+    pub const fn new(x: f32, y: f32, z: f32) -> Self {
+        Self { x, y, z }
+    }
+
+    pub const fn splat(value: f32) -> Self {
+        Self::new(value, value, value)
+    }
+
+    pub fn dot(self, other: Self) -> f32 {
+        self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn cross(self, other: Self) -> Self {
+        Self::new(
+            self.y * other.z - self.z * other.y,
+            self.z * other.x - self.x * other.z,
+            self.x * other.y - self.y * other.x,
+        )
+    }
+
+    pub fn length(self) -> f32 {
+        self.dot(self).sqrt()
+    }
+
+    /// The vector scaled to a length of 1. The zero vector has no direction and gives NaNs.
+    pub fn normalize(self) -> Self {
+        self / self.length()
+    }
+
+    /// The smallest of each component.
+    pub fn min(self, other: Self) -> Self {
+        Self::new(self.x.min(other.x), self.y.min(other.y), self.z.min(other.z))
+    }
+
+    /// The largest of each component.
+    pub fn max(self, other: Self) -> Self {
+        Self::new(self.x.max(other.x), self.y.max(other.y), self.z.max(other.z))
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<glam::Vec3> for Vec3 {
+    fn from(v: glam::Vec3) -> Self {
+        Self::new(v.x, v.y, v.z)
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<Vec3> for glam::Vec3 {
+    fn from(v: Vec3) -> Self {
+        glam::Vec3::new(v.x, v.y, v.z)
+    }
+}
+
+impl Add for Vec3 {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self::new(self.x + other.x, self.y + other.y, self.z + other.z)
+    }
+}
+
+impl Sub for Vec3 {
+    type Output = Self;
+
+    fn sub(self, other: Self) -> Self {
+        Self::new(self.x - other.x, self.y - other.y, self.z - other.z)
+    }
+}
+
+impl Mul<f32> for Vec3 {
+    type Output = Self;
+
+    fn mul(self, scale: f32) -> Self {
+        Self::new(self.x * scale, self.y * scale, self.z * scale)
+    }
+}
+
+impl Div<f32> for Vec3 {
+    type Output = Self;
+
+    fn div(self, scale: f32) -> Self {
+        Self::new(self.x / scale, self.y / scale, self.z / scale)
+    }
+}
+
+impl Neg for Vec3 {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::new(-self.x, -self.y, -self.z)
+    }
+}
+
+/// A column major affine transform, laid out like `glam::Mat4`.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Mat4 {
+    pub columns: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::IDENTITY
+    }
+}
+
+impl Mat4 {
+    pub const IDENTITY: Self = Self {
+        columns: [
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ],
+    };
+
+    pub const fn from_translation(translation: Vec3) -> Self {
+        let mut matrix = Self::IDENTITY;
+        matrix.columns[3] = [translation.x, translation.y, translation.z, 1.0];
+        matrix
+    }
+
+    /// Rotates by `angle` radians around +z, from +x towards +y.
+    pub fn from_rotation_z(angle: f32) -> Self {
+        let (sin, cos) = angle.sin_cos();
+        let mut matrix = Self::IDENTITY;
+        matrix.columns[0] = [cos, sin, 0.0, 0.0];
+        matrix.columns[1] = [-sin, cos, 0.0, 0.0];
+        matrix
+    }
+
+    pub fn translation(&self) -> Vec3 {
+        let [x, y, z, _] = self.columns[3];
+        Vec3::new(x, y, z)
+    }
+
+    pub fn transform_point(&self, point: Vec3) -> Vec3 {
+        let [x, y, z, _] = self.mul_column([point.x, point.y, point.z, 1.0]);
+        Vec3::new(x, y, z)
+    }
+
+    fn mul_column(&self, column: [f32; 4]) -> [f32; 4] {
+        let mut result = [0.0; 4];
+        for (row, value) in result.iter_mut().enumerate() {
+            *value = (0..4).map(|i| self.columns[i][row] * column[i]).sum();
+        }
+        result
+    }
+}
+
+/// `a * b` applies `b` first, then `a`.
+impl Mul for Mat4 {
+    type Output = Self;
+
+    fn mul(self, other: Self) -> Self {
+        Self {
+            columns: other.columns.map(|column| self.mul_column(column)),
+        }
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<glam::Mat4> for Mat4 {
+    fn from(m: glam::Mat4) -> Self {
+        Self {
+            columns: m.to_cols_array_2d(),
+        }
+    }
+}
+
+#[cfg(feature = "glam")]
+impl From<Mat4> for glam::Mat4 {
+    fn from(m: Mat4) -> Self {
+        glam::Mat4::from_cols_array_2d(&m.columns)
+    }
+}
+
+/// Fields missing from a scene file take their `Default` value.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Material {
+    pub albedo: Vec3,
+    pub is_mirror: u32,
+    pub camera_visible: u32,
+    /// Weight of a clear, smooth specular layer on top of the base. 0 disables it.
+    pub clearcoat_strength: f32,
+    pub clearcoat_roughness: f32,
+    /// One of `MaterialType`, as a `u32` for the GPU.
+    pub material_type: u32,
+    /// Index of refraction of dielectrics.
+    pub ior: f32,
+    /// GGX roughness of mirrors and of the specular reflection of diffuse materials. 0 is a
+    /// perfect mirror, higher values blur the reflection.
+    pub roughness: f32,
+    /// 1 alternates `albedo` and `checker_color` in squares along the horizontal world axes,
+    /// x and y, like a classic floor.
+    pub checkerboard: u32,
+    /// How much of a diffuse material is metal, reflecting like a mirror tinted by `albedo`
+    /// instead of diffusely, blurred by `roughness`. Between 0 and 1, mirrors are always 1. The rest
+    /// is diffuse under an untinted specular reflection of 4% head on.
+    pub metallic: f32,
+    /// Radiance emitted by the surface, scaled by `emission_strength`.
+    pub emission: Vec3,
+    pub emission_strength: f32,
+    pub checker_color: Vec3,
+    /// Side of the checkerboard squares, in world units.
+    pub checker_scale: f32,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            albedo: Default::default(),
+            is_mirror: 0,
+            camera_visible: 1,
+            clearcoat_strength: 0.0,
+            clearcoat_roughness: 0.0,
+            material_type: MaterialType::Diffuse as u32,
+            ior: 1.5,
+            roughness: 0.0,
+            checkerboard: 0,
+            metallic: 0.0,
+            emission: Default::default(),
+            emission_strength: 0.0,
+            checker_color: Default::default(),
+            checker_scale: 1.0,
+        }
+    }
+}
+
+impl Material {
+    /// How the kernel shades the material. `is_mirror` predates `material_type` and wins over it.
+    pub fn kind(&self) -> MaterialType {
+        if self.is_mirror == 1 {
+            return MaterialType::Mirror;
+        }
+        match self.material_type {
+            1 => MaterialType::Mirror,
+            2 => MaterialType::Dielectric,
+            _ => MaterialType::Diffuse,
+        }
+    }
+
+    pub fn set_kind(&mut self, kind: MaterialType) {
+        self.material_type = kind as u32;
+        self.is_mirror = (kind == MaterialType::Mirror) as u32;
+    }
+
+    /// The `metallic` the kernel shades with. Mirrors, including the older `is_mirror` ones, are
+    /// fully metallic and dielectrics not at all.
+    pub fn effective_metallic(&self) -> f32 {
+        match self.kind() {
+            MaterialType::Diffuse => self.metallic.clamp(0.0, 1.0),
+            MaterialType::Mirror => 1.0,
+            MaterialType::Dielectric => 0.0,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Default, Debug, PartialEq, Eq)]
+pub enum MaterialType {
+    #[default]
+    Diffuse,
+    Mirror,
+    /// Refracts with Snell's law and reflects with Fresnel, like glass.
+    Dielectric,
+}
+
+impl MaterialType {
+    pub const ALL: [MaterialType; 3] = [MaterialType::Diffuse, MaterialType::Mirror, MaterialType::Dielectric];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialType::Diffuse => "Diffuse",
+            MaterialType::Mirror => "Mirror",
+            MaterialType::Dielectric => "Dielectric",
+        }
+    }
+}
+
+/// Named starting points for common real world materials. Metals are colored mirrors tinted by
+/// their reflectance at normal incidence.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum MaterialPreset {
+    Gold,
+    Copper,
+    /// A colored diffuse base under a glossy clearcoat.
+    Plastic,
+    Glass,
+}
+
+impl MaterialPreset {
+    pub const ALL: [MaterialPreset; 4] = [
+        MaterialPreset::Gold,
+        MaterialPreset::Copper,
+        MaterialPreset::Plastic,
+        MaterialPreset::Glass,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaterialPreset::Gold => "Gold",
+            MaterialPreset::Copper => "Copper",
+            MaterialPreset::Plastic => "Plastic",
+            MaterialPreset::Glass => "Glass",
+        }
+    }
+
+    /// Overwrites how `material` reflects and refracts light, keeping its emission, checkerboard
+    /// and visibility.
+    pub fn apply(&self, material: &mut Material) {
+        let (kind, albedo, roughness) = match self {
+            MaterialPreset::Gold => (MaterialType::Mirror, (1.0, 0.766, 0.336), 0.2),
+            MaterialPreset::Copper => (MaterialType::Mirror, (0.955, 0.638, 0.538), 0.3),
+            MaterialPreset::Plastic => (MaterialType::Diffuse, (0.8, 0.1, 0.1), 0.0),
+            MaterialPreset::Glass => (MaterialType::Dielectric, (1.0, 1.0, 1.0), 0.0),
+        };
+        material.set_kind(kind);
+        material.metallic = (kind == MaterialType::Mirror) as u32 as f32;
+        material.albedo = Vec3::new(albedo.0, albedo.1, albedo.2);
+        material.roughness = roughness;
+        material.ior = 1.5;
+        let is_plastic = *self == MaterialPreset::Plastic;
+        material.clearcoat_strength = if is_plastic { 1.0 } else { 0.0 };
+        material.clearcoat_roughness = if is_plastic { 0.05 } else { 0.0 };
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct Sphere {
+    /// Center at time 0.
+    pub position: Vec3,
+    pub radius: f32,
+    #[serde(default)]
+    pub mat: Material,
+    /// Distance the center moves per unit of `SceneInfo::time`. Moving spheres blur over the
+    /// time the shutter is open.
+    #[serde(default)]
+    pub velocity: Vec3,
+    #[serde(skip)]
+    unused_buffer: [u32; 1],
+}
+
+impl Sphere {
+    pub fn new(position: Vec3, radius: f32, mat: Material) -> Self {
+        Self {
+            position,
+            radius,
+            mat,
+            ..Default::default()
+        }
+    }
+
+    /// Center at `time`. Must match `sphere_at_sample_time` in the kernel.
+    pub fn position_at(&self, time: f32) -> Vec3 {
+        self.position + self.velocity * time
+    }
+}
+
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct Plane {
+    pub point: Vec3,
+    #[serde(skip)]
+    unused_buffer: [u32; 1],
+    pub normal: Vec3,
+    #[serde(skip)]
+    unused_buffer_2: [u32; 1],
+    #[serde(default)]
+    pub mat: Material,
+}
+
+impl Plane {
+    pub fn new(point: Vec3, normal: Vec3, mat: Material) -> Self {
+        Self {
+            point,
+            normal,
+            mat,
+            ..Default::default()
+        }
+    }
+}
+
+/// A light at a single point, which bounces never hit so the kernel samples it explicitly. Its
+/// light falls off with the square of the distance.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct PointLight {
+    pub position: Vec3,
+    pub intensity: f32,
+    pub color: Vec3,
+    #[serde(skip)]
+    unused_buffer: [u32; 1],
+}
+
+impl PointLight {
+    pub fn new(position: Vec3, color: Vec3, intensity: f32) -> Self {
+        Self {
+            position,
+            intensity,
+            color,
+            ..Default::default()
+        }
+    }
+}
+
+/// Both sides of a triangle are visible, so the winding order doesn't matter.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+pub struct Triangle {
+    pub v0: Vec3,
+    #[serde(skip)]
+    unused_buffer: [u32; 1],
+    pub v1: Vec3,
+    #[serde(skip)]
+    unused_buffer_2: [u32; 1],
+    pub v2: Vec3,
+    #[serde(skip)]
+    unused_buffer_3: [u32; 1],
+    #[serde(default)]
+    pub mat: Material,
+}
+
+impl Triangle {
+    pub fn new(v0: Vec3, v1: Vec3, v2: Vec3, mat: Material) -> Self {
+        Self {
+            v0,
+            v1,
+            v2,
+            mat,
+            ..Default::default()
+        }
+    }
+}
+
+/// The geometry uploaded to the raytracer every frame, and the camera looking at it.
+///
+/// Scenes can be loaded from RON or JSON files, see `crates/app/scenes` for examples.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Scene {
+    pub camera: Camera,
+    pub spheres: Vec<Sphere>,
+    #[serde(default)]
+    pub planes: Vec<Plane>,
+    #[serde(default)]
+    pub triangles: Vec<Triangle>,
+    #[serde(default)]
+    pub lights: Vec<PointLight>,
+    /// Overrides the current render settings when the scene is loaded.
+    #[serde(default)]
+    pub settings: Option<RenderSettings>,
+    #[serde(default)]
+    pub groups: Vec<Group>,
+}
+
+/// Spheres moved together. The spheres keep their world positions, so what is uploaded stays flat:
+/// `Scene::transform_group` moves them and accumulates the move in `transform`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Group {
+    /// Everything the group was moved by since it was created.
+    pub transform: Mat4,
+    /// Indices into `Scene::spheres`.
+    pub sphere_indices: Vec<usize>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RenderSettings {
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples_per_pixel: DEFAULT_SAMPLES_PER_PIXEL,
+            max_bounces: DEFAULT_MAX_BOUNCES,
+        }
+    }
+}
+
+impl Scene {
+    /// Reads a scene from a `.ron` or `.json` file, picking the format from the extension.
+    pub fn load(path: &std::path::Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let contents = std::fs::read_to_string(path)?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("json") => Ok(serde_json::from_str(&contents)?),
+            _ => Ok(ron::from_str(&contents)?),
+        }
+    }
+
+    /// Moves the spheres of group `index` by `transform`, on top of the group's previous moves.
+    /// Returns false when there is no group `index`.
+    pub fn transform_group(&mut self, index: usize, transform: Mat4) -> bool {
+        let Some(group) = self.groups.get_mut(index) else {
+            return false;
+        };
+        group.transform = transform * group.transform;
+        for &sphere in &group.sphere_indices {
+            if let Some(sphere) = self.spheres.get_mut(sphere) {
+                sphere.position = transform.transform_point(sphere.position);
+            }
+        }
+        true
+    }
+
+    /// Average position of the spheres of group `index`, `None` when it has none or there is no
+    /// group `index`.
+    pub fn group_center(&self, index: usize) -> Option<Vec3> {
+        let positions: Vec<Vec3> = self
+            .groups
+            .get(index)?
+            .sphere_indices
+            .iter()
+            .filter_map(|&sphere| Some(self.spheres.get(sphere)?.position))
+            .collect();
+        let sum = positions.iter().fold(Vec3::default(), |sum, &position| sum + position);
+        (!positions.is_empty()).then(|| sum / positions.len() as f32)
+    }
+
+    /// Removes sphere `index`, taking it out of the groups and shifting the indices after it.
+    pub fn remove_sphere(&mut self, index: usize) -> Sphere {
+        for group in &mut self.groups {
+            group.sphere_indices.retain(|&sphere| sphere != index);
+            for sphere in &mut group.sphere_indices {
+                if *sphere > index {
+                    *sphere -= 1;
+                }
+            }
+        }
+        self.spheres.remove(index)
+    }
+}
+
+/// A thin lens camera. The basis is orthonormal and derived from a yaw around +z and a pitch
+/// towards +z, so yaw = pitch = 0 looks down +x. With a zero aperture it is a pinhole camera and
+/// everything is in focus.
+///
+/// Scene files store the position and the angles in degrees rather than the basis.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, PartialEq, Pod, Zeroable, Serialize, Deserialize)]
+#[serde(from = "CameraDescription", into = "CameraDescription")]
+pub struct Camera {
+    pub position: Vec3,
+    /// Diameter of the lens. Rays start anywhere on it, blurring what's off the focal plane.
+    pub aperture: f32,
+    forward: Vec3,
+    /// Distance along `forward` of the plane in focus.
+    pub focus_distance: f32,
+    right: Vec3,
+    /// Vertical field of view in radians, the horizontal one follows from the render's aspect
+    /// ratio.
+    pub fov_y: f32,
+    up: Vec3,
+    unused_buffer_4: [u32; 1],
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self::new(Vec3::default(), 0.0, 0.0)
+    }
+}
+
+impl Camera {
+    /// `yaw` and `pitch` are in radians.
+    pub fn new(position: Vec3, yaw: f32, pitch: f32) -> Self {
+        let mut camera = Self::zeroed();
+        camera.position = position;
+        camera.focus_distance = DEFAULT_FOCUS_DISTANCE;
+        camera.fov_y = DEFAULT_FOV_Y;
+        camera.set_orientation(yaw, pitch);
+        camera
+    }
+
+    pub fn set_orientation(&mut self, yaw: f32, pitch: f32) {
+        let (sin_yaw, cos_yaw) = yaw.sin_cos();
+        let (sin_pitch, cos_pitch) = pitch.sin_cos();
+        self.forward = Vec3::new(cos_pitch * cos_yaw, cos_pitch * sin_yaw, sin_pitch);
+        self.right = Vec3::new(sin_yaw, -cos_yaw, 0.0);
+        self.up = self.right.cross(self.forward);
+    }
+
+    pub fn yaw(&self) -> f32 {
+        self.forward.y.atan2(self.forward.x)
+    }
+
+    pub fn pitch(&self) -> f32 {
+        self.forward.z.clamp(-1.0, 1.0).asin()
+    }
+
+    /// The camera on a single line of RON, in the scene file format, to share a viewpoint.
+    pub fn to_ron_line(&self) -> String {
+        ron::to_string(self).expect("A camera is always serializable")
+    }
+
+    /// Reads a camera back from `to_ron_line`, or from the `camera` of a scene file.
+    pub fn from_ron(text: &str) -> Result<Self, ron::error::SpannedError> {
+        ron::from_str(text.trim())
+    }
+
+    pub fn forward(&self) -> Vec3 {
+        self.forward
+    }
+
+    pub fn right(&self) -> Vec3 {
+        self.right
+    }
+
+    pub fn up(&self) -> Vec3 {
+        self.up
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct CameraDescription {
+    position: Vec3,
+    #[serde(default)]
+    yaw: f32,
+    #[serde(default)]
+    pitch: f32,
+    #[serde(default)]
+    aperture: f32,
+    #[serde(default = "default_focus_distance")]
+    focus_distance: f32,
+    /// In degrees, like `yaw` and `pitch`.
+    #[serde(default = "default_fov_y")]
+    fov_y: f32,
+}
+
+fn default_focus_distance() -> f32 {
+    DEFAULT_FOCUS_DISTANCE
+}
+
+fn default_fov_y() -> f32 {
+    DEFAULT_FOV_Y.to_degrees()
+}
+
+impl From<CameraDescription> for Camera {
+    fn from(description: CameraDescription) -> Self {
+        let mut camera = Camera::new(
+            description.position,
+            description.yaw.to_radians(),
+            description.pitch.to_radians(),
+        );
+        camera.aperture = description.aperture;
+        camera.focus_distance = description.focus_distance;
+        camera.fov_y = description
+            .fov_y
+            .clamp(MIN_FOV_Y_DEGREES, MAX_FOV_Y_DEGREES)
+            .to_radians();
+        camera
+    }
+}
+
+impl From<Camera> for CameraDescription {
+    fn from(camera: Camera) -> Self {
+        CameraDescription {
+            position: camera.position,
+            yaw: camera.yaw().to_degrees(),
+            pitch: camera.pitch().to_degrees(),
+            aperture: camera.aperture,
+            focus_distance: camera.focus_distance,
+            fov_y: camera.fov_y.to_degrees(),
+        }
+    }
+}
+
+/// Uniforms of the raytracer kernel.
+#[repr(C)]
+#[derive(Copy, Clone, Default, Debug, PartialEq, Pod, Zeroable)]
+pub struct SceneInfo {
+    pub camera: Camera,
+    pub time: f32,
+    pub sphere_count: u32,
+    pub random_seed: f32,
+    pub frame_count: u32,
+    /// 0 path traces, anything else shows the first hit instead, see `debug_view` in the kernel.
+    pub debug_mode: u32,
+    pub pixel_center: f32,
+    pub accumulate: u32,
+    pub stable_intersection: u32,
+    pub background_color: Vec3,
+    pub background_mode: u32,
+    pub plane_count: u32,
+    pub samples_per_pixel: u32,
+    pub max_bounces: u32,
+    /// Offsets every sample randomly within its pixel, antialiasing the accumulated image.
+    pub jitter: u32,
+    /// The gradient sky blends from `sky_bottom_color` to `sky_top_color` along z.
+    pub sky_top_color: Vec3,
+    pub triangle_count: u32,
+    pub sky_bottom_color: Vec3,
+    /// Scales `sun_color`. 0 turns the sun off.
+    pub sun_intensity: f32,
+    /// Points towards the sun, which lights diffuse surfaces it isn't hidden from.
+    pub sun_direction: Vec3,
+    /// 1 makes the kernel write the first hit of every pixel to the `GBuffer` textures.
+    pub write_gbuffer: u32,
+    pub sun_color: Vec3,
+    /// Render width over its height, so pixels stay square whatever the resolution.
+    pub aspect: f32,
+    pub light_count: u32,
+    /// Scales down samples brighter than this in any channel, 0 disables the clamp. Removes
+    /// fireflies at the cost of some bias, as the clamped light is lost.
+    pub max_sample_radiance: f32,
+    /// Pixel the dispatch starts at when the frame is rendered in tiles, see `raytracer::tiles`,
+    /// or only in a region.
+    pub tile_offset: [u32; 2],
+    /// How far the rays of the ambient occlusion debug view look for occluders.
+    pub ao_radius: f32,
+    /// Rays the ambient occlusion debug view shoots from every first hit, per sample.
+    pub ao_samples: u32,
+    /// How long the shutter stays open from `time`, in the same units. Every sample is traced at
+    /// a random time in between, blurring moving spheres. 0 traces the instant `time`.
+    pub shutter: f32,
+    pub unused_buffer: [u32; 1],
+    /// Pixel the dispatch stops before, to only render a region. 0 stops at the edges of the
+    /// texture.
+    pub tile_end: [u32; 2],
+    pub unused_buffer_2: [u32; 2],
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-6, "{a} != {b}");
+    }
+
+    #[test]
+    fn camera_basis_is_orthonormal() {
+        for (yaw, pitch) in [(0.0, 0.0), (1.0, 0.5), (-2.5, -1.2), (3.0, 1.5)] {
+            let camera = Camera::new(Vec3::default(), yaw, pitch);
+            let (forward, right, up) = (camera.forward(), camera.right(), camera.up());
+            assert_close(forward.length(), 1.0);
+            assert_close(right.length(), 1.0);
+            assert_close(up.length(), 1.0);
+            assert_close(forward.dot(right), 0.0);
+            assert_close(forward.dot(up), 0.0);
+            assert_close(right.dot(up), 0.0);
+            assert_close(camera.yaw(), yaw);
+            assert_close(camera.pitch(), pitch);
+        }
+    }
+
+    #[test]
+    fn vec3_arithmetic() {
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(-2.0, 0.5, 4.0);
+        assert_eq!(a + b, Vec3::new(-1.0, 2.5, 7.0));
+        assert_eq!(a - b, Vec3::new(3.0, 1.5, -1.0));
+        assert_eq!(a * 2.0, Vec3::new(2.0, 4.0, 6.0));
+        assert_eq!(a / 2.0, Vec3::new(0.5, 1.0, 1.5));
+        assert_eq!(-a, Vec3::new(-1.0, -2.0, -3.0));
+        assert_eq!(a.dot(b), 11.0);
+        assert_eq!(a.min(b), Vec3::new(-2.0, 0.5, 3.0));
+        assert_eq!(a.max(b), Vec3::new(1.0, 2.0, 4.0));
+    }
+
+    #[test]
+    fn vec3_cross_is_right_handed_and_orthogonal() {
+        let (x, y, z) = (
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 1.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+        );
+        assert_eq!(x.cross(y), z);
+        assert_eq!(y.cross(x), -z);
+
+        let a = Vec3::new(1.0, 2.0, 3.0);
+        let b = Vec3::new(-2.0, 0.5, 4.0);
+        let c = a.cross(b);
+        assert_close(c.dot(a), 0.0);
+        assert_close(c.dot(b), 0.0);
+        assert_eq!(a.cross(a), Vec3::default());
+    }
+
+    #[test]
+    fn vec3_normalize_keeps_the_direction() {
+        let v = Vec3::new(3.0, 0.0, -4.0);
+        assert_eq!(v.length(), 5.0);
+        assert_eq!(v.normalize(), Vec3::new(0.6, 0.0, -0.8));
+        assert_close(Vec3::splat(-7.0).normalize().length(), 1.0);
+        assert!(Vec3::default().normalize().x.is_nan());
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn vec3_round_trips_through_glam() {
+        let a = Vec3::new(1.0, -2.0, 3.0);
+        let b = Vec3::new(0.5, 4.0, -1.0);
+        assert_eq!(Vec3::from(glam::Vec3::from(a)), a);
+        assert_eq!(Vec3::from(glam::Vec3::from(a).cross(b.into())), a.cross(b));
+    }
+
+    #[cfg(feature = "glam")]
+    #[test]
+    fn mat4_matches_glam() {
+        let m = Mat4::from_rotation_z(0.7) * Mat4::from_translation(Vec3::new(1.0, -2.0, 3.0));
+        let expected = glam::Mat4::from_rotation_z(0.7) * glam::Mat4::from_translation(glam::vec3(1.0, -2.0, 3.0));
+        assert_eq!(Mat4::from(glam::Mat4::from(m)), m);
+        let point = Vec3::new(0.5, 4.0, -1.0);
+        let difference = m.transform_point(point) - expected.transform_point3(point.into()).into();
+        assert!(difference.length() < 1e-5);
+    }
+
+    #[test]
+    fn groups_move_their_spheres() {
+        let sphere = |x| Sphere::new(Vec3::new(x, 0.0, 0.0), 1.0, Material::default());
+        let mut scene = Scene {
+            spheres: vec![sphere(1.0), sphere(2.0), sphere(3.0)],
+            groups: vec![Group {
+                transform: Mat4::IDENTITY,
+                sphere_indices: vec![0, 2],
+            }],
+            ..Default::default()
+        };
+        assert_eq!(scene.group_center(0), Some(Vec3::new(2.0, 0.0, 0.0)));
+
+        let offset = Vec3::new(0.0, 1.0, 2.0);
+        assert!(scene.transform_group(0, Mat4::from_translation(offset)));
+        assert!(scene.transform_group(0, Mat4::from_rotation_z(std::f32::consts::FRAC_PI_2)));
+        let rotated = |v: Vec3| Vec3::new(-v.y, v.x, v.z);
+        for (index, x) in [(0, 1.0), (2, 3.0)] {
+            let expected = rotated(Vec3::new(x, 0.0, 0.0) + offset);
+            assert!((scene.spheres[index].position - expected).length() < 1e-6);
+        }
+        assert_eq!(scene.spheres[1].position, Vec3::new(2.0, 0.0, 0.0));
+        assert!((scene.groups[0].transform.translation() - rotated(offset)).length() < 1e-6);
+
+        scene.remove_sphere(0);
+        assert_eq!(scene.groups[0].sphere_indices, [1]);
+        scene.remove_sphere(1);
+        assert!(scene.groups[0].sphere_indices.is_empty());
+        assert_eq!(scene.group_center(0), None);
+
+        // A stale index, like the selection of a group that was just removed
+        assert!(!scene.transform_group(1, Mat4::from_translation(offset)));
+        assert_eq!(scene.group_center(1), None);
+    }
+
+    #[test]
+    fn mirrors_are_fully_metallic() {
+        let mut material = Material {
+            metallic: 0.25,
+            ..Default::default()
+        };
+        assert_eq!(material.effective_metallic(), 0.25);
+        material.is_mirror = 1;
+        assert_eq!(material.effective_metallic(), 1.0);
+        material.set_kind(MaterialType::Dielectric);
+        assert_eq!(material.effective_metallic(), 0.0);
+        material.set_kind(MaterialType::Mirror);
+        assert_eq!(material.effective_metallic(), 1.0);
+
+        MaterialPreset::Gold.apply(&mut material);
+        assert_eq!(material.metallic, 1.0);
+        MaterialPreset::Plastic.apply(&mut material);
+        assert_eq!(material.metallic, 0.0);
+    }
+
+    #[test]
+    fn presets_keep_emission_and_visibility() {
+        let mut material = Material {
+            emission: Vec3::new(1.0, 0.5, 0.0),
+            emission_strength: 2.0,
+            camera_visible: 0,
+            ..Default::default()
+        };
+
+        MaterialPreset::Plastic.apply(&mut material);
+        assert_eq!(material.kind(), MaterialType::Diffuse);
+        assert_eq!(material.clearcoat_strength, 1.0);
+
+        MaterialPreset::Gold.apply(&mut material);
+        assert_eq!(material.kind(), MaterialType::Mirror);
+        assert_eq!(material.clearcoat_strength, 0.0);
+        assert_eq!(material.emission_strength, 2.0);
+        assert_eq!(material.camera_visible, 0);
+    }
+
+    #[test]
+    fn material_kind_round_trips() {
+        let mut material = Material {
+            is_mirror: 1,
+            ..Default::default()
+        };
+        assert_eq!(material.kind(), MaterialType::Mirror);
+
+        for kind in MaterialType::ALL {
+            material.set_kind(kind);
+            assert_eq!(material.kind(), kind);
+        }
+        assert_eq!(material.is_mirror, 0);
+    }
+
+    #[test]
+    fn camera_survives_json_round_trip() {
+        let position = Vec3::new(1.0, 2.0, 3.0);
+        let mut camera = Camera::new(position, 0.5, -0.25);
+        camera.aperture = 0.2;
+        camera.focus_distance = 3.5;
+        camera.fov_y = 1.2;
+        let json = serde_json::to_string(&camera).unwrap();
+        let parsed: Camera = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed.position, camera.position);
+        assert_close(parsed.yaw(), camera.yaw());
+        assert_close(parsed.pitch(), camera.pitch());
+        assert_eq!(parsed.aperture, 0.2);
+        assert_eq!(parsed.focus_distance, 3.5);
+        assert_close(parsed.fov_y, 1.2);
+    }
+
+    #[test]
+    fn camera_survives_ron_line_round_trip() {
+        let mut camera = Camera::new(Vec3::new(-4.0, 0.5, 2.0), 1.0, 0.3);
+        camera.fov_y = 0.8;
+        let line = camera.to_ron_line();
+        assert!(!line.contains('\n'), "{line}");
+
+        let parsed = Camera::from_ron(&format!("  {line}\n")).unwrap();
+        assert_eq!(parsed.position, camera.position);
+        assert_close(parsed.yaw(), camera.yaw());
+        assert_close(parsed.pitch(), camera.pitch());
+        assert_close(parsed.fov_y, camera.fov_y);
+        assert!(Camera::from_ron("(position: oops)").is_err());
+    }
+
+    #[test]
+    fn cameras_without_a_lens_are_pinholes() {
+        let parsed: Camera = serde_json::from_str(r#"{ "position": { "x": 0.0, "y": 0.0, "z": 0.0 } }"#).unwrap();
+
+        assert_eq!(parsed.aperture, 0.0);
+        assert_eq!(parsed.focus_distance, DEFAULT_FOCUS_DISTANCE);
+        assert_close(parsed.fov_y, DEFAULT_FOV_Y);
+    }
+
+    #[test]
+    fn camera_fov_is_clamped() {
+        let parsed: Camera =
+            serde_json::from_str(r#"{ "position": { "x": 0.0, "y": 0.0, "z": 0.0 }, "fov_y": 179.0 }"#).unwrap();
+
+        assert_close(parsed.fov_y, MAX_FOV_Y_DEGREES.to_radians());
+    }
+}
@@ -0,0 +1,82 @@
+// One iteration of the edge-avoiding À-Trous wavelet filter, see denoiser.rs
+
+struct AtrousSettings {
+    step_width: i32,
+    color_phi: f32,
+    normal_phi: f32,
+    depth_phi: f32,
+    albedo_phi: f32,
+}
+
+@group(0) @binding(0)
+var input_color: texture_2d<f32>;
+
+@group(0) @binding(1)
+var output_color: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(2)
+var normal_depth: texture_2d<f32>;
+
+@group(0) @binding(3)
+var albedo: texture_2d<f32>;
+
+@group(0) @binding(4)
+var<uniform> settings: AtrousSettings;
+
+// The B3 spline, 1/16 1/4 3/8 1/4 1/16, indexed by the distance to the center
+fn kernel_weight(offset: i32) -> f32 {
+    switch abs(offset) {
+        case 0: {
+            return 3.0 / 8.0;
+        }
+        case 1: {
+            return 1.0 / 4.0;
+        }
+        default: {
+            return 1.0 / 16.0;
+        }
+    }
+}
+
+// Must match `WORKGROUP_SIZE` in raytracer.rs
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) id: vec3<u32>) {
+    let size = textureDimensions(input_color);
+    let position = vec2<i32>(id.xy);
+    if (position.x >= size.x || position.y >= size.y) {
+        return;
+    }
+
+    let center_color = textureLoad(input_color, position, 0);
+    let center_normal_depth = textureLoad(normal_depth, position, 0);
+    let center_albedo = textureLoad(albedo, position, 0).rgb;
+    // Depth differences are relative, so far away surfaces aren't all treated as edges
+    let depth_scale = settings.depth_phi * max(center_normal_depth.w, 0.0001);
+
+    var color_sum = vec4<f32>(0.0);
+    var weight_sum = 0.0;
+    for (var y = -2; y <= 2; y++) {
+        for (var x = -2; x <= 2; x++) {
+            let tap = clamp(position + vec2<i32>(x, y) * settings.step_width, vec2<i32>(0), size - 1);
+            let color = textureLoad(input_color, tap, 0);
+            let tap_normal_depth = textureLoad(normal_depth, tap, 0);
+            let tap_albedo = textureLoad(albedo, tap, 0).rgb;
+
+            let color_difference = color.rgb - center_color.rgb;
+            let normal_difference = tap_normal_depth.xyz - center_normal_depth.xyz;
+            let albedo_difference = tap_albedo - center_albedo;
+            let color_weight = exp(-dot(color_difference, color_difference) / settings.color_phi);
+            let normal_weight = exp(-dot(normal_difference, normal_difference) / settings.normal_phi);
+            let depth_weight = exp(-abs(tap_normal_depth.w - center_normal_depth.w) / depth_scale);
+            let albedo_weight = exp(-dot(albedo_difference, albedo_difference) / settings.albedo_phi);
+
+            let weight = kernel_weight(x) * kernel_weight(y) * color_weight * normal_weight * depth_weight
+                * albedo_weight;
+            color_sum += color * weight;
+            weight_sum += weight;
+        }
+    }
+
+    // The center tap always has a non zero weight
+    textureStore(output_color, position, color_sum / weight_sum);
+}
@@ -0,0 +1,874 @@
+struct Material {
+    albedo: vec3<f32>,
+    is_mirror: u32,
+    camera_visible: u32,
+    clearcoat_strength: f32,
+    clearcoat_roughness: f32,
+    material_type: u32,
+    ior: f32,
+    roughness: f32,
+    checkerboard: u32,
+    metallic: f32,
+    emission: vec3<f32>,
+    emission_strength: f32,
+    checker_color: vec3<f32>,
+    checker_scale: f32,
+}
+
+struct Sphere {
+    center: vec3<f32>,
+    radius: f32,
+    material: Material,
+    velocity: vec3<f32>,
+}
+
+struct Plane {
+    point: vec3<f32>,
+    normal: vec3<f32>,
+    material: Material,
+}
+
+struct Triangle {
+    v0: vec3<f32>,
+    v1: vec3<f32>,
+    v2: vec3<f32>,
+    material: Material,
+}
+
+// Must match `BvhNode` in bvh.rs
+struct BvhNode {
+    min: vec3<f32>,
+    // First index into `bvh_indices` of a leaf, or the left child of an inner node, the right one follows it
+    left_or_first: u32,
+    max: vec3<f32>,
+    // 0 for inner nodes
+    count: u32,
+}
+
+struct Ray {
+    direction: vec3<f32>,
+    origin: vec3<f32>,
+}
+
+struct Camera {
+    position: vec3<f32>,
+    aperture: f32,
+    forward: vec3<f32>,
+    focus_distance: f32,
+    right: vec3<f32>,
+    fov_y: f32,
+    up: vec3<f32>,
+}
+
+struct SceneInfo {
+    camera: Camera,
+    time: f32,
+    sphere_count: u32,
+    random_seed: f32,
+    frame_count: u32,
+    debug_mode: u32,
+    pixel_center: f32,
+    accumulate: u32,
+    stable_intersection: u32,
+    background_color: vec3<f32>,
+    background_mode: u32,
+    plane_count: u32,
+    samples_per_pixel: u32,
+    max_bounces: u32,
+    jitter: u32,
+    sky_top_color: vec3<f32>,
+    triangle_count: u32,
+    sky_bottom_color: vec3<f32>,
+    sun_intensity: f32,
+    sun_direction: vec3<f32>,
+    write_gbuffer: u32,
+    sun_color: vec3<f32>,
+    aspect: f32,
+    light_count: u32,
+    max_sample_radiance: f32,
+    tile_offset: vec2<u32>,
+    ao_radius: f32,
+    ao_samples: u32,
+    shutter: f32,
+    tile_end: vec2<u32>,
+}
+
+struct PointLight {
+    position: vec3<f32>,
+    intensity: f32,
+    color: vec3<f32>,
+}
+
+struct HitResult {
+    t: f32,
+    point: vec3<f32>,
+    // Faces the ray, whichever side of the surface it comes from
+    normal: vec3<f32>,
+    // Whether the ray comes from the side the surface's normal points to, outside for spheres
+    front_face: bool,
+    uv: vec2<f32>,
+    material: Material,
+}
+
+@group(0) @binding(0)
+var color_buffer: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(1)
+var<uniform> scene_info: SceneInfo;
+
+@group(0) @binding(2)
+var<storage, read_write> spheres: array<Sphere>;
+
+@group(0) @binding(3)
+var<storage, read_write> progressive_buffer: array<u32>;
+
+@group(0) @binding(4)
+var<storage, read> planes: array<Plane>;
+
+@group(0) @binding(5)
+var<storage, read> bvh_nodes: array<BvhNode>;
+
+@group(0) @binding(6)
+var<storage, read> bvh_indices: array<u32>;
+
+@group(0) @binding(7)
+var<storage, read> triangles: array<Triangle>;
+
+// Only written when `scene_info.write_gbuffer` is set, they are 1x1 otherwise
+@group(0) @binding(8)
+var gbuffer_normal_depth: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(9)
+var gbuffer_albedo: texture_storage_2d<rgba16float, write>;
+
+@group(0) @binding(10)
+var<storage, read> lights: array<PointLight>;
+
+// Must match `FrameChange` in raytracer.rs
+struct FrameChange {
+    sum: atomic<u32>,
+    pixels: atomic<u32>,
+    frame_count: atomic<u32>,
+}
+
+@group(0) @binding(11)
+var<storage, read_write> frame_change: FrameChange;
+
+@group(0) @binding(12)
+var environment: texture_2d<f32>;
+
+@group(0) @binding(13)
+var environment_sampler: sampler;
+
+var<private> seed: vec2<f32>;
+// Time the spheres are intersected at, see `sphere_at_sample_time`
+var<private> sample_time: f32;
+
+// Must match `WORKGROUP_SIZE` in raytracer.rs, the app replaces it with `with_workgroup_size`
+@compute @workgroup_size(8, 8, 1)
+fn main(@builtin(global_invocation_id) GlobalInvocationID : vec3<u32>) {
+    let screen_size: vec2<i32> = textureDimensions(color_buffer);
+    let screen_pos : vec2<i32> = vec2<i32>(GlobalInvocationID.xy + scene_info.tile_offset);
+
+    // The last workgroups overhang the texture when its size isn't a multiple of theirs, and the
+    // render region when there is one
+    var end = screen_size;
+    if (scene_info.tile_end.x != 0u) {
+        end = min(end, vec2<i32>(scene_info.tile_end));
+    }
+    if (screen_pos.x >= end.x || screen_pos.y >= end.y) {
+        return;
+    }
+
+    seed = vec2<f32>(f32(screen_pos.x) / f32(screen_size.x), f32(screen_pos.y) / f32(screen_size.y)) + scene_info.random_seed;
+
+    var average_color = vec4<f32>(0.0, 0.0, 0.0, 0.0);
+    let sample_count = i32(scene_info.samples_per_pixel);
+    for (var i = 0; i < sample_count; i++) {
+        let pixel_color = sample(screen_pos, screen_size);
+        average_color += pixel_color / f32(sample_count);
+    }
+
+    if (scene_info.write_gbuffer == 1u) {
+        write_gbuffer(screen_pos, screen_size);
+    }
+
+    // The first frame after a reset must not read the stale running average, which may be NaN
+    if (scene_info.accumulate == 0u || scene_info.frame_count <= 1u) {
+        textureStore(color_buffer, screen_pos, average_color);
+        return;
+    }
+
+    let unpadded_bytes_per_row = 8 * screen_size.x;
+    // Must match `get_padded_bytes_per_row_from_width` in raytracer.rs
+    let padded_bytes_per_row = unpadded_bytes_per_row + (256 - unpadded_bytes_per_row % 256) % 256;
+    let padded_values_per_row = padded_bytes_per_row / 4;
+    let index = screen_pos.x * 2 + screen_pos.y * padded_values_per_row;
+    let rg = unpack2x16float(progressive_buffer[index]);
+    let ba = unpack2x16float(progressive_buffer[index + 1]);
+    let previous_average = vec4<f32>(rg.x, rg.y, ba.x, ba.y);
+    let progressive_color = previous_average
+        * (f32(scene_info.frame_count - u32(1)) / f32(scene_info.frame_count));
+
+    let final_color = progressive_color + average_color / f32(scene_info.frame_count);
+    textureStore(color_buffer, screen_pos, final_color);
+
+    // Clamped so a 4K frame can't overflow the sum, and randomly rounded so the small changes of a
+    // converging image still add up to the right mean. Must match `FRAME_CHANGE_SCALE` in raytracer.rs
+    let change = dot(abs(final_color.rgb - previous_average.rgb), vec3<f32>(1.0 / 3.0));
+    atomicAdd(&frame_change.sum, u32(min(change, 16.0) * 16.0 + random()));
+    atomicAdd(&frame_change.pixels, 1u);
+    atomicMax(&frame_change.frame_count, scene_info.frame_count);
+}
+
+// Direction of the pinhole camera ray through `pixel`, which spans [0, 1] across the image
+fn camera_ray_direction(pixel: vec2<f32>) -> vec3<f32> {
+    // Size of the image plane one unit in front of the camera
+    let height = 2.0 * tan(scene_info.camera.fov_y * 0.5);
+    let width = height * scene_info.aspect;
+    let horizontal_coefficient = (pixel.x - 0.5) * width;
+    let vertical_coefficient = (pixel.y - 0.5) * height;
+    let camera = scene_info.camera;
+    return normalize(camera.forward + horizontal_coefficient * camera.right + vertical_coefficient * camera.up);
+}
+
+// First hit of the ray through the pixel center, without jitter, depth of field or motion blur so
+// the denoiser gets stable edges
+fn write_gbuffer(screen_pos: vec2<i32>, screen_size: vec2<i32>) {
+    sample_time = scene_info.time;
+    var ray: Ray;
+    ray.origin = scene_info.camera.position;
+    ray.direction = camera_ray_direction((vec2<f32>(screen_pos) + 0.5) / vec2<f32>(screen_size));
+
+    let hit_result = hit_any(ray, true);
+    if (hit_result.t > 0.0001) {
+        textureStore(gbuffer_normal_depth, screen_pos, vec4<f32>(hit_result.normal, hit_result.t));
+        textureStore(gbuffer_albedo, screen_pos, vec4<f32>(hit_result.material.albedo, 1.0));
+    } else {
+        // A zero normal and depth, so misses only blend with other misses
+        textureStore(gbuffer_normal_depth, screen_pos, vec4<f32>(0.0));
+        textureStore(gbuffer_albedo, screen_pos, vec4<f32>(0.0));
+    }
+}
+
+fn sample(screen_pos: vec2<i32>, screen_size: vec2<i32>) -> vec4<f32> {
+    /* let light_pos = vec3<f32>(10.0, 1.3, -2.0); */
+    let forwards = scene_info.camera.forward;
+    let right = scene_info.camera.right;
+    let up = scene_info.camera.up;
+
+    // Jitter around the pixel center, then map the sample to [0, 1] across the image. The seed
+    // changes every frame, so accumulated frames cover the whole pixel and antialias edges.
+    var jitter = vec2<f32>(random(), random()) - 0.5;
+    if (scene_info.jitter == 0u) {
+        jitter = vec2<f32>(0.0, 0.0);
+    }
+    let pixel = (vec2<f32>(screen_pos) + scene_info.pixel_center + jitter) / vec2<f32>(screen_size);
+
+    // Likewise, samples are taken at random times while the shutter is open, which blurs moving
+    // spheres
+    sample_time = scene_info.time + random() * scene_info.shutter;
+
+
+    // Light gathered so far, and how much of the light found further along the path reaches the camera
+    var radiance = vec3<f32>(0.0, 0.0, 0.0);
+    var throughput = vec3<f32>(1.0, 1.0, 1.0);
+
+    let max_bounces = i32(scene_info.max_bounces);
+
+    var ray: Ray;
+    ray.direction = camera_ray_direction(pixel);
+    ray.origin = scene_info.camera.position;
+
+    // Thin lens: start anywhere on the aperture and aim at where the pinhole ray meets the focal plane
+    if (scene_info.camera.aperture > 0.0) {
+        let focus_point = ray.origin + ray.direction * (scene_info.camera.focus_distance / dot(ray.direction, forwards));
+        let lens = random_in_unit_disk() * scene_info.camera.aperture * 0.5;
+        ray.origin += lens.x * right + lens.y * up;
+        ray.direction = normalize(focus_point - ray.origin);
+    }
+
+    if (scene_info.debug_mode != 0u) {
+        return debug_view(ray);
+    }
+
+    for (var i = 0; i < max_bounces; i++) {
+        // Only camera rays skip spheres hidden from the camera
+        var hit_result = hit_any(ray, i == 0);
+        if (hit_result.t > 0.0001) {
+            let material = hit_result.material;
+            radiance += throughput * material.emission * material.emission_strength;
+            if (is_diffuse(material)) {
+                // Only the diffuse part of a partly metallic surface scatters the lights, and only
+                // the light its specular reflection and the clear coat let through reaches it
+                let specular = specular_reflectance(ray.direction, hit_result.normal);
+                let coat = clearcoat_reflectance(material, ray.direction, hit_result.normal);
+                let diffuse = material.albedo * (1.0 - metallic(material)) * (1.0 - specular) * (1.0 - coat);
+                radiance += throughput * diffuse * (sun_light(hit_result) + point_light(hit_result));
+            }
+            scatter(&ray, &throughput, hit_result);
+
+            // Past the first few bounces, end dim paths at random and boost the survivors by as much
+            // as they were likely to end, which keeps the estimate unbiased
+            if (i >= 3) {
+                let survival = clamp(max(throughput.x, max(throughput.y, throughput.z)), 0.05, 1.0);
+                if (random() >= survival) {
+                    break;
+                }
+                throughput /= survival;
+            }
+        }
+        else {
+            // Transparent background only applies to camera rays, escaped bounces still see the sky
+            if (i == 0 && scene_info.background_mode == 2u) {
+                return vec4<f32>(0.0, 0.0, 0.0, 0.0);
+            }
+            radiance += throughput * background(ray);
+            break;
+        }
+    }
+    return vec4<f32>(clamp_radiance(radiance), 1.0);
+}
+
+// Scales down samples brighter than `max_sample_radiance`, keeping their hue. Rare bright paths
+// otherwise show as fireflies for many frames, but the light they lose biases the image darker.
+fn clamp_radiance(radiance: vec3<f32>) -> vec3<f32> {
+    let brightest = max(radiance.x, max(radiance.y, radiance.z));
+    if (scene_info.max_sample_radiance <= 0.0 || brightest <= scene_info.max_sample_radiance) {
+        return radiance;
+    }
+    return radiance * (scene_info.max_sample_radiance / brightest);
+}
+
+// The first hit of a camera ray as `DebugMode` in renderer.rs shows it, black on miss
+fn debug_view(ray: Ray) -> vec4<f32> {
+    let hit_result = hit_any(ray, true);
+    if (hit_result.t <= 0.0001) {
+        return vec4<f32>(0.0, 0.0, 0.0, 1.0);
+    }
+    switch scene_info.debug_mode {
+        case 2u: {
+            return vec4<f32>(hit_result.normal * 0.5 + 0.5, 1.0);
+        }
+        case 3u: {
+            // There is no far plane, so fade smoothly instead of normalizing to a range
+            let depth = 1.0 / (1.0 + 0.1 * hit_result.t);
+            return vec4<f32>(depth, depth, depth, 1.0);
+        }
+        case 4u: {
+            let unoccluded = ambient_occlusion(hit_result);
+            return vec4<f32>(unoccluded, unoccluded, unoccluded, 1.0);
+        }
+        default: {
+            // u in red, v in green
+            return vec4<f32>(hit_result.uv, 0.0, 1.0);
+        }
+    }
+}
+
+// Fraction of `ao_samples` cosine weighted rays from the hit that travel `ao_radius` without hitting
+// anything. Noisy with few rays, but every frame draws new ones so it converges as it accumulates
+fn ambient_occlusion(hit_result: HitResult) -> f32 {
+    let ray_count = max(scene_info.ao_samples, 1u);
+    var unoccluded = 0u;
+    for (var i = 0u; i < ray_count; i++) {
+        var occlusion_ray: Ray;
+        occlusion_ray.origin = hit_result.point;
+        occlusion_ray.direction = sample_cosine_hemisphere(hit_result.normal);
+        let t = hit_any(occlusion_ray, false).t;
+        if (t <= 0.0001 || t >= scene_info.ao_radius) {
+            unoccluded++;
+        }
+    }
+    return f32(unoccluded) / f32(ray_count);
+}
+
+fn is_diffuse(material: Material) -> bool {
+    return material.material_type == 0u && material.is_mirror == 0u;
+}
+
+// Probability of reflecting off the metal rather than the diffuse base. Mirrors, flagged by
+// `is_mirror` or `material_type`, predate `metallic` and are fully metallic. Must match
+// `Material::effective_metallic` in scene.rs
+fn metallic(material: Material) -> f32 {
+    if (material.is_mirror == 1u || material.material_type == 1u) {
+        return 1.0;
+    }
+    if (material.material_type == 2u) {
+        return 0.0;
+    }
+    return clamp(material.metallic, 0.0, 1.0);
+}
+
+// Light reaching a diffuse surface straight from the sun, through a shadow ray. The sun is a
+// delta light that bounces can never hit, so it has to be sampled explicitly at every diffuse hit.
+// The Lambertian 1 / pi is folded into the intensity.
+fn sun_light(hit_result: HitResult) -> vec3<f32> {
+    if (scene_info.sun_intensity <= 0.0) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    let to_sun = normalize(scene_info.sun_direction);
+    let cos_theta = dot(hit_result.normal, to_sun);
+    if (cos_theta <= 0.0) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    var shadow_ray: Ray;
+    shadow_ray.origin = hit_result.point;
+    shadow_ray.direction = to_sun;
+    if (hit_any(shadow_ray, false).t > 0.0001) {
+        return vec3<f32>(0.0, 0.0, 0.0);
+    }
+    return scene_info.sun_color * scene_info.sun_intensity * cos_theta;
+}
+
+// Light reaching a diffuse surface straight from every point light, through a shadow ray each.
+// Like the sun they are delta lights, and the Lambertian 1 / pi is folded into their intensity.
+fn point_light(hit_result: HitResult) -> vec3<f32> {
+    var light = vec3<f32>(0.0, 0.0, 0.0);
+    for (var i: u32 = 0u; i < scene_info.light_count; i++) {
+        let point_light = lights[i];
+        let to_light = point_light.position - hit_result.point;
+        let distance_squared = dot(to_light, to_light);
+        let direction = to_light / sqrt(distance_squared);
+        let cos_theta = dot(hit_result.normal, direction);
+        if (cos_theta <= 0.0) {
+            continue;
+        }
+        var shadow_ray: Ray;
+        shadow_ray.origin = hit_result.point;
+        shadow_ray.direction = direction;
+        // Only what's between the surface and the light casts a shadow
+        let shadow_t = hit_any(shadow_ray, false).t;
+        if (shadow_t > 0.0001 && shadow_t * shadow_t < distance_squared) {
+            continue;
+        }
+        light += point_light.color * point_light.intensity * cos_theta / distance_squared;
+    }
+    return light;
+}
+
+fn background(ray: Ray) -> vec3<f32> {
+    if (scene_info.background_mode == 1u) {
+        return scene_info.background_color;
+    }
+    if (scene_info.background_mode == 3u) {
+        return environment_radiance(ray.direction);
+    }
+    // Gradient sky
+    let t = 0.5 * (ray.direction.z + 1.0);
+    return mix(scene_info.sky_bottom_color, scene_info.sky_top_color, t);
+}
+
+// Equirectangular map wrapping around z. Must match `Environment::uv` in environment.rs
+fn environment_radiance(direction: vec3<f32>) -> vec3<f32> {
+    let d = normalize(direction);
+    let u = 0.5 + atan2(d.y, d.x) / 6.2831853;
+    let v = acos(clamp(d.z, -1.0, 1.0)) / 3.14159265;
+    // Compute shaders have no derivatives to pick a mip level with, there is only one anyway
+    return textureSampleLevel(environment, environment_sampler, vec2<f32>(u, v), 0.0).rgb;
+}
+
+fn scatter(ray: ptr<function, Ray>, color: ptr<function, vec3<f32>>, hit_result: HitResult) {
+    let material = hit_result.material;
+    if (material.clearcoat_strength > 0.0) {
+        // Pick the coat with its Fresnel reflectance, otherwise fall through to the base
+        let reflectance = clearcoat_reflectance(material, (*ray).direction, hit_result.normal);
+        if (random() < reflectance) {
+            (*ray).origin = hit_result.point;
+            let mirror_direction = reflect((*ray).direction, hit_result.normal);
+            let direction = normalize(mirror_direction + material.clearcoat_roughness * random_in_unit_sphere());
+            if (dot(direction, hit_result.normal) > 0.0) {
+                (*ray).direction = direction;
+            }
+            else {
+                (*ray).direction = mirror_direction;
+            }
+            // The coat is clear, so it doesn't tint the reflection
+            return;
+        }
+    }
+
+    if (material.material_type == 2u) {
+        // Dielectric: rays hitting the back face are leaving the material
+        let direction = normalize((*ray).direction);
+        let normal = hit_result.normal;
+        var eta_ratio = 1.0 / material.ior;
+        if (!hit_result.front_face) {
+            eta_ratio = material.ior;
+        }
+
+        let cos_theta = min(dot(-direction, normal), 1.0);
+        let sin_theta = sqrt(1.0 - cos_theta * cos_theta);
+        let cannot_refract = eta_ratio * sin_theta > 1.0;
+
+        (*ray).origin = hit_result.point;
+        if (cannot_refract || schlick(cos_theta, material.ior) > random()) {
+            (*ray).direction = reflect(direction, normal);
+        }
+        else {
+            (*ray).direction = refract(direction, normal, eta_ratio);
+        }
+        *color *= material.albedo;
+        return;
+    }
+
+    // Pick the metal with probability `metallic`, each lobe keeps its own weight. Full metals
+    // don't draw a random number, so they trace like before `metallic`
+    let metal = metallic(material);
+    if (metal >= 1.0 || (metal > 0.0 && random() < metal)) {
+        *color *= material.albedo * reflect_glossy(ray, hit_result, material.roughness);
+        return;
+    }
+
+    // The rest is a dielectric: its surface reflects with its Fresnel reflectance, untinted, and
+    // the diffuse layer under it gets the light that goes through
+    if (random() < specular_reflectance((*ray).direction, hit_result.normal)) {
+        *color *= reflect_glossy(ray, hit_result, material.roughness);
+        return;
+    }
+    (*ray).origin = hit_result.point;
+    // The Lambertian BRDF, albedo / pi, times cos(theta) over the pdf leaves only the albedo
+    (*ray).direction = sample_cosine_hemisphere(hit_result.normal);
+    *color *= material.albedo;
+}
+
+// Reflects the ray like a mirror, or around a microfacet normal drawn from the GGX distribution
+// when `roughness` blurs the reflection. Returns the weight of the reflected light, 0 when it went
+// below the surface.
+fn reflect_glossy(ray: ptr<function, Ray>, hit_result: HitResult, roughness: f32) -> f32 {
+    (*ray).origin = hit_result.point;
+    if (roughness <= 0.0) {
+        (*ray).direction = reflect((*ray).direction, hit_result.normal);
+        return 1.0;
+    }
+    let normal = hit_result.normal;
+    let alpha = roughness * roughness;
+    let view = -normalize((*ray).direction);
+    let half_vector = sample_ggx(normal, alpha);
+    let direction = reflect(-view, half_vector);
+    let n_dot_l = dot(normal, direction);
+    let n_dot_v = dot(normal, view);
+    if (n_dot_l <= 0.0 || n_dot_v <= 0.0) {
+        // Reflected below the surface, the light is lost
+        return 0.0;
+    }
+    (*ray).direction = direction;
+    // D cancels with the sampling pdf, leaving the masking-shadowing term and the Jacobian
+    let v_dot_h = max(dot(view, half_vector), 0.0);
+    let n_dot_h = max(dot(normal, half_vector), 1e-6);
+    return smith_g1(n_dot_v, alpha) * smith_g1(n_dot_l, alpha) * v_dot_h / (n_dot_v * n_dot_h);
+}
+
+// Fresnel reflectance of a dielectric surface hit along `direction`. Its F0 of 0.04 is that of
+// an ior of 1.5, which most plastics, paints and coatings are close to.
+fn specular_reflectance(direction: vec3<f32>, normal: vec3<f32>) -> f32 {
+    let cos_theta = min(dot(-direction, normal), 1.0);
+    return schlick(cos_theta, 1.5);
+}
+
+// Fraction of the light arriving along `direction` that the clear coat reflects, the rest reaches
+// the base
+fn clearcoat_reflectance(material: Material, direction: vec3<f32>, normal: vec3<f32>) -> f32 {
+    if (material.clearcoat_strength <= 0.0) {
+        return 0.0;
+    }
+    return material.clearcoat_strength * specular_reflectance(direction, normal);
+}
+
+// Half vector with a pdf of D(h) * dot(n, h) for the GGX distribution of roughness `alpha`
+fn sample_ggx(normal: vec3<f32>, alpha: f32) -> vec3<f32> {
+    let u = random();
+    let phi = 6.2831853 * random();
+    let cos_theta = sqrt((1.0 - u) / (1.0 + (alpha * alpha - 1.0) * u));
+    let sin_theta = sqrt(max(1.0 - cos_theta * cos_theta, 0.0));
+
+    // Any tangent frame will do, the distribution is isotropic
+    let local = vec3<f32>(sin_theta * cos(phi), sin_theta * sin(phi), cos_theta);
+    return normalize(tangent_frame(normal) * local);
+}
+
+// Direction with a pdf of cos(theta) / pi, theta being its angle to `normal`. Importance samples
+// the cos(theta) every BRDF is multiplied with, a BRDF f only needs weighting by f * pi.
+fn sample_cosine_hemisphere(normal: vec3<f32>) -> vec3<f32> {
+    // A point drawn uniformly in the disk, projected up onto the hemisphere
+    let disk = random_in_unit_disk();
+    let local = vec3<f32>(disk, sqrt(max(1.0 - dot(disk, disk), 0.0)));
+    return normalize(tangent_frame(normal) * local);
+}
+
+// Columns of tangent, bitangent and `normal`, taking local directions around +z to world space
+fn tangent_frame(normal: vec3<f32>) -> mat3x3<f32> {
+    var helper = vec3<f32>(1.0, 0.0, 0.0);
+    if (abs(normal.x) > 0.9) {
+        helper = vec3<f32>(0.0, 1.0, 0.0);
+    }
+    let tangent = normalize(cross(helper, normal));
+    let bitangent = cross(normal, tangent);
+    return mat3x3<f32>(tangent, bitangent, normal);
+}
+
+// Smith masking for GGX along a direction making `cos_theta` with the normal
+fn smith_g1(cos_theta: f32, alpha: f32) -> f32 {
+    let alpha2 = alpha * alpha;
+    return 2.0 * cos_theta / (cos_theta + sqrt(alpha2 + (1.0 - alpha2) * cos_theta * cos_theta));
+}
+
+// Schlick's approximation of the Fresnel reflectance of a dielectric in air
+fn schlick(cos_theta: f32, ior: f32) -> f32 {
+    var r0 = (1.0 - ior) / (1.0 + ior);
+    r0 = r0 * r0;
+    return r0 + (1.0 - r0) * pow(1.0 - cos_theta, 5.0);
+}
+
+// Sphere `index` where it has moved to at `sample_time`. Must match `Sphere::position_at` in
+// scene.rs
+fn sphere_at_sample_time(index: u32) -> Sphere {
+    var sphere = spheres[index];
+    sphere.center += sphere.velocity * sample_time;
+    return sphere;
+}
+
+fn hit_any(ray: Ray, primary: bool) -> HitResult {
+    var min_t: f32 = -1.0;
+    var sphere_hit: u32;
+    // The median split keeps the BVH balanced, so 32 entries are plenty
+    var stack: array<u32, 32>;
+    var stack_size = 0u;
+    if (scene_info.sphere_count > 0u) {
+        stack[0] = 0u;
+        stack_size = 1u;
+    }
+    let inverse_direction = 1.0 / ray.direction;
+    while (stack_size > 0u) {
+        stack_size -= 1u;
+        let node = bvh_nodes[stack[stack_size]];
+        if (!hit_aabb(ray, inverse_direction, node.min, node.max, min_t)) {
+            continue;
+        }
+        if (node.count == 0u) {
+            stack[stack_size] = node.left_or_first;
+            stack[stack_size + 1u] = node.left_or_first + 1u;
+            stack_size += 2u;
+            continue;
+        }
+        for (var j: u32 = node.left_or_first; j < node.left_or_first + node.count; j++) {
+            let i = bvh_indices[j];
+            let sphere = sphere_at_sample_time(i);
+            if (primary && sphere.material.camera_visible == 0u) {
+                continue;
+            }
+            let t: f32 = hit(ray, sphere);
+            if (t >= 0.0) {
+                if (min_t < 0.0 || t < min_t) {
+                    min_t = t;
+                    sphere_hit = i;
+                }
+            }
+        }
+    }
+    var plane_hit: u32;
+    var is_plane = false;
+    for (var i: u32 = 0u; i < scene_info.plane_count; i++) {
+        let plane = planes[i];
+        if (primary && plane.material.camera_visible == 0u) {
+            continue;
+        }
+        let t: f32 = hit_plane(ray, plane);
+        if (t >= 0.0) {
+            if (min_t < 0.0 || t < min_t) {
+                min_t = t;
+                plane_hit = i;
+                is_plane = true;
+            }
+        }
+    }
+    var triangle_hit: u32;
+    var triangle_uv: vec2<f32>;
+    var is_triangle = false;
+    for (var i: u32 = 0u; i < scene_info.triangle_count; i++) {
+        let triangle = triangles[i];
+        if (primary && triangle.material.camera_visible == 0u) {
+            continue;
+        }
+        let t_uv = hit_triangle(ray, triangle);
+        if (t_uv.x >= 0.0) {
+            if (min_t < 0.0 || t_uv.x < min_t) {
+                min_t = t_uv.x;
+                triangle_hit = i;
+                triangle_uv = t_uv.yz;
+                is_triangle = true;
+                is_plane = false;
+            }
+        }
+    }
+    var result: HitResult;
+    result.t = min_t;
+    result.point = ray.origin + ray.direction * min_t;
+    if (is_triangle) {
+        let triangle = triangles[triangle_hit];
+        let normal = normalize(cross(triangle.v1 - triangle.v0, triangle.v2 - triangle.v0));
+        // Like planes, triangles are two sided
+        result.front_face = dot(ray.direction, normal) <= 0.0;
+        result.normal = select(-normal, normal, result.front_face);
+        result.uv = triangle_uv;
+        result.material = triangle.material;
+    } else if (is_plane) {
+        let plane = planes[plane_hit];
+        let normal = normalize(plane.normal);
+        // Face the ray, so both sides of a plane scatter the same way
+        result.front_face = dot(ray.direction, normal) <= 0.0;
+        result.normal = select(-normal, normal, result.front_face);
+        result.uv = vec2<f32>(0.0, 0.0);
+        result.material = plane.material;
+    } else {
+        let outward_normal = normalize(result.point - sphere_at_sample_time(sphere_hit).center);
+        // Rays starting inside, like refracted ones or those of a camera inside the sphere, hit
+        // it from within, where diffuse bounces and lights must stay
+        result.front_face = dot(ray.direction, outward_normal) <= 0.0;
+        result.normal = select(-outward_normal, outward_normal, result.front_face);
+        result.uv = sphere_uv(outward_normal);
+        result.material = spheres[sphere_hit].material;
+    }
+
+    if (result.material.checkerboard == 1u) {
+        result.material.albedo = checker_albedo(result.material, result.point);
+    }
+    return result;
+}
+
+// Alternates between the two colors in squares along x and y, z being up
+fn checker_albedo(material: Material, point: vec3<f32>) -> vec3<f32> {
+    var scale = material.checker_scale;
+    if (scale <= 0.0) {
+        scale = 1.0;
+    }
+    let cell = floor(point.xy / scale);
+    // % keeps the sign of the dividend, so odd negative sums give -1
+    if (abs((cell.x + cell.y) % 2.0) >= 1.0) {
+        return material.checker_color;
+    }
+    return material.albedo;
+}
+
+// Slab test, skipping boxes entirely behind the ray or further than the closest hit so far
+fn hit_aabb(ray: Ray, inverse_direction: vec3<f32>, box_min: vec3<f32>, box_max: vec3<f32>, closest_t: f32) -> bool {
+    let t0 = (box_min - ray.origin) * inverse_direction;
+    let t1 = (box_max - ray.origin) * inverse_direction;
+    let near = max(max(min(t0.x, t1.x), min(t0.y, t1.y)), min(t0.z, t1.z));
+    let far = min(min(max(t0.x, t1.x), max(t0.y, t1.y)), max(t0.z, t1.z));
+    return near <= far && far >= 0.0 && (closest_t < 0.0 || near <= closest_t);
+}
+
+fn hit_plane(ray: Ray, plane: Plane) -> f32 {
+    let denominator = dot(plane.normal, ray.direction);
+    if (abs(denominator) < 1e-6) {
+        return -1.0;
+    }
+    let t = dot(plane.point - ray.origin, plane.normal) / denominator;
+    // Don't hit the plane a bounce starts from
+    if (t < 0.0001) {
+        return -1.0;
+    }
+    return t;
+}
+
+// Möller–Trumbore. Returns t and the barycentric coordinates of v1 and v2, or a negative t on miss.
+fn hit_triangle(ray: Ray, triangle: Triangle) -> vec3<f32> {
+    let edge1 = triangle.v1 - triangle.v0;
+    let edge2 = triangle.v2 - triangle.v0;
+    let p = cross(ray.direction, edge2);
+    let determinant = dot(edge1, p);
+    // Parallel to the triangle, or degenerate
+    if (abs(determinant) < 1e-8) {
+        return vec3<f32>(-1.0, 0.0, 0.0);
+    }
+    let inverse_determinant = 1.0 / determinant;
+    let s = ray.origin - triangle.v0;
+    let u = dot(s, p) * inverse_determinant;
+    if (u < 0.0 || u > 1.0) {
+        return vec3<f32>(-1.0, 0.0, 0.0);
+    }
+    let q = cross(s, edge1);
+    let v = dot(ray.direction, q) * inverse_determinant;
+    if (v < 0.0 || u + v > 1.0) {
+        return vec3<f32>(-1.0, 0.0, 0.0);
+    }
+    let t = dot(edge2, q) * inverse_determinant;
+    // Don't hit the triangle a bounce starts from
+    if (t < 0.0001) {
+        return vec3<f32>(-1.0, 0.0, 0.0);
+    }
+    return vec3<f32>(t, u, v);
+}
+
+// Spherical coordinates of a point on the unit sphere (z is up).
+// u follows the azimuth around z, v the inclination from the north pole.
+fn sphere_uv(normal: vec3<f32>) -> vec2<f32> {
+    let pi = 3.14159265;
+    let u = (atan2(normal.y, normal.x) + pi) / (2.0 * pi);
+    let v = acos(clamp(normal.z, -1.0, 1.0)) / pi;
+    return vec2<f32>(u, v);
+}
+
+fn hit(ray: Ray, sphere: Sphere) -> f32 {
+    let oc = ray.origin - sphere.center;
+    let a: f32 = dot(ray.direction, ray.direction);
+    let half_b = dot(oc, ray.direction);
+    let c = dot(oc, oc) - sphere.radius * sphere.radius;
+    let discriminant = half_b * half_b - a * c;
+    if (discriminant < 0.0) {
+        return -1.0;
+    }
+    var near: f32;
+    var far: f32;
+    if (scene_info.stable_intersection == 0u) {
+        near = (-half_b - sqrt(discriminant) ) / a;
+        far = (-half_b + sqrt(discriminant) ) / a;
+    } else {
+        // Only add terms of the same sign, then get the other root from t0 * t1 = c / a
+        var sign_b = 1.0;
+        if (half_b < 0.0) {
+            sign_b = -1.0;
+        }
+        let q = -(half_b + sign_b * sqrt(discriminant));
+        // Both roots are 0 when the ray starts on the sphere and only grazes it
+        if (q == 0.0) {
+            return -1.0;
+        }
+        near = min(q / a, c / q);
+        far = max(q / a, c / q);
+    }
+    // Rays starting inside the sphere, like refracted ones, hit its far side
+    if (near > 0.0001) {
+        return near;
+    }
+    if (far > 0.0001) {
+        return far;
+    }
+    return -1.0;
+}
+
+fn random() -> f32 {
+    seed += 0.1;
+    return fract(sin(dot(seed.xy, vec2(12.9898,78.233))) * 43758.5453);
+}
+
+fn random_in_unit_sphere() -> vec3<f32> {
+    while (true) {
+        let x = (random() * 2.0) - 1.0;
+        let y = (random() * 2.0) - 1.0;
+        let z = (random() * 2.0) - 1.0;
+        let value = vec3<f32>(x, y, z);
+        if (dot(value, value) > 1.0) {
+            continue;
+        }
+        return value;
+    }
+    return vec3<f32>(0.0, 0.0, 0.0);
+}
+
+fn random_in_unit_disk() -> vec2<f32> {
+    let radius = sqrt(random());
+    let angle = 6.2831853 * random();
+    return radius * vec2<f32>(cos(angle), sin(angle));
+}
@@ -0,0 +1,632 @@
+use renderer::renderer::{KernelSettings, PollMode, Renderer, RendererError};
+use renderer::scene::{
+    Camera, Material, Plane, PointLight, RenderSettings, Scene, Sphere, Triangle, Vec3, DEFAULT_SKY_BOTTOM_COLOR,
+    DEFAULT_SKY_TOP_COLOR,
+};
+
+fn sphere_in_front_of_camera() -> Scene {
+    Scene {
+        camera: Camera::new(Vec3::default(), 0.0, 0.0),
+        spheres: vec![Sphere::new(
+            Vec3::new(5.0, 0.0, 0.0),
+            1.0,
+            // A black metal, as even black dielectrics reflect some of the sky
+            Material {
+                albedo: Vec3::new(0.0, 0.0, 0.0),
+                metallic: 1.0,
+                ..Default::default()
+            },
+        )],
+        ..Default::default()
+    }
+}
+
+fn pixel(view: &[u8], dimensions: renderer::renderer::BufferDimensions, x: usize, y: usize) -> [f32; 4] {
+    let row = &view[y * dimensions.padded_bytes_per_row..][..dimensions.unpadded_bytes_per_row];
+    let pixel: &[half::f16] = &bytemuck::cast_slice(row)[x * 4..][..4];
+    [
+        pixel[0].to_f32(),
+        pixel[1].to_f32(),
+        pixel[2].to_f32(),
+        pixel[3].to_f32(),
+    ]
+}
+
+fn mean_color(view: &[u8], dimensions: renderer::renderer::BufferDimensions) -> [f32; 3] {
+    let mut sum = [0.0; 3];
+    for y in 0..dimensions.height {
+        for x in 0..dimensions.width {
+            let color = pixel(view, dimensions, x, y);
+            (0..3).for_each(|channel| sum[channel] += color[channel]);
+        }
+    }
+    sum.map(|channel| channel / (dimensions.width * dimensions.height) as f32)
+}
+
+#[test]
+fn empty_sizes_are_rejected() {
+    pollster::block_on(async {
+        let result = Renderer::new(0, 64).await;
+        assert!(matches!(result, Err(RendererError::EmptySize { width: 0, height: 64 })));
+    });
+}
+
+#[test]
+fn renders_one_frame_headless() {
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&sphere_in_front_of_camera(), 0.0)
+            .await
+            .expect("Could not read back the frame");
+
+        assert_eq!(dimensions.width, 64);
+        assert_eq!(dimensions.height, 64);
+        assert_eq!(view.len(), dimensions.padded_bytes_per_row * dimensions.height);
+
+        // The black sphere covers the center while the corners see the sky
+        let center = pixel(&view, dimensions, 32, 32);
+        let corner = pixel(&view, dimensions, 0, 0);
+        assert_eq!(center[..3], [0.0; 3], "The sphere isn't black: {center:?}");
+        assert!(
+            corner[..3].iter().any(|channel| *channel > 0.0),
+            "The sky is black: {corner:?}"
+        );
+        assert_eq!(corner[3], 1.0);
+    });
+}
+
+#[test]
+fn renders_on_worker_thread_without_blocking_poll() {
+    let mut renderer = pollster::block_on(Renderer::new(64, 64)).expect("Could not create the renderer");
+    renderer.set_poll_mode(PollMode::Yield);
+
+    let worker = std::thread::spawn(move || {
+        pollster::block_on(async {
+            let (view, dimensions) = renderer
+                .render(&Scene::default(), 0.0)
+                .await
+                .expect("Could not read back the frame");
+            assert_eq!(view.len(), dimensions.padded_bytes_per_row * dimensions.height);
+        });
+    });
+    worker.join().unwrap();
+}
+
+#[test]
+fn grows_buffers_for_large_scenes() {
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres = vec![scene.spheres[0]; 100];
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(16, 16).await.expect("Could not create the renderer");
+        renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+    });
+}
+
+#[test]
+fn renders_triangles() {
+    let black = Material {
+        albedo: Vec3::new(0.0, 0.0, 0.0),
+        metallic: 1.0,
+        ..Default::default()
+    };
+    let scene = Scene {
+        camera: Camera::new(Vec3::default(), 0.0, 0.0),
+        triangles: vec![Triangle::new(
+            Vec3::new(5.0, -1.0, -1.0),
+            Vec3::new(5.0, 1.0, -1.0),
+            Vec3::new(5.0, 0.0, 1.0),
+            black,
+        )],
+        ..Default::default()
+    };
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let center = pixel(&view, dimensions, 32, 32);
+        assert_eq!(center[..3], [0.0; 3], "The triangle isn't black: {center:?}");
+    });
+}
+
+#[test]
+fn point_lights_light_diffuse_surfaces() {
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].mat = Material {
+        albedo: Vec3::new(0.5, 0.5, 0.5),
+        ..Default::default()
+    };
+    let mut lit = scene.clone();
+    // Between the camera and the sphere, which it doesn't hide as bounces never hit point lights
+    lit.lights = vec![PointLight::new(Vec3::new(2.0, 0.0, 0.0), Vec3::new(1.0, 1.0, 1.0), 8.0)];
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        // Shadow rays don't draw random numbers, so both frames trace the same paths
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let unlit_center = pixel(&view, dimensions, 32, 32);
+        drop(view);
+        let (view, dimensions) = renderer.render(&lit, 0.0).await.expect("Could not read back the frame");
+        let lit_center = pixel(&view, dimensions, 32, 32);
+        assert!(
+            lit_center[0] > unlit_center[0] + 0.5,
+            "The light doesn't reach the sphere: {lit_center:?} vs {unlit_center:?}"
+        );
+    });
+}
+
+#[test]
+fn lights_reach_the_inside_of_spheres() {
+    // The camera and the light are inside a large sphere, which must be lit from within
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0] = Sphere::new(
+        Vec3::default(),
+        10.0,
+        Material {
+            albedo: Vec3::new(0.5, 0.5, 0.5),
+            ..Default::default()
+        },
+    );
+    let mut lit = scene.clone();
+    lit.lights = vec![PointLight::new(
+        Vec3::new(5.0, 0.0, 0.0),
+        Vec3::new(1.0, 1.0, 1.0),
+        50.0,
+    )];
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let unlit_center = pixel(&view, dimensions, 32, 32);
+        drop(view);
+        let (view, dimensions) = renderer.render(&lit, 0.0).await.expect("Could not read back the frame");
+        let lit_center = pixel(&view, dimensions, 32, 32);
+        assert!(
+            lit_center[0] > unlit_center[0] + 0.5,
+            "The light doesn't reach the inside of the sphere: {lit_center:?} vs {unlit_center:?}"
+        );
+    });
+}
+
+#[test]
+fn russian_roulette_conserves_energy_in_a_furnace() {
+    // Inside a closed mirror sphere emitting 0.5 with albedo 0.5, every path gathers
+    // 0.5 + 0.25 + ... = 1, as long as the paths ended early are made up for by the ones that survive.
+    // A diffuse furnace would also reflect the 4% of its dielectric surface untinted
+    let scene = Scene {
+        camera: Camera::new(Vec3::default(), 0.0, 0.0),
+        spheres: vec![Sphere::new(
+            Vec3::default(),
+            10.0,
+            Material {
+                albedo: Vec3::new(0.5, 0.5, 0.5),
+                metallic: 1.0,
+                emission: Vec3::new(1.0, 1.0, 1.0),
+                emission_strength: 0.5,
+                ..Default::default()
+            },
+        )],
+        settings: Some(RenderSettings {
+            samples_per_pixel: 64,
+            max_bounces: 1000,
+        }),
+        ..Default::default()
+    };
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(32, 32).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let mean = mean_color(&view, dimensions);
+        assert!(
+            mean.iter().all(|channel| (channel - 1.0).abs() < 0.02),
+            "The furnace isn't uniformly 1: {mean:?}"
+        );
+    });
+}
+
+#[test]
+fn accumulation_converges_over_a_thousand_frames() {
+    // As many frames as the app accumulates by default before it stops
+    const FRAMES: u32 = 1024;
+    // Inside a closed mirror sphere emitting 0.5, like the furnace above but with one noisy sample a frame
+    let furnace = |albedo| Scene {
+        camera: Camera::new(Vec3::default(), 0.0, 0.0),
+        spheres: vec![Sphere::new(
+            Vec3::default(),
+            10.0,
+            Material {
+                albedo: Vec3::new(albedo, albedo, albedo),
+                metallic: 1.0,
+                emission: Vec3::new(1.0, 1.0, 1.0),
+                emission_strength: 0.5,
+                ..Default::default()
+            },
+        )],
+        settings: Some(RenderSettings {
+            samples_per_pixel: 1,
+            max_bounces: 1000,
+        }),
+        ..Default::default()
+    };
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(4, 4).await.expect("Could not create the renderer");
+
+        // Black walls give every frame the same 0.5, which the weights of the average must keep
+        renderer.set_scene(&furnace(0.0));
+        {
+            let (view, dimensions) = renderer
+                .render_accumulated(FRAMES, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let mean = mean_color(&view, dimensions);
+            assert!(
+                mean.iter().all(|channel| (channel - 0.5).abs() < 1e-3),
+                "The constant frames don't average to 0.5: {mean:?}"
+            );
+        }
+        assert_eq!(renderer.frame_count(), FRAMES);
+
+        // Gray walls gather 1 on average, which the late frames must still refine towards
+        renderer.set_scene(&furnace(0.5));
+        let (view, dimensions) = renderer
+            .render_accumulated(FRAMES, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let mean = mean_color(&view, dimensions);
+        assert!(
+            mean.iter().all(|channel| (channel - 1.0).abs() < 0.05),
+            "The noisy frames don't average to 1: {mean:?}"
+        );
+    });
+}
+
+#[test]
+fn diffuse_bounces_follow_the_cosine() {
+    // A white floor lit by the gradient sky only, which blends linearly with the z of the bounce.
+    // Sampled with a pdf of cos(theta) / pi, that z averages 2/3, so the floor shows the sky 5/6 of
+    // the way up. Uniformly sampled, it would be 3/4. Looked at head on, its specular reflection
+    // sends 4% of the rays straight up to the top of the sky instead.
+    let scene = Scene {
+        camera: Camera::new(Vec3::new(0.0, 0.0, 1.0), 0.0, -std::f32::consts::FRAC_PI_2),
+        planes: vec![Plane::new(
+            Vec3::default(),
+            Vec3::new(0.0, 0.0, 1.0),
+            Material {
+                albedo: Vec3::new(1.0, 1.0, 1.0),
+                ..Default::default()
+            },
+        )],
+        settings: Some(RenderSettings {
+            samples_per_pixel: 64,
+            max_bounces: 2,
+        }),
+        ..Default::default()
+    };
+    let [bottom, top] = [DEFAULT_SKY_BOTTOM_COLOR, DEFAULT_SKY_TOP_COLOR].map(|color| [color.x, color.y, color.z]);
+    let expected: [f32; 3] = std::array::from_fn(|channel| {
+        let diffuse = bottom[channel] + (top[channel] - bottom[channel]) * 5.0 / 6.0;
+        diffuse * 0.96 + top[channel] * 0.04
+    });
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(16, 16).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let mean = mean_color(&view, dimensions);
+        assert!(
+            mean.iter()
+                .zip(expected)
+                .all(|(channel, expected)| (channel - expected).abs() < 0.01),
+            "The floor is {mean:?} rather than {expected:?}"
+        );
+    });
+}
+
+#[test]
+fn bright_samples_are_clamped_keeping_their_hue() {
+    // The camera sees the emission of the black sphere and nothing else
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].mat.emission = Vec3::new(1.0, 0.5, 0.0);
+    scene.spheres[0].mat.emission_strength = 100.0;
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        for (max_sample_radiance, expected) in [(0.0, [100.0, 50.0, 0.0]), (10.0, [10.0, 5.0, 0.0])] {
+            renderer.set_kernel_settings(KernelSettings {
+                max_sample_radiance,
+                ..Default::default()
+            });
+            let (view, dimensions) = renderer
+                .render(&scene, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let center = pixel(&view, dimensions, 32, 32);
+            assert!(
+                center[..3]
+                    .iter()
+                    .zip(expected)
+                    .all(|(channel, expected)| (channel - expected).abs() <= expected * 1e-2),
+                "Clamping to {max_sample_radiance} gives {center:?}"
+            );
+        }
+    });
+}
+
+#[test]
+fn moving_spheres_follow_the_time() {
+    // The black sphere leaves the center of the view by time 1
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].velocity = Vec3::new(0.0, 3.0, 0.0);
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(64, 64).await.expect("Could not create the renderer");
+        let (view, dimensions) = renderer
+            .render(&scene, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let before = pixel(&view, dimensions, 32, 32);
+        drop(view);
+        let (view, dimensions) = renderer
+            .render(&scene, 1.0)
+            .await
+            .expect("Could not read back the frame");
+        let after = pixel(&view, dimensions, 32, 32);
+        assert_eq!(before[..3], [0.0; 3], "The sphere isn't in view at first: {before:?}");
+        assert!(
+            after[..3].iter().any(|channel| *channel > 0.0),
+            "The sphere didn't move: {after:?}"
+        );
+    });
+}
+
+#[test]
+fn edits_update_the_uploaded_scene() {
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(16, 16).await.expect("Could not create the renderer");
+        renderer.set_scene(&sphere_in_front_of_camera());
+        {
+            let (view, dimensions) = renderer
+                .render_accumulated(4, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let center = pixel(&view, dimensions, 8, 8);
+            assert_eq!(center[..3], [0.0; 3], "The sphere isn't in view: {center:?}");
+        }
+        assert_eq!(renderer.frame_count(), 4);
+
+        // Moving the sphere behind the camera rebuilds the BVH and restarts the average
+        let mut sphere = renderer.scene().spheres[0];
+        sphere.position = Vec3::new(-5.0, 0.0, 0.0);
+        assert!(renderer.set_sphere(0, sphere));
+        assert!(!renderer.set_sphere(1, sphere), "There is no second sphere");
+        assert_eq!(renderer.frame_count(), 0);
+        {
+            let (view, dimensions) = renderer
+                .render_accumulated(1, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let center = pixel(&view, dimensions, 8, 8);
+            assert!(
+                center[..3].iter().any(|channel| *channel > 0.0),
+                "The sphere didn't move: {center:?}"
+            );
+        }
+
+        // Turning around finds it again
+        renderer.set_camera(Camera::new(Vec3::default(), std::f32::consts::PI, 0.0));
+        let (view, dimensions) = renderer
+            .render_accumulated(1, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let center = pixel(&view, dimensions, 8, 8);
+        assert_eq!(center[..3], [0.0; 3], "The camera didn't turn: {center:?}");
+    });
+}
+
+#[test]
+fn single_pixels_are_sampled_at_their_center() {
+    // Only the ray through the middle of the image hits the black sphere straight ahead, the one
+    // through its corner sees the sky
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].radius = 0.1;
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(1, 1).await.expect("Could not create the renderer");
+        for (pixel_center, hits) in [(0.5, true), (0.0, false)] {
+            renderer.set_kernel_settings(KernelSettings {
+                pixel_center,
+                jitter: false,
+                ..Default::default()
+            });
+            let (view, dimensions) = renderer
+                .render(&scene, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let color = pixel(&view, dimensions, 0, 0);
+            assert_eq!(
+                color[..3] == [0.0; 3],
+                hits,
+                "Pixel center {pixel_center} gives {color:?}"
+            );
+        }
+    });
+}
+
+#[test]
+fn centered_pixels_span_the_image_symmetrically() {
+    // In the normals debug view of a sphere straight ahead, mirrored pixels have mirrored normals
+    // along the camera's right vector, -y, and the same x and z
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].radius = 4.9;
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(4, 1).await.expect("Could not create the renderer");
+        for (pixel_center, symmetric) in [(0.5, true), (0.0, false)] {
+            renderer.set_kernel_settings(KernelSettings {
+                pixel_center,
+                jitter: false,
+                debug_mode: 2,
+                ..Default::default()
+            });
+            let (view, dimensions) = renderer
+                .render(&scene, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let mirrored = |x: usize| {
+                let [r, g, b, _] = pixel(&view, dimensions, x, 0);
+                let [mirror_r, mirror_g, mirror_b, _] = pixel(&view, dimensions, 3 - x, 0);
+                (r - mirror_r).abs() < 2e-3 && (g - (1.0 - mirror_g)).abs() < 2e-3 && (b - mirror_b).abs() < 2e-3
+            };
+            assert_eq!(
+                mirrored(0) && mirrored(1),
+                symmetric,
+                "Pixel center {pixel_center} should be mirrored: {symmetric}"
+            );
+        }
+    });
+}
+
+#[test]
+fn both_sphere_intersections_find_a_large_floor() {
+    // The camera looks down at 45 degrees from 2 units above the radius 100 floor sphere
+    let pitch = -std::f32::consts::FRAC_PI_4;
+    let scene = Scene {
+        camera: Camera::new(Vec3::new(0.0, 0.0, 2.0), 0.0, pitch),
+        spheres: vec![Sphere::new(Vec3::new(0.0, 0.0, -100.0), 100.0, Material::default())],
+        ..Default::default()
+    };
+    let half_b = -102.0 * (pitch as f64).abs().sin();
+    let c = 102.0f64 * 102.0 - 100.0 * 100.0;
+    let t = -half_b - (half_b * half_b - c).sqrt();
+    // As the depth debug view shows it
+    let expected = 1.0 / (1.0 + 0.1 * t);
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(1, 1).await.expect("Could not create the renderer");
+        for stable_intersection in [true, false] {
+            renderer.set_kernel_settings(KernelSettings {
+                jitter: false,
+                stable_intersection,
+                debug_mode: 3,
+                ..Default::default()
+            });
+            let (view, dimensions) = renderer
+                .render(&scene, 0.0)
+                .await
+                .expect("Could not read back the frame");
+            let depth = pixel(&view, dimensions, 0, 0)[0] as f64;
+            assert!(
+                (depth - expected).abs() < 2e-3,
+                "Stable intersection {stable_intersection}: depth {depth} != {expected}"
+            );
+        }
+    });
+}
+
+#[test]
+fn tiled_frames_match_whole_frames() {
+    let scene = sphere_in_front_of_camera();
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(40, 24).await.expect("Could not create the renderer");
+        let (view, _) = renderer
+            .render(&scene, 1.0)
+            .await
+            .expect("Could not read back the frame");
+        let whole = view.to_vec();
+        drop(view);
+
+        renderer.set_tile_size(Some(16));
+        let mut progress = Vec::new();
+        let index = renderer.submit_with_progress(&scene, 1.0, |done, total| progress.push((done, total)));
+        let (view, _) = renderer
+            .read_output(index)
+            .await
+            .expect("Could not read back the frame");
+        // 3 columns and 2 rows, the last tile is waited on by `read_output`
+        assert_eq!(progress, [(1, 6), (2, 6), (3, 6), (4, 6), (5, 6)]);
+        assert!(*view == *whole, "The tiled frame differs from the whole frame");
+    });
+}
+
+#[test]
+fn full_metals_render_like_mirrors() {
+    let mirror_scene = |mat| Scene {
+        camera: Camera::new(Vec3::default(), 0.0, 0.0),
+        spheres: vec![Sphere::new(Vec3::new(5.0, 0.0, 0.0), 1.5, mat)],
+        ..Default::default()
+    };
+    let albedo = Vec3::new(0.9, 0.6, 0.3);
+    let mirror = mirror_scene(Material {
+        albedo,
+        is_mirror: 1,
+        ..Default::default()
+    });
+    let metal = mirror_scene(Material {
+        albedo,
+        metallic: 1.0,
+        ..Default::default()
+    });
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(32, 32).await.expect("Could not create the renderer");
+        let (view, _) = renderer
+            .render(&mirror, 1.0)
+            .await
+            .expect("Could not read back the frame");
+        let mirrored = view.to_vec();
+        drop(view);
+        let (view, _) = renderer
+            .render(&metal, 1.0)
+            .await
+            .expect("Could not read back the frame");
+        assert!(*view == *mirrored, "A metallic 1 sphere differs from a mirror");
+    });
+}
+
+#[test]
+fn dielectrics_reflect_four_percent_head_on() {
+    // Hit head on, a black dielectric only shows the 4% of the sky its surface reflects, straight
+    // back towards the horizon
+    let mut scene = sphere_in_front_of_camera();
+    scene.spheres[0].mat.metallic = 0.0;
+    scene.settings = Some(RenderSettings {
+        samples_per_pixel: 256,
+        max_bounces: 2,
+    });
+    let horizon = (DEFAULT_SKY_BOTTOM_COLOR + DEFAULT_SKY_TOP_COLOR) * 0.5;
+
+    pollster::block_on(async {
+        let mut renderer = Renderer::new(1, 1).await.expect("Could not create the renderer");
+        renderer.set_kernel_settings(KernelSettings {
+            jitter: false,
+            ..Default::default()
+        });
+        renderer.set_scene(&scene);
+        let (view, dimensions) = renderer
+            .render_accumulated(16, 0.0)
+            .await
+            .expect("Could not read back the frame");
+        let center = pixel(&view, dimensions, 0, 0);
+        let reflectance = [center[0] / horizon.x, center[1] / horizon.y, center[2] / horizon.z];
+        assert!(
+            reflectance.iter().all(|reflectance| (reflectance - 0.04).abs() < 0.012),
+            "The sphere reflects {reflectance:?} of the sky rather than 4%"
+        );
+    });
+}